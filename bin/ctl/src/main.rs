@@ -5,7 +5,10 @@
 use anyhow::{anyhow, Result};
 use protocols::empty;
 use protocols::{mem_agent, mem_agent_ttrpc};
-use share::option::{CompactSetOption, MemcgSetOption};
+use share::option::{
+    CompactNowOption, CompactSetOption, MemcgReclaimOption, MemcgSetOption, PauseOption,
+    ResumeOption,
+};
 use structopt::StructOpt;
 use ttrpc::r#async::Client;
 
@@ -30,6 +33,33 @@ enum Command {
     #[structopt(name = "compactset", about = "set compact")]
     CompactSet(CompactSetOption),
 
+    #[structopt(name = "memcgconfig", about = "get memory cgroup config")]
+    MemcgConfig,
+
+    #[structopt(name = "compactconfig", about = "get compact config")]
+    CompactConfig,
+
+    #[structopt(name = "compactstatus", about = "get compact status")]
+    CompactStatus,
+
+    #[structopt(name = "compactnow", about = "run compaction now")]
+    CompactNow(CompactNowOption),
+
+    #[structopt(name = "pause", about = "pause memcg, compact or all")]
+    Pause(PauseOption),
+
+    #[structopt(name = "resume", about = "resume memcg, compact or all")]
+    Resume(ResumeOption),
+
+    #[structopt(name = "workhistory", about = "get the reports of the last work cycles")]
+    WorkHistory,
+
+    #[structopt(name = "memcgreclaim", about = "run reclaim for a memory cgroup now")]
+    MemcgReclaim(MemcgReclaimOption),
+
+    #[structopt(name = "watch", about = "watch the events of reclaim and compaction")]
+    Watch,
+
     #[structopt(name = "loglevelset", about = "set log level (trace, debug, info, warn, error, critical)")]
     LogLevelSet(LogLevelOption),
 }
@@ -54,6 +84,7 @@ async fn main() -> Result<()> {
                 .memcg_status(ttrpc::context::with_timeout(0), &empty::Empty::new())
                 .await
                 .map_err(|e| anyhow!("client.memcg_status fail: {}", e))?;
+            println!("pause {:?}", mss.pause);
             for mcg in mss.mem_cgroups {
                 println!("{:?}", mcg);
                 for (numa_id, n) in mcg.numa {
@@ -80,6 +111,132 @@ async fn main() -> Result<()> {
                 .map_err(|e| anyhow!("client.memcg_status fail: {}", e))?;
         }
 
+        Command::MemcgConfig => {
+            let mc = client
+                .memcg_config_get(ttrpc::context::with_timeout(0), &empty::Empty::new())
+                .await
+                .map_err(|e| anyhow!("client.memcg_config_get fail: {}", e))?;
+            println!("default {:?}", mc.default);
+            println!(
+                "eviction_workers {} eviction_workers_cpu_percent {}",
+                mc.eviction_workers, mc.eviction_workers_cpu_percent
+            );
+            for item in mc.cgroups {
+                println!("{:?}", item);
+            }
+        }
+
+        Command::CompactConfig => {
+            let cc = client
+                .compact_config_get(ttrpc::context::with_timeout(0), &empty::Empty::new())
+                .await
+                .map_err(|e| anyhow!("client.compact_config_get fail: {}", e))?;
+            println!("{:?}", cc);
+        }
+
+        Command::CompactStatus => {
+            let cs = client
+                .compact_status(ttrpc::context::with_timeout(0), &empty::Empty::new())
+                .await
+                .map_err(|e| anyhow!("client.compact_status fail: {}", e))?;
+            println!("{:?}", cs);
+            if let Some(r) = cs.last_run.as_ref() {
+                if let Some(t) = r.start_time.clone().into_option() {
+                    println!("last start {:?}", share::misc::timestamp_to_datetime(t)?);
+                }
+                if let Some(t) = r.end_time.clone().into_option() {
+                    println!("last end {:?}", share::misc::timestamp_to_datetime(t)?);
+                }
+            }
+            if let Some(t) = cs.next_run_time.into_option() {
+                println!("next run {:?}", share::misc::timestamp_to_datetime(t)?);
+            }
+        }
+
+        Command::CompactNow(c) => {
+            let r = client
+                .compact_now(
+                    ttrpc::context::with_timeout(0),
+                    &c.to_rpc_compact_now_request(),
+                )
+                .await
+                .map_err(|e| anyhow!("client.compact_now fail: {}", e))?;
+            println!("{:?}", r);
+        }
+
+        Command::Pause(c) => {
+            client
+                .pause(ttrpc::context::with_timeout(0), &c.to_rpc_pause_request())
+                .await
+                .map_err(|e| anyhow!("client.pause fail: {}", e))?;
+        }
+
+        Command::Resume(c) => {
+            client
+                .resume(ttrpc::context::with_timeout(0), &c.to_rpc_resume_request())
+                .await
+                .map_err(|e| anyhow!("client.resume fail: {}", e))?;
+        }
+
+        Command::WorkHistory => {
+            let wh = client
+                .work_history(ttrpc::context::with_timeout(0), &empty::Empty::new())
+                .await
+                .map_err(|e| anyhow!("client.work_history fail: {}", e))?;
+            for r in wh.reports {
+                if let (Some(start), Some(end)) =
+                    (r.start_time.clone().into_option(), r.end_time.clone().into_option())
+                {
+                    println!(
+                        "{} - {}",
+                        share::misc::timestamp_to_datetime(start)?.to_rfc3339(),
+                        share::misc::timestamp_to_datetime(end)?.to_rfc3339()
+                    );
+                }
+                println!("memcg period secs {:?}", r.memcg_period_secs);
+                for w in &r.memcg {
+                    println!("{:?}", w);
+                }
+                println!("compact {:?}", r.compact_outcome);
+                if !r.error.is_empty() {
+                    println!("error {}", r.error);
+                }
+            }
+        }
+
+        Command::MemcgReclaim(c) => {
+            let reply = client
+                .memcg_reclaim(
+                    ttrpc::context::with_timeout(0),
+                    &c.to_rpc_memcg_reclaim_request(),
+                )
+                .await
+                .map_err(|e| anyhow!("client.memcg_reclaim fail: {}", e))?;
+            for w in reply.numa {
+                println!("{:?}", w);
+            }
+        }
+
+        Command::Watch => {
+            let mut stream = client
+                .watch_events(ttrpc::context::with_timeout(0), &empty::Empty::new())
+                .await
+                .map_err(|e| anyhow!("client.watch_events fail: {}", e))?;
+            while let Some(ev) = stream
+                .recv()
+                .await
+                .map_err(|e| anyhow!("stream.recv fail: {}", e))?
+            {
+                let time = match ev.time.clone().into_option() {
+                    Some(t) => share::misc::timestamp_to_datetime(t)?.to_rfc3339(),
+                    None => "-".to_string(),
+                };
+                if let Some(e) = ev.event {
+                    println!("{} {:?}", time, e);
+                }
+            }
+        }
+
         Command::LogLevelSet(c) => {
             let config = mem_agent::LogLevel {
                 level: c.level.clone(),
@@ -3,9 +3,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{anyhow, Result};
-use share::option::{CompactSetOption, MemcgSetupOption};
+use share::config::ConfigFile;
+use share::option::{CompactSetOption, MemcgSetupOption, MetricsOption};
 use slog::Level;
 use slog_scope::{error, info};
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -17,10 +19,19 @@ struct Opt {
     log_file: Option<String>,
     #[structopt(long, default_value = "trace", parse(try_from_str = share::logger::parse_slog_level))]
     log_level: Level,
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    #[structopt(long, default_value = "/", parse(from_os_str))]
+    host_root: PathBuf,
+    // The count of the work reports that are kept for WorkHistory.
+    #[structopt(long, default_value = "64")]
+    work_history_size: usize,
     #[structopt(flatten)]
     memcg: MemcgSetupOption,
     #[structopt(flatten)]
     compact: CompactSetOption,
+    #[structopt(flatten)]
+    metrics: MetricsOption,
 }
 
 fn main() -> Result<()> {
@@ -30,19 +41,43 @@ fn main() -> Result<()> {
     let _logger_guard = share::logger::setup_logging(&opt.log_file, opt.log_level)
         .map_err(|e| anyhow!("setup_logging fail: {}", e))?;
 
-    let memcg_config = opt.memcg.to_mem_agent_memcg_config();
-    let compact_config = opt.compact.to_mem_agent_compact_config();
+    let (memcg_config, compact_config, config_file) = if let Some(path) = &opt.config {
+        let cf = ConfigFile::load(path, opt.memcg, opt.compact)
+            .map_err(|e| anyhow!("ConfigFile::load {:?} fail: {}", path, e))?;
+        (cf.memcg_config(), cf.compact_config(), Some(cf))
+    } else {
+        (
+            opt.memcg.to_mem_agent_memcg_config(),
+            opt.compact.to_mem_agent_compact_config(),
+            None,
+        )
+    };
+
+    // The agent and the rpc server share this runtime.
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| anyhow!("Builder::new_multi_thread fail: {}", e))?;
 
-    let (ma, _rt) = mem_agent_lib::agent::MemAgent::new(memcg_config, compact_config)
-        .map_err(|e| anyhow!("MemAgent::new fail: {}", e))?;
+    let handle = rt.handle().clone();
+    let ma = rt
+        .block_on(mem_agent_lib::agent::MemAgent::spawn_on_with_host_root(
+            memcg_config,
+            compact_config,
+            &opt.host_root,
+            opt.work_history_size,
+            &handle,
+        ))
+        .map_err(|e| anyhow!("MemAgent::spawn_on fail: {}", e))?;
 
     info!("MemAgent started");
 
-    share::rpc::rpc_loop(ma, opt.addr).map_err(|e| {
-        let estr = format!("rpc::rpc_loop fail: {}", e);
-        error!("{}", estr);
-        anyhow!("{}", estr)
-    })?;
+    rt.block_on(share::rpc::rpc_loop(ma, opt.addr, config_file, opt.metrics))
+        .map_err(|e| {
+            let estr = format!("rpc::rpc_loop fail: {}", e);
+            error!("{}", estr);
+            anyhow!("{}", estr)
+        })?;
 
     Ok(())
 }
@@ -3,24 +3,107 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::compact;
+use crate::event::{Event, Events};
+use crate::hostfs::HostFs;
 use crate::memcg::{self, MemCgroup};
+use crate::pause::{Pause, PauseStatus, PauseTarget};
 use crate::{debug, error, info};
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
-use std::thread;
-use tokio::runtime::{Builder, Runtime};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use tokio::runtime::{Builder, Handle, Runtime};
 use tokio::select;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 use tokio::time::{sleep, Duration, Instant};
 
 const AGENT_WORK_ERROR_SLEEP_SECS: u64 = 5 * 60;
 
+pub const WORK_HISTORY_SIZE_DEFAULT: usize = 64;
+
+// The report of a work cycle of the agent.
+#[derive(Debug, Clone)]
+pub struct WorkReport {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    // the period_secs of the memcg configs that ran
+    pub memcg_period_secs: Vec<u64>,
+    pub memcg: Vec<memcg::NumaWork>,
+    // None if compaction didn't run
+    pub compact: Option<compact::Outcome>,
+    pub error: Option<String>,
+}
+
+impl WorkReport {
+    fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            start_time: now,
+            end_time: now,
+            memcg_period_secs: Vec::new(),
+            memcg: Vec::new(),
+            compact: None,
+            error: None,
+        }
+    }
+}
+
+// The last size work reports.
+#[derive(Debug)]
+struct WorkHistory {
+    size: usize,
+    reports: VecDeque<WorkReport>,
+}
+
+impl WorkHistory {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            reports: VecDeque::with_capacity(size),
+        }
+    }
+
+    fn push(&mut self, report: WorkReport) {
+        if self.size == 0 {
+            return;
+        }
+        if self.reports.len() >= self.size {
+            self.reports.pop_front();
+        }
+        self.reports.push_back(report);
+    }
+
+    fn get(&self) -> Vec<WorkReport> {
+        self.reports.iter().cloned().collect()
+    }
+}
+
+// The status of the agent when it stopped.
+#[derive(Debug)]
+pub struct FinalStatus {
+    pub memcg: HashMap<String, MemCgroup>,
+    pub compact: compact::Status,
+    pub history: Vec<WorkReport>,
+}
+
 #[derive(Debug)]
 enum AgentCmd {
     MemcgStatus,
     MemcgSet(memcg::OptionConfig),
     CompactSet(compact::OptionConfig),
+    MemcgConfigGet,
+    CompactConfigGet,
+    CompactStatus,
+    WorkHistory,
+    MemcgReclaim(memcg::ReclaimRequest),
+    CompactNow(compact::CompactRequest),
+    // None means pause until resumed.
+    Pause(PauseTarget, Option<DateTime<Utc>>),
+    Resume(PauseTarget),
+    Shutdown,
 }
 
 #[allow(dead_code)]
@@ -29,6 +112,13 @@ enum AgentReturn {
     Ok,
     Err(anyhow::Error),
     MemcgStatus(HashMap<String, memcg::MemCgroup>),
+    MemcgConfig(memcg::Config),
+    CompactConfig(compact::Config),
+    CompactStatus(compact::Status),
+    WorkHistory(Vec<WorkReport>),
+    MemcgReclaim(Vec<memcg::NumaWork>),
+    CompactNow(compact::LastRun),
+    Shutdown(FinalStatus),
 }
 
 async fn handle_agent_cmd(
@@ -36,6 +126,8 @@ async fn handle_agent_cmd(
     ret_tx: oneshot::Sender<AgentReturn>,
     memcg: &mut memcg::MemCG,
     comp: &mut compact::Compact,
+    history: &WorkHistory,
+    workers: &mut Vec<JoinHandle<()>>,
 ) -> Result<bool> {
     #[allow(unused_assignments)]
     let mut ret_msg = AgentReturn::Ok;
@@ -45,7 +137,7 @@ async fn handle_agent_cmd(
             ret_msg = AgentReturn::MemcgStatus(memcg.get_status().await);
             false
         }
-        AgentCmd::MemcgSet(opt) => match memcg.set_config(opt).await {
+        AgentCmd::MemcgSet(opt) => match set_memcg_config(memcg, opt).await {
             Ok(reset) => {
                 ret_msg = AgentReturn::Ok;
                 reset
@@ -56,6 +148,76 @@ async fn handle_agent_cmd(
             }
         },
         AgentCmd::CompactSet(opt) => comp.set_config(opt).await,
+        AgentCmd::MemcgConfigGet => {
+            ret_msg = AgentReturn::MemcgConfig(memcg.get_config().await);
+            false
+        }
+        AgentCmd::CompactConfigGet => {
+            ret_msg = AgentReturn::CompactConfig(comp.get_config().await);
+            false
+        }
+        AgentCmd::CompactStatus => {
+            ret_msg = AgentReturn::CompactStatus(comp.get_status().await);
+            false
+        }
+        AgentCmd::WorkHistory => {
+            ret_msg = AgentReturn::WorkHistory(history.get());
+            false
+        }
+        AgentCmd::MemcgReclaim(req) => {
+            // The reclaim takes a while, run it in a thread and let the
+            // thread send the return.
+            // memcg.reclaim waits the running work of the work thread.
+            let mut thread_memcg = memcg.clone();
+            workers.push(thread::spawn(move || {
+                let ret_msg = match thread_memcg.reclaim(&req) {
+                    Ok(works) => AgentReturn::MemcgReclaim(works),
+                    Err(e) => AgentReturn::Err(e),
+                };
+                if let Err(e) = ret_tx.send(ret_msg) {
+                    error!("ret_tx.send failed: {:?}", e);
+                }
+            }));
+            return Ok(false);
+        }
+        AgentCmd::CompactNow(req) => {
+            // comp.compact_now waits the running work of the work thread.
+            let mut thread_comp = comp.clone();
+            workers.push(thread::spawn(move || {
+                let ret_msg = match thread_comp.compact_now(&req) {
+                    Ok(run) => AgentReturn::CompactNow(run),
+                    Err(e) => AgentReturn::Err(e),
+                };
+                if let Err(e) = ret_tx.send(ret_msg) {
+                    error!("ret_tx.send failed: {:?}", e);
+                }
+            }));
+            return Ok(false);
+        }
+        AgentCmd::Pause(target, until) => {
+            if target.memcg() {
+                memcg.pause().pause(until);
+            }
+            if target.compact() {
+                comp.pause().pause(until);
+                // Don't keep the raised value while paused, the next
+                // work_proactive applies it again after the resume.
+                if let Err(e) = comp.restore_proactiveness().await {
+                    error!("compact pause restore_proactiveness failed: {}", e);
+                }
+            }
+            true
+        }
+        AgentCmd::Resume(target) => {
+            if target.memcg() {
+                memcg.pause().resume();
+            }
+            if target.compact() {
+                comp.pause().resume();
+            }
+            true
+        }
+        AgentCmd::Shutdown => unreachable!("Shutdown is handled by mem_agent_loop"),
     };
 
     ret_tx
@@ -65,6 +227,15 @@ async fn handle_agent_cmd(
     Ok(need_reset_mas)
 }
 
+// MemCG::set_config reads the memcgs from the kernel, run it in the
+// blocking threads to not block the runtime.
+async fn set_memcg_config(memcg: &memcg::MemCG, opt: memcg::OptionConfig) -> Result<bool> {
+    let mut thread_memcg = memcg.clone();
+    tokio::task::spawn_blocking(move || thread_memcg.blocking_set_config(opt))
+        .await
+        .map_err(|e| anyhow!("spawn_blocking failed: {}", e))?
+}
+
 fn get_remaining_tokio_duration(memcg: &memcg::MemCG, comp: &compact::Compact) -> Duration {
     let memcg_d = memcg.get_remaining_tokio_duration();
     let comp_d = comp.get_remaining_tokio_duration();
@@ -103,11 +274,16 @@ async fn async_get_remaining_tokio_duration(
     }
 }
 
-fn agent_work(mut memcg: memcg::MemCG, mut comp: compact::Compact) -> Result<Duration> {
+fn agent_work(
+    mut memcg: memcg::MemCG,
+    mut comp: compact::Compact,
+    report: &mut WorkReport,
+) -> Result<Duration> {
     let memcg_work_list = memcg.get_timeout_list();
     if memcg_work_list.len() > 0 {
         info!("memcg.work start");
-        memcg
+        report.memcg_period_secs = memcg_work_list.clone();
+        report.memcg = memcg
             .work(&memcg_work_list)
             .map_err(|e| anyhow!("memcg.work failed: {}", e))?;
         info!("memcg.work stop");
@@ -115,8 +291,10 @@ fn agent_work(mut memcg: memcg::MemCG, mut comp: compact::Compact) -> Result<Dur
 
     let compact_need_reset = if comp.need_work() {
         info!("compact.work start");
-        comp.work()
-            .map_err(|e| anyhow!("comp.work failed: {}", e))?;
+        report.compact = Some(
+            comp.work()
+                .map_err(|e| anyhow!("comp.work failed: {}", e))?,
+        );
         info!("compact.work stop");
         true
     } else {
@@ -180,26 +358,34 @@ async fn mem_agent_loop(
     mut cmd_rx: mpsc::Receiver<(AgentCmd, oneshot::Sender<AgentReturn>)>,
     mut memcg: memcg::MemCG,
     mut comp: compact::Compact,
+    mut history: WorkHistory,
 ) -> Result<()> {
     let (work_ret_tx, mut work_ret_rx) = mpsc::channel(2);
     // the time that wait to next.
     let mut mas = MemAgentSleep::new();
+    // the agent work thread that is running
+    let mut work_thread: Option<JoinHandle<()>> = None;
+    // the threads of memcg reclaim and compact now
+    let mut workers: Vec<JoinHandle<()>> = Vec::new();
 
     loop {
         if mas.timeout {
             let thread_memcg = memcg.clone();
             let thread_comp = comp.clone();
             let thread_work_ret_tx = work_ret_tx.clone();
-            thread::spawn(move || {
+            work_thread = Some(thread::spawn(move || {
                 info!("agent work thread start");
-                let d = agent_work(thread_memcg, thread_comp).unwrap_or_else(|err| {
+                let mut report = WorkReport::new();
+                let d = agent_work(thread_memcg, thread_comp, &mut report).unwrap_or_else(|err| {
                     error!("agent work thread fail {}", err);
+                    report.error = Some(err.to_string());
                     Duration::from_secs(AGENT_WORK_ERROR_SLEEP_SECS)
                 });
-                if let Err(e) = thread_work_ret_tx.blocking_send(d) {
+                report.end_time = Utc::now();
+                if let Err(e) = thread_work_ret_tx.blocking_send((d, report)) {
                     error!("work_ret_tx.blocking_send failed: {}", e);
                 }
-            });
+            }));
 
             mas.timeout = false;
         } else {
@@ -211,13 +397,38 @@ async fn mem_agent_loop(
         info!("mem_agent_loop wait timeout {:?}", mas.duration);
         select! {
             Some((cmd, ret_tx)) = cmd_rx.recv() => {
-                if handle_agent_cmd(cmd, ret_tx, &mut memcg, &mut comp).await.map_err(|e| anyhow!("handle_agent_cmd failed: {}", e))? && !mas.timeout{
+                if let AgentCmd::Shutdown = cmd {
+                    let status = shutdown(
+                        &memcg,
+                        &comp,
+                        &mut history,
+                        &mut work_ret_rx,
+                        work_thread.take(),
+                        workers,
+                    )
+                    .await;
+                    ret_tx
+                        .send(AgentReturn::Shutdown(status))
+                        .map_err(|e| anyhow!("ret_tx.send failed: {:?}", e))?;
+                    return Ok(());
+                }
+
+                workers.retain(|w| !w.is_finished());
+                if handle_agent_cmd(cmd, ret_tx, &mut memcg, &mut comp, &history, &mut workers).await.map_err(|e| anyhow!("handle_agent_cmd failed: {}", e))? && !mas.timeout{
                     mas.set_sleep(async_get_remaining_tokio_duration(&memcg, &comp).await);
                 }
             }
-            d = work_ret_rx.recv() => {
+            ret = work_ret_rx.recv() => {
                 info!("agent work thread stop");
-                mas.set_sleep(d.unwrap_or(Duration::from_secs(AGENT_WORK_ERROR_SLEEP_SECS)));
+                work_thread = None;
+                let d = match ret {
+                    Some((d, report)) => {
+                        history.push(report);
+                        d
+                    }
+                    None => Duration::from_secs(AGENT_WORK_ERROR_SLEEP_SECS),
+                };
+                mas.set_sleep(d);
             }
             _ = async {
                 sleep(mas.duration).await;
@@ -228,9 +439,62 @@ async fn mem_agent_loop(
     }
 }
 
+// Stop the running threads at their next safe point and wait them.
+async fn shutdown(
+    memcg: &memcg::MemCG,
+    comp: &compact::Compact,
+    history: &mut WorkHistory,
+    work_ret_rx: &mut mpsc::Receiver<(Duration, WorkReport)>,
+    work_thread: Option<JoinHandle<()>>,
+    mut workers: Vec<JoinHandle<()>>,
+) -> FinalStatus {
+    info!("mem-agent shutdown start");
+
+    // The threads check the pause between the eviction and compaction
+    // steps.
+    memcg.pause().pause(None);
+    comp.pause().pause(None);
+
+    if let Some(t) = work_thread {
+        info!("mem-agent shutdown wait agent work thread");
+        if let Some((_, report)) = work_ret_rx.recv().await {
+            history.push(report);
+        }
+        workers.push(t);
+    }
+
+    let join = tokio::task::spawn_blocking(move || {
+        for w in workers {
+            if w.join().is_err() {
+                error!("mem-agent shutdown join thread failed");
+            }
+        }
+    });
+    if let Err(e) = join.await {
+        error!("mem-agent shutdown spawn_blocking failed: {}", e);
+    }
+
+    if let Err(e) = comp.restore_proactiveness().await {
+        error!("mem-agent shutdown restore_proactiveness failed: {}", e);
+    }
+    // Don't leave it to the drop of memcg, the process might exit first.
+    memcg.restore_memory_highs().await;
+
+    info!("mem-agent shutdown stop");
+
+    FinalStatus {
+        memcg: memcg.get_status().await,
+        compact: comp.get_status().await,
+        history: history.get(),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MemAgent {
     cmd_tx: mpsc::Sender<(AgentCmd, oneshot::Sender<AgentReturn>)>,
+    events: Events,
+    memcg_pause: Pause,
+    compact_pause: Pause,
 }
 
 impl MemAgent {
@@ -238,36 +502,178 @@ impl MemAgent {
         memcg_config: memcg::Config,
         compact_config: compact::Config,
     ) -> Result<(Self, Runtime)> {
-        let is_cg_v2 = crate::cgroup::is_cgroup_v2()?;
+        Self::new_with_host_root(
+            memcg_config,
+            compact_config,
+            Path::new("/"),
+            WORK_HISTORY_SIZE_DEFAULT,
+        )
+    }
+
+    // host_root is the directory that has /sys and /proc of the host.
+    // history_size is the count of the work reports that are kept.
+    pub fn new_with_host_root(
+        memcg_config: memcg::Config,
+        compact_config: compact::Config,
+        host_root: &Path,
+        history_size: usize,
+    ) -> Result<(Self, Runtime)> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|e| anyhow!("Builder::new_multi_threa failed: {}", e))?;
+
+        let (mg, comp, events) = Self::create(memcg_config, compact_config, host_root)?;
+        let agent = Self::start(mg, comp, events, history_size, runtime.handle());
+
+        Ok((agent, runtime))
+    }
+
+    // Run the agent on the runtime of handle instead of a private one.
+    // The agent stops when the runtime is shut down.
+    pub async fn spawn_on(
+        memcg_config: memcg::Config,
+        compact_config: compact::Config,
+        handle: &Handle,
+    ) -> Result<Self> {
+        Self::spawn_on_with_host_root(
+            memcg_config,
+            compact_config,
+            Path::new("/"),
+            WORK_HISTORY_SIZE_DEFAULT,
+            handle,
+        )
+        .await
+    }
+
+    // The memcgs are read from the kernel in a blocking thread of the
+    // current runtime, so it doesn't block the caller.
+    pub async fn spawn_on_with_host_root(
+        memcg_config: memcg::Config,
+        compact_config: compact::Config,
+        host_root: &Path,
+        history_size: usize,
+        handle: &Handle,
+    ) -> Result<Self> {
+        let host_root = host_root.to_path_buf();
+        let (mg, comp, events) = tokio::task::spawn_blocking(move || {
+            Self::create(memcg_config, compact_config, &host_root)
+        })
+        .await
+        .map_err(|e| anyhow!("spawn_blocking MemAgent::create failed: {}", e))??;
+
+        Ok(Self::start(mg, comp, events, history_size, handle))
+    }
+
+    fn create(
+        memcg_config: memcg::Config,
+        compact_config: compact::Config,
+        host_root: &Path,
+    ) -> Result<(memcg::MemCG, compact::Compact, Events)> {
+        let host = HostFs::new(host_root);
+        if !host.is_default_root() {
+            info!("host root is {:?}", host_root);
+        }
+
+        let is_cg_v2 = crate::cgroup::is_cgroup_v2(&host)?;
         if is_cg_v2 {
             info!("current host use cgroup v2");
         } else {
             info!("current host use cgroup v1");
         }
 
-        let mg = memcg::MemCG::new(is_cg_v2, memcg_config)
+        let events = Events::default();
+
+        let mut mg = memcg::MemCG::new(&host, is_cg_v2, memcg_config)
             .map_err(|e| anyhow!("memcg::MemCG::new fail: {}", e))?;
+        mg.set_events(events.clone());
 
-        let comp = compact::Compact::new(is_cg_v2, compact_config)
+        let mut comp = compact::Compact::new(&host, is_cg_v2, compact_config)
             .map_err(|e| anyhow!("compact::Compact::new fail: {}", e))?;
+        comp.set_events(events.clone());
 
-        let (cmd_tx, cmd_rx) = mpsc::channel(10);
+        Ok((mg, comp, events))
+    }
 
-        let runtime = Builder::new_multi_thread()
-            .worker_threads(1)
-            .enable_all()
-            .build()
-            .map_err(|e| anyhow!("Builder::new_multi_threa failed: {}", e))?;
+    // Spawn mem_agent_loop on handle.
+    fn start(
+        mg: memcg::MemCG,
+        comp: compact::Compact,
+        events: Events,
+        history_size: usize,
+        handle: &Handle,
+    ) -> Self {
+        let memcg_pause = mg.pause().clone();
+        let compact_pause = comp.pause().clone();
 
-        runtime.spawn(async move {
+        let (cmd_tx, cmd_rx) = mpsc::channel(10);
+
+        handle.spawn(async move {
             info!("mem-agent start");
-            match mem_agent_loop(cmd_rx, mg, comp).await {
+            match mem_agent_loop(cmd_rx, mg, comp, WorkHistory::new(history_size)).await {
                 Err(e) => error!("mem-agent error {}", e),
                 Ok(()) => info!("mem-agent stop"),
             }
         });
 
-        Ok((Self { cmd_tx }, runtime))
+        Self {
+            cmd_tx,
+            events,
+            memcg_pause,
+            compact_pause,
+        }
+    }
+
+    // Stop the agent and return its final status.
+    // The running work stops at its next safe point, such as between two
+    // eviction steps, and its thread is joined.
+    // The agent cannot be used after it.
+    pub async fn shutdown_async(&self) -> Result<FinalStatus> {
+        let ret = self
+            .send_cmd_async(AgentCmd::Shutdown)
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!("mem_agent thread shutdown_async failed: {}", e)),
+            AgentReturn::Shutdown(s) => Ok(s),
+            _ => Err(anyhow!(
+                "mem_agent thread shutdown_async return wrong value"
+            )),
+        }
+    }
+
+    // Same as shutdown_async but for the caller outside of the async
+    // context.
+    pub fn shutdown(&self) -> Result<FinalStatus> {
+        let (ret_tx, ret_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .blocking_send((AgentCmd::Shutdown, ret_tx))
+            .map_err(|e| anyhow!("cmd_tx.blocking_send cmd failed: {}", e))?;
+
+        match ret_rx
+            .blocking_recv()
+            .map_err(|e| anyhow!("ret_rx.blocking_recv failed: {}", e))?
+        {
+            AgentReturn::Err(e) => Err(anyhow!("mem_agent thread shutdown failed: {}", e)),
+            AgentReturn::Shutdown(s) => Ok(s),
+            _ => Err(anyhow!("mem_agent thread shutdown return wrong value")),
+        }
+    }
+
+    // Subscribe the events of the reclaim and compaction activity.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    pub fn memcg_pause_status(&self) -> PauseStatus {
+        self.memcg_pause.status()
+    }
+
+    pub fn compact_pause_status(&self) -> PauseStatus {
+        self.compact_pause.status()
     }
 
     async fn send_cmd_async(&self, cmd: AgentCmd) -> Result<AgentReturn> {
@@ -331,21 +737,171 @@ impl MemAgent {
             AgentReturn::Err(e) => {
                 return Err(anyhow!("mem_agent thread memcg_status_async failed: {}", e))
             }
-            AgentReturn::Ok => {
+            AgentReturn::MemcgStatus(s) => s,
+            _ => {
                 return Err(anyhow!(
                     "mem_agent thread memcg_status_async return wrong value"
                 ))
             }
-            AgentReturn::MemcgStatus(s) => s,
         };
 
         Ok(status)
     }
+
+    pub async fn memcg_get_config_async(&self) -> Result<memcg::Config> {
+        let ret = self
+            .send_cmd_async(AgentCmd::MemcgConfigGet)
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!(
+                "mem_agent thread memcg_get_config_async failed: {}",
+                e
+            )),
+            AgentReturn::MemcgConfig(c) => Ok(c),
+            _ => Err(anyhow!(
+                "mem_agent thread memcg_get_config_async return wrong value"
+            )),
+        }
+    }
+
+    pub async fn compact_get_config_async(&self) -> Result<compact::Config> {
+        let ret = self
+            .send_cmd_async(AgentCmd::CompactConfigGet)
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!(
+                "mem_agent thread compact_get_config_async failed: {}",
+                e
+            )),
+            AgentReturn::CompactConfig(c) => Ok(c),
+            _ => Err(anyhow!(
+                "mem_agent thread compact_get_config_async return wrong value"
+            )),
+        }
+    }
+
+    pub async fn work_history_async(&self) -> Result<Vec<WorkReport>> {
+        let ret = self
+            .send_cmd_async(AgentCmd::WorkHistory)
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => {
+                Err(anyhow!("mem_agent thread work_history_async failed: {}", e))
+            }
+            AgentReturn::WorkHistory(h) => Ok(h),
+            _ => Err(anyhow!(
+                "mem_agent thread work_history_async return wrong value"
+            )),
+        }
+    }
+
+    // Run aging and eviction for a memcg right now.
+    pub async fn memcg_reclaim_async(
+        &self,
+        req: memcg::ReclaimRequest,
+    ) -> Result<Vec<memcg::NumaWork>> {
+        let ret = self
+            .send_cmd_async(AgentCmd::MemcgReclaim(req))
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!(
+                "mem_agent thread memcg_reclaim_async failed: {}",
+                e
+            )),
+            AgentReturn::MemcgReclaim(w) => Ok(w),
+            _ => Err(anyhow!(
+                "mem_agent thread memcg_reclaim_async return wrong value"
+            )),
+        }
+    }
+
+    // Run a compaction right now.
+    pub async fn compact_now_async(
+        &self,
+        req: compact::CompactRequest,
+    ) -> Result<compact::LastRun> {
+        let ret = self
+            .send_cmd_async(AgentCmd::CompactNow(req))
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!("mem_agent thread compact_now_async failed: {}", e)),
+            AgentReturn::CompactNow(r) => Ok(r),
+            _ => Err(anyhow!(
+                "mem_agent thread compact_now_async return wrong value"
+            )),
+        }
+    }
+
+    // Stop the work of target until resumed or duration passed.
+    // The running work stops at the next safe point.
+    pub async fn pause_async(&self, target: PauseTarget, duration: Option<Duration>) -> Result<()> {
+        let until = match duration {
+            Some(d) => Some(
+                Utc::now()
+                    + chrono::Duration::from_std(d)
+                        .map_err(|e| anyhow!("duration {:?} is invalid: {}", d, e))?,
+            ),
+            None => None,
+        };
+
+        let ret = self
+            .send_cmd_async(AgentCmd::Pause(target, until))
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!("mem_agent thread pause_async failed: {}", e)),
+            AgentReturn::Ok => Ok(()),
+            _ => Err(anyhow!("mem_agent thread pause_async return wrong value")),
+        }
+    }
+
+    pub async fn resume_async(&self, target: PauseTarget) -> Result<()> {
+        let ret = self
+            .send_cmd_async(AgentCmd::Resume(target))
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!("mem_agent thread resume_async failed: {}", e)),
+            AgentReturn::Ok => Ok(()),
+            _ => Err(anyhow!("mem_agent thread resume_async return wrong value")),
+        }
+    }
+
+    pub async fn compact_status_async(&self) -> Result<compact::Status> {
+        let ret = self
+            .send_cmd_async(AgentCmd::CompactStatus)
+            .await
+            .map_err(|e| anyhow!("send_cmd failed: {}", e))?;
+
+        match ret {
+            AgentReturn::Err(e) => Err(anyhow!(
+                "mem_agent thread compact_status_async failed: {}",
+                e
+            )),
+            AgentReturn::CompactStatus(s) => Ok(s),
+            _ => Err(anyhow!(
+                "mem_agent thread compact_status_async return wrong value"
+            )),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::misc::TestDir;
 
     #[test]
     fn test_agent() {
@@ -395,4 +951,312 @@ mod tests {
             .block_on(ma.memcg_status_async())
             .unwrap();
     }
+
+    #[test]
+    fn test_agent_config_get() {
+        let root = setup_fake_host("test_agent_config_get");
+
+        let mut memcg_config = memcg::Config::default();
+        memcg_config.default.disabled = true;
+        let compact_config = compact::Config {
+            disabled: true,
+            ..Default::default()
+        };
+
+        let (ma, _rt) = MemAgent::new_with_host_root(
+            memcg_config,
+            compact_config,
+            &root,
+            WORK_HISTORY_SIZE_DEFAULT,
+        )
+        .unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let mut memcg_config = memcg::OptionConfig::default();
+        memcg_config.default.period_secs = Some(120);
+        rt.block_on(ma.memcg_set_config_async(memcg_config))
+            .unwrap();
+        let memcg_config = rt.block_on(ma.memcg_get_config_async()).unwrap();
+        assert!(memcg_config.default.disabled);
+        assert_eq!(memcg_config.default.period_secs, 120);
+
+        let compact_config = rt.block_on(ma.compact_get_config_async()).unwrap();
+        assert!(compact_config.disabled);
+    }
+
+    #[test]
+    fn test_agent_compact_status() {
+        let root = setup_fake_host("test_agent_compact_status");
+
+        let mut memcg_config = memcg::Config::default();
+        memcg_config.default.disabled = true;
+        let compact_config = compact::Config {
+            disabled: true,
+            ..Default::default()
+        };
+
+        let (ma, _rt) = MemAgent::new_with_host_root(
+            memcg_config,
+            compact_config,
+            &root,
+            WORK_HISTORY_SIZE_DEFAULT,
+        )
+        .unwrap();
+
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(ma.compact_status_async())
+            .unwrap();
+        assert!(status.last_run.is_none());
+        assert!(status.next_run_time.is_none());
+    }
+
+    #[test]
+    fn test_work_history() {
+        let report = |secs| {
+            let mut r = WorkReport::new();
+            r.memcg_period_secs = vec![secs];
+            r
+        };
+
+        let mut h = WorkHistory::new(2);
+        h.push(report(1));
+        h.push(report(2));
+        h.push(report(3));
+        let secs: Vec<u64> = h.get().iter().map(|r| r.memcg_period_secs[0]).collect();
+        assert_eq!(secs, vec![2, 3]);
+
+        let mut h = WorkHistory::new(0);
+        h.push(report(1));
+        assert!(h.get().is_empty());
+    }
+
+    // Wait until cond is true, fail the test instead of hanging it.
+    fn wait_until(what: &str, mut cond: impl FnMut() -> bool) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !cond() {
+            if std::time::Instant::now() > deadline {
+                panic!("{} didn't happen in 10 seconds", what);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn setup_fake_host(name: &str) -> TestDir {
+        let root = TestDir::new(name);
+
+        let cgroup = root.join("sys/fs/cgroup");
+        std::fs::create_dir_all(cgroup.join("fake.slice")).unwrap();
+        std::fs::write(cgroup.join("cgroup.controllers"), "memory io\n").unwrap();
+        for dir in [cgroup.clone(), cgroup.join("fake.slice")] {
+            for psi in ["memory.pressure", "io.pressure"] {
+                std::fs::write(
+                    dir.join(psi),
+                    "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n\
+                     full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n",
+                )
+                .unwrap();
+            }
+        }
+
+        std::fs::create_dir_all(root.join("sys/kernel/mm/lru_gen")).unwrap();
+        std::fs::write(root.join("sys/kernel/mm/lru_gen/enabled"), "0x0007\n").unwrap();
+        std::fs::create_dir_all(root.join("sys/kernel/debug")).unwrap();
+        std::fs::write(
+            root.join("sys/kernel/debug/lru_gen"),
+            "memcg     1 /\n\
+             node     0\n\
+             \x20     0  1000  10  20\n\
+             \x20     1  1000  10  20\n\
+             \x20     2  1000  10  20\n\
+             \x20     3  1000  10  20\n\
+             memcg     2 /fake.slice\n\
+             node     0\n\
+             \x20     4  1000  10  20\n\
+             \x20     5  1000  10  20\n\
+             \x20     6  1000  10  20\n\
+             \x20     7  1000  10  20\n",
+        )
+        .unwrap();
+
+        std::fs::create_dir_all(root.join("proc/sys/vm")).unwrap();
+        std::fs::write(
+            root.join("proc/meminfo"),
+            "MemTotal:        8000000 kB\nMemFree:         4000000 kB\nSwapFree:              0 kB\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("proc/pagetypeinfo"),
+            "Node    0, zone   Normal, type      Movable      1      1      1\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("proc/sys/vm/compact_memory"), "").unwrap();
+
+        root
+    }
+
+    #[test]
+    fn test_agent_host_root() {
+        let root = setup_fake_host("test_host_root");
+
+        let mut memcg_config = memcg::Config::default();
+        memcg_config.default.disabled = true;
+        let compact_config = compact::Config {
+            disabled: true,
+            ..Default::default()
+        };
+
+        let (ma, _rt) = MemAgent::new_with_host_root(
+            memcg_config,
+            compact_config,
+            &root,
+            WORK_HISTORY_SIZE_DEFAULT,
+        )
+        .unwrap();
+
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(ma.memcg_status_async())
+            .unwrap();
+        let mcg = status.get("/fake.slice").unwrap();
+        assert_eq!(mcg.id, 2);
+        assert!(mcg.numa.contains_key(&0));
+    }
+    #[test]
+    fn test_agent_shutdown() {
+        let root = setup_fake_host("test_agent_shutdown");
+        let high = root.join("sys/fs/cgroup/fake.slice/memory.high");
+        std::fs::write(&high, "max\n").unwrap();
+
+        let mut memcg_config = memcg::Config::default();
+        memcg_config.default.memory_high = true;
+        let compact_config = compact::Config {
+            disabled: true,
+            ..Default::default()
+        };
+
+        let (ma, _rt) = MemAgent::new_with_host_root(
+            memcg_config,
+            compact_config,
+            &root,
+            WORK_HISTORY_SIZE_DEFAULT,
+        )
+        .unwrap();
+
+        // The first work cycle sets memory.high.
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        wait_until("the first work cycle", || {
+            !rt.block_on(ma.work_history_async()).unwrap().is_empty()
+        });
+        assert_ne!(std::fs::read_to_string(&high).unwrap(), "max\n");
+
+        let status = ma.shutdown().unwrap();
+        // memory.high is restored before shutdown returns.
+        assert_eq!(std::fs::read_to_string(&high).unwrap(), "max");
+        assert!(status.memcg.contains_key("/fake.slice"));
+        assert_eq!(status.history.len(), 1);
+        assert!(status.compact.pause.paused);
+
+        assert!(tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(ma.memcg_status_async())
+            .is_err());
+        assert!(ma.shutdown().is_err());
+    }
+
+    #[test]
+    fn test_agent_compact_pause_proactive() {
+        let root = setup_fake_host("test_agent_compact_pause_proactive");
+        let proactiveness = root.join("proc/sys/vm/compaction_proactiveness");
+        std::fs::write(&proactiveness, "20\n").unwrap();
+
+        let mut memcg_config = memcg::Config::default();
+        memcg_config.default.disabled = true;
+        let compact_config = compact::Config {
+            mode: compact::Mode::Proactive,
+            proactiveness_min: 30,
+            period_secs: 1,
+            ..Default::default()
+        };
+
+        let (ma, _rt) = MemAgent::new_with_host_root(
+            memcg_config,
+            compact_config,
+            &root,
+            WORK_HISTORY_SIZE_DEFAULT,
+        )
+        .unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let wait_proactiveness = |value: &str| {
+            wait_until("compaction_proactiveness change", || {
+                std::fs::read_to_string(&proactiveness).unwrap() == value
+            });
+        };
+        wait_proactiveness("30\n");
+
+        // The original value is restored while compact is paused.
+        rt.block_on(ma.pause_async(PauseTarget::Compact, None))
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&proactiveness).unwrap(), "20\n");
+        let status = rt.block_on(ma.compact_status_async()).unwrap();
+        assert!(status.proactiveness.is_none());
+        assert!(status.proactiveness_original.is_none());
+
+        // And raised again after the resume.
+        rt.block_on(ma.resume_async(PauseTarget::Compact)).unwrap();
+        wait_proactiveness("30\n");
+        let status = rt.block_on(ma.compact_status_async()).unwrap();
+        assert_eq!(status.proactiveness_original, Some(20));
+    }
+
+    #[test]
+    fn test_agent_spawn_on() {
+        let root = setup_fake_host("test_agent_spawn_on");
+
+        let mut memcg_config = memcg::Config::default();
+        memcg_config.default.disabled = true;
+        let compact_config = compact::Config {
+            disabled: true,
+            ..Default::default()
+        };
+
+        // The runtime of the embedder, the agent must not block it.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let status = rt.block_on(async {
+            let ma = MemAgent::spawn_on_with_host_root(
+                memcg_config,
+                compact_config,
+                &root,
+                WORK_HISTORY_SIZE_DEFAULT,
+                &Handle::current(),
+            )
+            .await
+            .unwrap();
+
+            let mut opt = memcg::OptionConfig::default();
+            opt.default.period_secs = Some(120);
+            ma.memcg_set_config_async(opt).await.unwrap();
+            assert_eq!(
+                ma.memcg_get_config_async()
+                    .await
+                    .unwrap()
+                    .default
+                    .period_secs,
+                120
+            );
+            assert!(ma
+                .memcg_status_async()
+                .await
+                .unwrap()
+                .contains_key("/fake.slice"));
+
+            ma.shutdown_async().await.unwrap()
+        });
+        assert!(status.memcg.contains_key("/fake.slice"));
+    }
 }
@@ -2,9 +2,9 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::hostfs::HostFs;
 use anyhow::{anyhow, Result};
 use nix::sys::statfs::statfs;
-use std::path::Path;
 
 #[cfg(target_env = "musl")]
 const CGROUP2_SUPER_MAGIC: nix::sys::statfs::FsType = nix::sys::statfs::FsType(0x63677270);
@@ -13,11 +13,39 @@ use nix::sys::statfs::CGROUP2_SUPER_MAGIC;
 
 pub const CGROUP_PATH: &str = "/sys/fs/cgroup/";
 pub const MEMCGS_V1_PATH: &str = "/sys/fs/cgroup/memory";
+const MEMORY_CURRENT: &str = "memory.current";
+const MEMORY_USAGE_IN_BYTES: &str = "memory.usage_in_bytes";
 
-pub fn is_cgroup_v2() -> Result<bool> {
-    let cgroup_path = Path::new("/sys/fs/cgroup");
+pub fn is_cgroup_v2(host: &HostFs) -> Result<bool> {
+    let cgroup_path = host.path(CGROUP_PATH);
 
     let stat =
-        statfs(cgroup_path).map_err(|e| anyhow!("statfs {:?} failed: {}", cgroup_path, e))?;
-    Ok(stat.filesystem_type() == CGROUP2_SUPER_MAGIC)
+        statfs(&cgroup_path).map_err(|e| anyhow!("statfs {:?} failed: {}", cgroup_path, e))?;
+    if stat.filesystem_type() == CGROUP2_SUPER_MAGIC {
+        return Ok(true);
+    }
+
+    // A fake tree is not cgroup2 filesystem, check the file that only
+    // the root of cgroup v2 has.
+    Ok(!host.is_default_root() && cgroup_path.join("cgroup.controllers").is_file())
+}
+
+// Get the memory usage in bytes of the memcg path.
+pub fn memory_current(host: &HostFs, is_cg_v2: bool, path: &str) -> Result<u64> {
+    let file = if is_cg_v2 {
+        host.path(CGROUP_PATH)
+            .join(path.trim_start_matches('/'))
+            .join(MEMORY_CURRENT)
+    } else {
+        host.path(MEMCGS_V1_PATH)
+            .join(path.trim_start_matches('/'))
+            .join(MEMORY_USAGE_IN_BYTES)
+    };
+
+    let content =
+        std::fs::read_to_string(&file).map_err(|e| anyhow!("read {:?} failed: {}", file, e))?;
+    content
+        .trim()
+        .parse::<u64>()
+        .map_err(|e| anyhow!("parse {:?} failed: {}", file, e))
 }
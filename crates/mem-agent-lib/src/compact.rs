@@ -3,24 +3,68 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::cgroup::CGROUP_PATH;
+use crate::event::{ConfigTarget, EventKind, Events};
+use crate::hostfs::HostFs;
+use crate::pause::{Pause, PauseStatus};
 use crate::proc;
 use crate::psi;
 use crate::timer::Timeout;
-use crate::{debug, error, info, trace};
+use crate::{debug, error, info, trace, warn};
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use nix::errno::Errno;
 use nix::sched::sched_yield;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::Command;
-use std::sync::Arc;
-use std::thread;
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{fork, ForkResult, Pid};
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::time::Duration as TokioDuration;
 
 const PAGE_REPORTING_MIN_ORDER: u8 = 9;
 
+// How compact works.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    // Trigger the compaction through compact_memory.
+    #[default]
+    Trigger,
+    // Raise and lower vm.compaction_proactiveness and let kcompactd
+    // compact in the background.
+    Proactive,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "trigger" => Ok(Mode::Trigger),
+            "proactive" => Ok(Mode::Proactive),
+            _ => Err(anyhow!("compact mode {} is invalid", s)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Trigger => write!(f, "trigger"),
+            Mode::Proactive => write!(f, "proactive"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     pub disabled: bool,
@@ -50,6 +94,35 @@ pub struct Config {
     // If compact_force_times is set to 0, will do force compaction each time.
     // If compact_force_times is set to std::u64::MAX, will never do force compaction.
     pub compact_force_times: u64,
+
+    // Not write compact_memory, record the free movable pages that
+    // would be compacted.
+    pub dry_run: bool,
+
+    // Check compact_threshold for each numa node and compact the nodes
+    // that need it one by one.
+    pub per_node: bool,
+    // The nodes that per_node mode works on, empty means all the nodes.
+    pub nodes_include: Vec<u32>,
+    // The nodes that per_node mode doesn't work on.
+    pub nodes_exclude: Vec<u32>,
+
+    pub mode: Mode,
+    // In proactive mode, each run period raises
+    // vm.compaction_proactiveness by proactiveness_step if the free
+    // movable pages of the orders smaller than compact_order are more
+    // than compact_threshold, and lowers it by proactiveness_step if not.
+    // It is kept inside [proactiveness_min, proactiveness_max].
+    pub proactiveness_min: u8,
+    pub proactiveness_max: u8,
+    pub proactiveness_step: u8,
+}
+
+impl Config {
+    fn node_selected(&self, node: u32) -> bool {
+        (self.nodes_include.is_empty() || self.nodes_include.contains(&node))
+            && !self.nodes_exclude.contains(&node)
+    }
 }
 
 impl Default for Config {
@@ -64,6 +137,14 @@ impl Default for Config {
             compact_order: PAGE_REPORTING_MIN_ORDER,
             compact_threshold: 2 << PAGE_REPORTING_MIN_ORDER,
             compact_force_times: std::u64::MAX,
+            dry_run: false,
+            per_node: false,
+            nodes_include: Vec::new(),
+            nodes_exclude: Vec::new(),
+            mode: Mode::Trigger,
+            proactiveness_min: 0,
+            proactiveness_max: 50,
+            proactiveness_step: 10,
         }
     }
 }
@@ -82,6 +163,160 @@ pub struct OptionConfig {
     pub compact_threshold: Option<u64>,
 
     pub compact_force_times: Option<u64>,
+
+    pub dry_run: Option<bool>,
+
+    pub per_node: Option<bool>,
+    pub nodes_include: Option<Vec<u32>>,
+    pub nodes_exclude: Option<Vec<u32>>,
+
+    pub mode: Option<Mode>,
+    pub proactiveness_min: Option<u8>,
+    pub proactiveness_max: Option<u8>,
+    pub proactiveness_step: Option<u8>,
+}
+
+// An on-demand compaction, None means use the value of the config.
+#[derive(Debug, Clone, Default)]
+pub struct CompactRequest {
+    pub compact_order: Option<u8>,
+    pub compact_sec_max: Option<i64>,
+    pub compact_psi_percent_limit: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    // period psi exceeds period_psi_percent_limit
+    PsiSkipped,
+    // not enough free movable pages, see compact_threshold
+    ThresholdSkipped,
+    Done,
+    // killed because compaction seconds is bigger than compact_sec_max
+    SecMaxKilled,
+    // killed because psi exceeds compact_psi_percent_limit
+    PsiKilled,
+    // not run because of dry_run
+    DryRun,
+    // killed because compact is paused
+    PauseKilled,
+    // proactive mode raised vm.compaction_proactiveness or kept it at
+    // proactiveness_max
+    ProactiveRaised,
+    // proactive mode lowered it by a step because of compact_threshold or
+    // kept it at proactiveness_min
+    ProactiveLowered,
+    // proactive mode lowered it to proactiveness_min because psi exceeds
+    // compact_psi_percent_limit
+    ProactivePsiLowered,
+    // proactive mode didn't change it because compact is paused
+    ProactivePaused,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OutcomeCount {
+    pub psi_skipped: u64,
+    pub threshold_skipped: u64,
+    pub forced: u64,
+    pub done: u64,
+    pub sec_max_killed: u64,
+    pub psi_killed: u64,
+    pub dry_run: u64,
+    pub pause_killed: u64,
+    pub proactive_raised: u64,
+    pub proactive_lowered: u64,
+    pub proactive_psi_lowered: u64,
+    pub proactive_paused: u64,
+}
+
+impl OutcomeCount {
+    fn inc(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::PsiSkipped => self.psi_skipped += 1,
+            Outcome::ThresholdSkipped => self.threshold_skipped += 1,
+            Outcome::Done => self.done += 1,
+            Outcome::SecMaxKilled => self.sec_max_killed += 1,
+            Outcome::PsiKilled => self.psi_killed += 1,
+            Outcome::DryRun => self.dry_run += 1,
+            Outcome::PauseKilled => self.pause_killed += 1,
+            Outcome::ProactiveRaised => self.proactive_raised += 1,
+            Outcome::ProactiveLowered => self.proactive_lowered += 1,
+            Outcome::ProactivePsiLowered => self.proactive_psi_lowered += 1,
+            Outcome::ProactivePaused => self.proactive_paused += 1,
+        }
+    }
+}
+
+// How the compact sysctl is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecMethod {
+    // A forked helper process writes it. SIGKILL stops the compaction
+    // inside the kernel.
+    Process,
+    // A helper thread writes it when fork is not available. The write
+    // cannot be interrupted.
+    Thread,
+}
+
+#[derive(Debug, Clone)]
+pub struct LastRun {
+    pub forced: bool,
+    pub outcome: Outcome,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub free_movable_pages_before: u64,
+    pub free_movable_pages_after: u64,
+    // None if no compaction was executed.
+    pub exec_method: Option<ExecMethod>,
+    // The compaction was asked to stop but kept running until it finished.
+    pub uninterruptible: bool,
+}
+
+// The status of a numa node in per_node mode.
+#[derive(Debug, Clone, Default)]
+pub struct NodeStatus {
+    pub count: OutcomeCount,
+    pub prev_free_movable_pages_after_compact: u64,
+    pub prev_memfree_kb: u64,
+    pub last_outcome: Option<Outcome>,
+    pub last_run: Option<LastRun>,
+}
+
+impl NodeStatus {
+    fn record_skip(&mut self, outcome: Outcome) {
+        self.count.inc(outcome);
+        self.last_outcome = Some(outcome);
+    }
+
+    fn record_run(&mut self, run: LastRun) {
+        if run.forced {
+            self.count.forced += 1;
+        }
+        self.count.inc(run.outcome);
+        self.last_outcome = Some(run.outcome);
+        self.last_run = Some(run);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub count: OutcomeCount,
+    pub force_counter: u64,
+    pub prev_free_movable_pages_after_compact: u64,
+    pub prev_memfree_kb: u64,
+    pub last_outcome: Option<Outcome>,
+    // the last time that compaction was really run
+    pub last_run: Option<LastRun>,
+    // None if compact is disabled
+    pub next_run_time: Option<DateTime<Utc>>,
+    pub pause: PauseStatus,
+    // node id->status, only has the nodes that per_node mode worked on
+    pub nodes: BTreeMap<u32, NodeStatus>,
+    // The vm.compaction_proactiveness that proactive mode set, None if
+    // it is not changed.
+    pub proactiveness: Option<u64>,
+    // The vm.compaction_proactiveness before proactive mode, it is
+    // restored when proactive mode stops.
+    pub proactiveness_original: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -92,6 +327,17 @@ struct CompactCore {
     force_counter: u64,
     prev_free_movable_pages_after_compact: u64,
     prev_memfree_kb: u64,
+
+    count: OutcomeCount,
+    last_outcome: Option<Outcome>,
+    last_run: Option<LastRun>,
+
+    nodes: BTreeMap<u32, NodeStatus>,
+
+    proactiveness: Option<u64>,
+    proactiveness_original: Option<u64>,
+    // compact psi of proactive mode, it is checked each run period.
+    proactive_psi: Option<psi::Period>,
 }
 
 impl CompactCore {
@@ -102,15 +348,52 @@ impl CompactCore {
             force_counter: 0,
             prev_free_movable_pages_after_compact: 0,
             prev_memfree_kb: 0,
+            count: OutcomeCount::default(),
+            last_outcome: None,
+            last_run: None,
+            nodes: BTreeMap::new(),
+            proactiveness: None,
+            proactiveness_original: None,
+            proactive_psi: None,
             config,
         }
     }
 
-    fn psi_ok(&mut self) -> bool {
-        if crate::misc::is_test_environment() {
-            return false;
+    fn record_skip(&mut self, outcome: Outcome) {
+        self.count.inc(outcome);
+        self.last_outcome = Some(outcome);
+    }
+
+    fn record_run(&mut self, run: LastRun) {
+        if run.forced {
+            self.count.forced += 1;
+        }
+        self.count.inc(run.outcome);
+        self.last_outcome = Some(run.outcome);
+        self.last_run = Some(run);
+    }
+
+    fn get_status(&self) -> Status {
+        Status {
+            count: self.count.clone(),
+            force_counter: self.force_counter,
+            prev_free_movable_pages_after_compact: self.prev_free_movable_pages_after_compact,
+            prev_memfree_kb: self.prev_memfree_kb,
+            last_outcome: self.last_outcome,
+            last_run: self.last_run.clone(),
+            next_run_time: if self.config.disabled {
+                None
+            } else {
+                Some(self.timeout.timeout_time())
+            },
+            pause: PauseStatus::default(),
+            nodes: self.nodes.clone(),
+            proactiveness: self.proactiveness,
+            proactiveness_original: self.proactiveness_original,
         }
+    }
 
+    fn psi_ok(&mut self) -> bool {
         self.psi
             .compare_percent_maybe_update(self.config.period_psi_percent_limit as u64)
             .unwrap_or_else(|e| {
@@ -144,6 +427,34 @@ impl CompactCore {
         }
     }
 
+    fn check_node_compact_threshold(
+        &self,
+        node: u32,
+        memfree_kb: u64,
+        free_movable_pages: u64,
+    ) -> bool {
+        let (prev_memfree_kb, prev_free_movable_pages) = self
+            .nodes
+            .get(&node)
+            .map(|n| (n.prev_memfree_kb, n.prev_free_movable_pages_after_compact))
+            .unwrap_or_default();
+
+        if prev_memfree_kb > memfree_kb + (self.config.compact_threshold << 2) {
+            return true;
+        }
+
+        let threshold = self.config.compact_threshold + prev_free_movable_pages;
+        if free_movable_pages > threshold {
+            true
+        } else {
+            info!(
+                "compact will not work on node {} because free movable pages {} less than threshold {} and prev_free {}kB current_free {}kB",
+                node, free_movable_pages, threshold, prev_memfree_kb, memfree_kb
+            );
+            false
+        }
+    }
+
     fn get_special_psi(&self) -> psi::Period {
         psi::Period::new(&self.config.psi_path, true)
     }
@@ -192,6 +503,30 @@ impl CompactCore {
         if let Some(p) = new_config.compact_force_times {
             self.config.compact_force_times = p;
         }
+        if let Some(d) = new_config.dry_run {
+            self.config.dry_run = d;
+        }
+        if let Some(p) = new_config.per_node {
+            self.config.per_node = p;
+        }
+        if let Some(n) = new_config.nodes_include {
+            self.config.nodes_include = n;
+        }
+        if let Some(n) = new_config.nodes_exclude {
+            self.config.nodes_exclude = n;
+        }
+        if let Some(m) = new_config.mode {
+            self.config.mode = m;
+        }
+        if let Some(p) = new_config.proactiveness_min {
+            self.config.proactiveness_min = p;
+        }
+        if let Some(p) = new_config.proactiveness_max {
+            self.config.proactiveness_max = p;
+        }
+        if let Some(p) = new_config.proactiveness_step {
+            self.config.proactiveness_step = p;
+        }
         if let Some(p) = new_config.period_secs {
             self.config.period_secs = p;
             self.timeout.set_sleep_duration(p);
@@ -226,42 +561,257 @@ impl CompactCore {
 
 #[derive(Debug, Clone)]
 pub struct Compact {
+    host: HostFs,
     core: Arc<RwLock<CompactCore>>,
+    events: Events,
+    pause: Pause,
+    // work and compact_now write the same files and prev values, run them
+    // one by one.
+    run_lock: Arc<Mutex<()>>,
+}
+
+const PAGETYPEINFO_PATH: &str = "/proc/pagetypeinfo";
+const COMPACT_MEMORY_PATH: &str = "/proc/sys/vm/compact_memory";
+const COMPACTION_PROACTIVENESS_PATH: &str = "/proc/sys/vm/compaction_proactiveness";
+
+const NODE_PATH: &str = "/sys/devices/system/node";
+// The seconds to wait for a killed compaction to quit.
+const COMPACT_KILL_WAIT_SECS: i64 = 10;
+
+// Decide the outcome and the next vm.compaction_proactiveness of a run
+// period of proactive mode.
+fn next_proactiveness(
+    config: &Config,
+    current: u64,
+    compact_psi_percent: u64,
+    period_psi_ok: bool,
+    free_movable_pages: u64,
+) -> (Outcome, u64) {
+    let min = config.proactiveness_min as u64;
+    let max = (config.proactiveness_max as u64).max(min);
+    let step = config.proactiveness_step as u64;
+    let current = current.clamp(min, max);
+
+    if compact_psi_percent > config.compact_psi_percent_limit as u64 {
+        (Outcome::ProactivePsiLowered, min)
+    } else if !period_psi_ok {
+        (Outcome::PsiSkipped, current)
+    } else if free_movable_pages > config.compact_threshold {
+        (Outcome::ProactiveRaised, (current + step).min(max))
+    } else {
+        (
+            Outcome::ProactiveLowered,
+            current.saturating_sub(step).max(min),
+        )
+    }
+}
+
+fn node_compact_path(node: u32) -> String {
+    format!("{}/node{}/compact", NODE_PATH, node)
+}
+
+// The result of writing a compact sysctl.
+#[derive(Debug, Clone, Copy)]
+struct Exec {
+    outcome: Outcome,
+    method: Option<ExecMethod>,
+    uninterruptible: bool,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+}
+
+impl Exec {
+    // Merge the execs of the chunks of a compaction to the outcome, the
+    // execution method and if it was uninterruptible.
+    fn merge(execs: &[Exec]) -> (Outcome, Option<ExecMethod>, bool) {
+        (
+            execs.last().map_or(Outcome::Done, |e| e.outcome),
+            execs.iter().rev().find_map(|e| e.method),
+            execs.iter().any(|e| e.uninterruptible),
+        )
+    }
+}
+
+#[derive(Debug)]
+enum CompactHelper {
+    // None after the process is reaped.
+    Process(Option<Pid>),
+    Thread(Option<JoinHandle<std::io::Result<()>>>),
+}
+
+impl CompactHelper {
+    fn spawn(path: &Path) -> Result<Self> {
+        let cpath = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| anyhow!("CString::new failed: {}", e))?;
+
+        // Only async-signal-safe functions can be called in the child
+        // because this process is multi-threaded.
+        // The sysctl file must exist, don't create it.
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => unsafe {
+                let fd = nix::libc::open(cpath.as_ptr(), nix::libc::O_WRONLY | nix::libc::O_TRUNC);
+                let mut code = 1;
+                if fd >= 0 && nix::libc::write(fd, b"1\n".as_ptr() as *const _, 2) == 2 {
+                    code = 0;
+                }
+                nix::libc::_exit(code);
+            },
+            Ok(ForkResult::Parent { child }) => Ok(CompactHelper::Process(Some(child))),
+            Err(e) => {
+                warn!("fork failed: {}, compact with a thread", e);
+                Ok(Self::spawn_thread(path.to_path_buf()))
+            }
+        }
+    }
+
+    fn spawn_thread(path: PathBuf) -> Self {
+        CompactHelper::Thread(Some(thread::spawn(move || {
+            OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(path)?
+                .write_all(b"1\n")
+        })))
+    }
+
+    fn method(&self) -> ExecMethod {
+        match self {
+            CompactHelper::Process(_) => ExecMethod::Process,
+            CompactHelper::Thread(_) => ExecMethod::Thread,
+        }
+    }
+
+    // Return true if the write is finished.
+    fn try_wait(&mut self) -> Result<bool> {
+        match self {
+            CompactHelper::Process(pid) => {
+                let p = match pid {
+                    Some(p) => *p,
+                    None => return Ok(true),
+                };
+                let status = match waitpid(p, Some(WaitPidFlag::WNOHANG)) {
+                    Ok(status) => status,
+                    // The SIGCHLD handler of the embedder might reap the
+                    // helper, its exit status is lost.
+                    Err(Errno::ECHILD) => {
+                        warn!("compact helper {} was reaped by others", p);
+                        *pid = None;
+                        return Ok(true);
+                    }
+                    Err(e) => return Err(anyhow!("waitpid failed: {}", e)),
+                };
+                if status == WaitStatus::StillAlive {
+                    return Ok(false);
+                }
+                *pid = None;
+                match status {
+                    WaitStatus::Exited(_, 0) | WaitStatus::Signaled(_, Signal::SIGKILL, _) => {
+                        Ok(true)
+                    }
+                    status => Err(anyhow!("helper quit with {:?}", status)),
+                }
+            }
+            CompactHelper::Thread(handle) => {
+                if !handle.as_ref().is_some_and(|h| h.is_finished()) {
+                    return Ok(handle.is_none());
+                }
+                handle
+                    .take()
+                    .unwrap()
+                    .join()
+                    .map_err(|_| anyhow!("helper thread panicked"))?
+                    .map_err(|e| anyhow!("write failed: {}", e))?;
+                Ok(true)
+            }
+        }
+    }
+
+    // Return false if the write cannot be interrupted.
+    fn kill(&self) -> Result<bool> {
+        match self {
+            CompactHelper::Process(Some(pid)) => {
+                kill(*pid, Signal::SIGKILL).map_err(|e| anyhow!("kill failed: {}", e))?;
+                Ok(true)
+            }
+            CompactHelper::Process(None) => Ok(true),
+            CompactHelper::Thread(_) => Ok(false),
+        }
+    }
+}
+
+impl Drop for CompactHelper {
+    // Reap the helper process that doesn't quit in time.
+    fn drop(&mut self) {
+        if let CompactHelper::Process(Some(pid)) = *self {
+            if let Ok(WaitStatus::StillAlive) = waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                thread::spawn(move || waitpid(pid, None));
+            }
+        }
+    }
 }
 
 impl Compact {
-    pub fn new(is_cg_v2: bool, mut config: Config) -> Result<Self> {
+    pub fn new(host: &HostFs, is_cg_v2: bool, mut config: Config) -> Result<Self> {
         if is_cg_v2 {
-            config.psi_path = PathBuf::from(CGROUP_PATH);
+            config.psi_path = host.path(CGROUP_PATH);
         }
 
         config.psi_path =
-            psi::check(&config.psi_path).map_err(|e| anyhow!("psi::check failed: {}", e))?;
+            psi::check(host, &config.psi_path).map_err(|e| anyhow!("psi::check failed: {}", e))?;
 
         let c = Self {
+            host: host.clone(),
             core: Arc::new(RwLock::new(CompactCore::new(config))),
+            events: Events::default(),
+            pause: Pause::default(),
+            run_lock: Arc::new(Mutex::new(())),
         };
 
         Ok(c)
     }
 
     fn calculate_free_movable_pages(&self) -> Result<u64> {
-        let file = File::open("/proc/pagetypeinfo")?;
+        let order = self.core.blocking_read().config.compact_order;
+        self.calculate_free_movable_pages_order(order)
+    }
+
+    // Get the free movable pages of the orders smaller than order.
+    fn calculate_free_movable_pages_order(&self, order: u8) -> Result<u64> {
+        Ok(self
+            .calculate_node_free_movable_pages_order(order)?
+            .values()
+            .sum())
+    }
+
+    // Get the free movable pages of the orders smaller than order of each
+    // numa node.
+    fn calculate_node_free_movable_pages_order(&self, order: u8) -> Result<BTreeMap<u32, u64>> {
+        let file = File::open(self.host.path(PAGETYPEINFO_PATH))?;
         let reader = BufReader::new(file);
 
-        let order_limit = self.core.blocking_read().config.compact_order as usize;
+        let order_limit = order as usize;
 
-        let mut total_free_movable_pages = 0;
+        let mut node_free_movable_pages = BTreeMap::new();
 
         for line in reader.lines() {
             let line = line?;
             if line.contains("Movable") {
                 let parts: Vec<&str> = line.split_whitespace().collect();
+                // The lines are like
+                // "Node    0, zone   Normal, type      Movable      4      3"
+                if parts.len() < 2 || parts[0] != "Node" {
+                    continue;
+                }
+                let node = match parts[1].trim_end_matches(',').parse::<u32>() {
+                    Ok(node) => node,
+                    Err(_) => continue,
+                };
+                let free_movable_pages = node_free_movable_pages.entry(node).or_insert(0);
                 if let Some(index) = parts.iter().position(|&element| element == "Movable") {
                     for (order, &count_str) in parts[(index + 1)..].iter().enumerate() {
                         if order < order_limit {
                             if let Ok(count) = count_str.parse::<u64>() {
-                                total_free_movable_pages += count * 1 << order;
+                                *free_movable_pages += count << order;
                             }
                         }
                     }
@@ -269,11 +819,11 @@ impl Compact {
             }
         }
 
-        Ok(total_free_movable_pages)
+        Ok(node_free_movable_pages)
     }
 
     fn check_compact_threshold(&self) -> bool {
-        let memfree_kb = match proc::get_memfree_kb() {
+        let memfree_kb = match proc::get_memfree_kb(&self.host) {
             Ok(v) => v,
             Err(e) => {
                 error!("get_memfree_kb failed: {}", e);
@@ -293,9 +843,10 @@ impl Compact {
             .check_compact_threshold(memfree_kb, free_movable_pages)
     }
 
-    fn set_prev(&mut self) -> Result<()> {
-        let memfree_kb =
-            proc::get_memfree_kb().map_err(|e| anyhow!("get_memfree_kb failed: {}", e))?;
+    // return the free movable pages after compaction
+    fn set_prev(&mut self) -> Result<u64> {
+        let memfree_kb = proc::get_memfree_kb(&self.host)
+            .map_err(|e| anyhow!("get_memfree_kb failed: {}", e))?;
         let free_movable_pages = self
             .calculate_free_movable_pages()
             .map_err(|e| anyhow!("calculate_free_movable_pages failed: {}", e))?;
@@ -304,72 +855,92 @@ impl Compact {
             .blocking_write()
             .set_prev(memfree_kb, free_movable_pages);
 
-        Ok(())
+        Ok(free_movable_pages)
     }
 
-    fn do_compact(&self) -> Result<()> {
-        let compact_psi_percent_limit = self.core.blocking_read().config.compact_psi_percent_limit;
+    // Write 1 to path, it is compact_memory or the compact of a node.
+    fn do_compact(
+        &self,
+        path: &Path,
+        compact_sec_max: i64,
+        compact_psi_percent_limit: u8,
+    ) -> Result<Exec> {
         let mut compact_psi = self.core.blocking_read().get_special_psi();
-        let mut rest_sec = self.core.blocking_read().config.compact_sec_max;
+        let mut rest_sec = compact_sec_max;
 
         if let Err(e) = sched_yield() {
             error!("sched_yield failed: {:?}", e);
         }
 
-        info!("compact start");
+        info!("compact start {:?}", path);
 
-        let mut child = Command::new("sh")
-            .arg("-c")
-            .arg("echo 1 > /proc/sys/vm/compact_memory")
-            .spawn()
-            .map_err(|e| anyhow!("Command::new failed: {}", e))?;
+        let start_time = Utc::now();
+        let mut helper = CompactHelper::spawn(path)
+            .map_err(|e| anyhow!("CompactHelper::spawn failed: {}", e))?;
 
-        debug!("compact pid {}", child.id());
+        debug!("compact helper {:?}", helper);
 
+        let mut outcome = Outcome::Done;
         let mut killed = false;
+        let mut uninterruptible = false;
         loop {
-            match child.try_wait() {
-                Ok(Some(status)) => {
-                    debug!("compact done with status {}", status);
+            match helper.try_wait() {
+                Ok(true) => {
+                    debug!("compact done");
                     break;
                 }
-                Ok(None) => {
+                Ok(false) => {
                     if killed {
-                        if rest_sec <= 0 {
+                        // The helper thread cannot be left behind because
+                        // the next compaction would run with it.
+                        if rest_sec <= 0 && helper.method() == ExecMethod::Process {
                             error!("compact killed but not quit");
+                            uninterruptible = true;
                             break;
                         } else {
                             debug!("compact killed and keep wait");
                         }
                     } else {
+                        let mut stop = None;
                         if rest_sec <= 0 {
                             debug!("compact timeout");
-                            child
-                                .kill()
-                                .map_err(|e| anyhow!("child.kill failed: {}", e))?;
-                            killed = true;
+                            stop = Some(Outcome::SecMaxKilled);
+                        } else if self.pause.is_paused() {
+                            info!("compaction need stop because paused");
+                            stop = Some(Outcome::PauseKilled);
+                        } else {
+                            let percent = compact_psi
+                                .get_percent()
+                                .map_err(|e| anyhow!("compact_psi.get_percent failed: {}", e))?;
+                            if percent > compact_psi_percent_limit as u64 {
+                                info!(
+                                    "compaction need stop because period psi {}% exceeds limit",
+                                    percent
+                                );
+                                stop = Some(Outcome::PsiKilled);
+                            }
                         }
-                    }
 
-                    let percent = compact_psi
-                        .get_percent()
-                        .map_err(|e| anyhow!("compact_psi.get_percent failed: {}", e))?;
-                    if percent > compact_psi_percent_limit as u64 {
-                        info!(
-                            "compaction need stop because period psi {}% exceeds limit",
-                            percent
-                        );
-                        child
-                            .kill()
-                            .map_err(|e| anyhow!("child.kill failed: {}", e))?;
-                        killed = true;
+                        if let Some(o) = stop {
+                            outcome = o;
+                            killed = true;
+                            if !helper
+                                .kill()
+                                .map_err(|e| anyhow!("helper.kill failed: {}", e))?
+                            {
+                                warn!(
+                                    "compaction of {:?} cannot be interrupted, wait it finish",
+                                    path
+                                );
+                                uninterruptible = true;
+                            }
+                            // The time left is for the helper to quit.
+                            rest_sec = rest_sec.max(COMPACT_KILL_WAIT_SECS);
+                        }
                     }
                 }
                 Err(e) => {
-                    // try_wait will fail with code 10 because some task will
-                    // wait compact task before try_wait.
-                    debug!("compact try_wait fail: {:?}", e);
-                    break;
+                    return Err(anyhow!("compact {:?} failed: {}", path, e));
                 }
             }
 
@@ -377,12 +948,86 @@ impl Compact {
             rest_sec -= 1;
         }
 
-        info!("compact stop");
+        info!("compact stop {:?} with {:?}", outcome, helper.method());
 
-        Ok(())
+        Ok(Exec {
+            outcome,
+            method: Some(helper.method()),
+            uninterruptible,
+            start_time,
+            end_time: Utc::now(),
+        })
+    }
+
+    // Get the compact files of the numa nodes, empty if any node doesn't
+    // have it.
+    fn node_compact_paths(&self) -> Vec<PathBuf> {
+        let dir = match std::fs::read_dir(self.host.path(NODE_PATH)) {
+            Ok(dir) => dir,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut nodes: Vec<u32> = dir
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.strip_prefix("node"))
+                    .and_then(|id| id.parse::<u32>().ok())
+            })
+            .collect();
+        nodes.sort_unstable();
+
+        let paths: Vec<PathBuf> = nodes
+            .into_iter()
+            .map(|node| self.host.path(node_compact_path(node)))
+            .collect();
+        if paths.iter().all(|path| path.exists()) {
+            paths
+        } else {
+            Vec::new()
+        }
+    }
+
+    // Compact the paths one by one, they share compact_sec_max and the
+    // paths after a killed one are not compacted.
+    fn do_compact_chunks(
+        &self,
+        paths: &[PathBuf],
+        compact_sec_max: i64,
+        compact_psi_percent_limit: u8,
+    ) -> Result<Vec<Exec>> {
+        let start_time = Utc::now();
+        let mut execs = Vec::new();
+        for path in paths {
+            let rest_sec = compact_sec_max - (Utc::now() - start_time).num_seconds();
+            let exec = if rest_sec <= 0 {
+                let now = Utc::now();
+                Exec {
+                    outcome: Outcome::SecMaxKilled,
+                    method: None,
+                    uninterruptible: false,
+                    start_time: now,
+                    end_time: now,
+                }
+            } else {
+                self.do_compact(path, rest_sec, compact_psi_percent_limit)?
+            };
+            execs.push(exec);
+
+            if exec.outcome != Outcome::Done {
+                break;
+            }
+        }
+
+        Ok(execs)
     }
 
     pub fn need_work(&self) -> bool {
+        if self.pause.is_paused() {
+            return false;
+        }
         self.core.blocking_read().need_work()
     }
 
@@ -391,53 +1036,1049 @@ impl Compact {
     }
 
     pub fn get_remaining_tokio_duration(&self) -> TokioDuration {
-        self.core.blocking_read().get_remaining_tokio_duration()
+        self.pause
+            .delay(self.core.blocking_read().get_remaining_tokio_duration())
     }
 
     pub async fn async_get_remaining_tokio_duration(&self) -> TokioDuration {
-        self.core.read().await.get_remaining_tokio_duration()
+        self.pause
+            .delay(self.core.read().await.get_remaining_tokio_duration())
     }
 
-    pub fn work(&mut self) -> Result<()> {
+    // Return the outcome of this work.
+    pub fn work(&mut self) -> Result<Outcome> {
+        let run_lock = self.run_lock.clone();
+        let _run = run_lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        if self.core.blocking_read().config.mode == Mode::Proactive {
+            return self.work_proactive();
+        }
+        self.blocking_restore_proactiveness()
+            .map_err(|e| anyhow!("restore_proactiveness failed: {}", e))?;
+
+        if self.core.blocking_read().config.per_node {
+            return self.work_nodes();
+        }
+
         let mut can_work = self.core.blocking_write().psi_ok();
+        let mut forced = false;
+        let mut outcome = Outcome::PsiSkipped;
         if can_work {
             if !self.core.blocking_read().need_force_compact() {
                 if !self.check_compact_threshold() {
                     trace!("not enough free movable pages");
                     can_work = false;
+                    outcome = Outcome::ThresholdSkipped;
+                    self.core.blocking_write().record_skip(outcome);
                 }
             } else {
                 trace!("force compact");
+                forced = true;
             }
+        } else {
+            self.core.blocking_write().record_skip(outcome);
         }
 
-        if can_work {
-            self.do_compact()
-                .map_err(|e| anyhow!("do_compact failed: {}", e))?;
-
-            self.set_prev()?;
-
-            self.core.blocking_write().force_counter = 0;
+        if can_work && self.core.blocking_read().config.dry_run {
+            let free_movable_pages = self.calculate_free_movable_pages().unwrap_or_else(|e| {
+                error!("calculate_free_movable_pages failed: {}", e);
+                0
+            });
+            info!("compact dry run, free movable pages {}", free_movable_pages);
+
+            outcome = self
+                .record_dry_run(forced, &BTreeMap::new(), free_movable_pages)
+                .outcome;
+        } else if can_work {
+            let config = self.core.blocking_read().config.clone();
+            outcome = self
+                .run_compact(
+                    forced,
+                    config.compact_order,
+                    config.compact_sec_max,
+                    config.compact_psi_percent_limit,
+                )?
+                .outcome;
         } else {
             self.core.blocking_write().force_counter += 1;
         }
 
+        Ok(outcome)
+    }
+
+    fn read_proactiveness(&self) -> Result<u64> {
+        let path = self.host.path(COMPACTION_PROACTIVENESS_PATH);
+        let s =
+            std::fs::read_to_string(&path).map_err(|e| anyhow!("read {:?} failed: {}", path, e))?;
+        s.trim()
+            .parse::<u64>()
+            .map_err(|e| anyhow!("parse {:?} failed: {}", s, e))
+    }
+
+    fn write_proactiveness(&self, proactiveness: u64) -> Result<()> {
+        let path = self.host.path(COMPACTION_PROACTIVENESS_PATH);
+        std::fs::write(&path, format!("{}\n", proactiveness))
+            .map_err(|e| anyhow!("write {:?} failed: {}", path, e))
+    }
+
+    // The work of proactive mode, it only changes
+    // vm.compaction_proactiveness and kcompactd does the compaction.
+    fn work_proactive(&mut self) -> Result<Outcome> {
+        let config = self.core.blocking_read().config.clone();
+
+        let original = match self.core.blocking_read().proactiveness_original {
+            Some(original) => original,
+            None => {
+                let original = self
+                    .read_proactiveness()
+                    .map_err(|e| anyhow!("read_proactiveness failed: {}", e))?;
+                info!("compaction_proactiveness original value {}", original);
+                original
+            }
+        };
+
+        let mut core = self.core.blocking_write();
+        if core.proactive_psi.is_none() {
+            core.proactive_psi = Some(core.get_special_psi());
+        }
+        let compact_psi_percent = core
+            .proactive_psi
+            .as_mut()
+            .unwrap()
+            .get_percent()
+            .map_err(|e| anyhow!("proactive_psi.get_percent failed: {}", e))?;
+        let period_psi_ok = core.psi_ok();
+        drop(core);
+
+        let free_movable_pages = self
+            .calculate_free_movable_pages()
+            .map_err(|e| anyhow!("calculate_free_movable_pages failed: {}", e))?;
+
+        let current = self.core.blocking_read().proactiveness.unwrap_or(original);
+        let (mut outcome, proactiveness) = next_proactiveness(
+            &config,
+            current,
+            compact_psi_percent,
+            period_psi_ok,
+            free_movable_pages,
+        );
+        info!(
+            "compact proactive {:?} compaction_proactiveness {} -> {} free movable pages {} compact psi {}%",
+            outcome, current, proactiveness, free_movable_pages, compact_psi_percent
+        );
+
+        let mut core = self.core.blocking_write();
+        if config.dry_run {
+            outcome = Outcome::DryRun;
+        } else if self.pause.is_paused() {
+            // core is locked, a later pause restores what is written here.
+            info!("compaction_proactiveness not changed because paused");
+            outcome = Outcome::ProactivePaused;
+        } else if core.proactiveness != Some(proactiveness) {
+            self.write_proactiveness(proactiveness)
+                .map_err(|e| anyhow!("write_proactiveness failed: {}", e))?;
+            core.proactiveness_original = Some(original);
+            core.proactiveness = Some(proactiveness);
+        }
+        core.record_skip(outcome);
+
+        Ok(outcome)
+    }
+
+    fn restore_proactiveness_core(&self, core: &mut CompactCore) -> Result<()> {
+        core.proactive_psi = None;
+        if let (Some(original), Some(_)) = (core.proactiveness_original, core.proactiveness) {
+            info!("restore compaction_proactiveness to {}", original);
+            self.write_proactiveness(original)?;
+        }
+        core.proactiveness_original = None;
+        core.proactiveness = None;
+
         Ok(())
     }
 
+    fn blocking_restore_proactiveness(&self) -> Result<()> {
+        self.restore_proactiveness_core(&mut self.core.blocking_write())
+    }
+
+    // Restore vm.compaction_proactiveness if proactive mode changed it.
+    pub async fn restore_proactiveness(&self) -> Result<()> {
+        self.restore_proactiveness_core(&mut *self.core.write().await)
+    }
+
+    // The work of per_node mode, compact_threshold is checked for each
+    // node and only the nodes that need it are compacted.
+    fn work_nodes(&mut self) -> Result<Outcome> {
+        if !self.core.blocking_write().psi_ok() {
+            let mut core = self.core.blocking_write();
+            core.record_skip(Outcome::PsiSkipped);
+            core.force_counter += 1;
+            return Ok(Outcome::PsiSkipped);
+        }
+
+        let config = self.core.blocking_read().config.clone();
+        let forced = self.core.blocking_read().need_force_compact();
+        if forced {
+            trace!("force compact");
+        }
+
+        let node_free_movable_pages = self
+            .calculate_node_free_movable_pages_order(config.compact_order)
+            .map_err(|e| anyhow!("calculate_node_free_movable_pages failed: {}", e))?;
+
+        let mut nodes = Vec::new();
+        for (node, free_movable_pages) in &node_free_movable_pages {
+            if !config.node_selected(*node) {
+                continue;
+            }
+
+            if !forced {
+                let memfree_kb = match proc::get_node_memfree_kb(&self.host, *node) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("get_node_memfree_kb {} failed: {}", node, e);
+                        continue;
+                    }
+                };
+                let mut core = self.core.blocking_write();
+                if !core.check_node_compact_threshold(*node, memfree_kb, *free_movable_pages) {
+                    core.nodes
+                        .entry(*node)
+                        .or_default()
+                        .record_skip(Outcome::ThresholdSkipped);
+                    continue;
+                }
+            }
+
+            nodes.push(*node);
+        }
+
+        if nodes.is_empty() {
+            trace!("no node has enough free movable pages");
+            let mut core = self.core.blocking_write();
+            core.record_skip(Outcome::ThresholdSkipped);
+            core.force_counter += 1;
+            return Ok(Outcome::ThresholdSkipped);
+        }
+
+        if config.dry_run {
+            info!(
+                "compact dry run, nodes {:?} free movable pages {:?}",
+                nodes, node_free_movable_pages
+            );
+
+            let node_pages: BTreeMap<u32, u64> = nodes
+                .iter()
+                .map(|node| (*node, node_free_movable_pages[node]))
+                .collect();
+            let total = node_pages.values().sum();
+            return Ok(self.record_dry_run(forced, &node_pages, total).outcome);
+        }
+
+        Ok(self
+            .run_compact_nodes(
+                forced,
+                &nodes,
+                config.compact_order,
+                config.compact_sec_max,
+                config.compact_psi_percent_limit,
+            )?
+            .outcome)
+    }
+
+    // Record a run that dry_run skipped.
+    // node_free_movable_pages is empty if not per_node.
+    fn record_dry_run(
+        &self,
+        forced: bool,
+        node_free_movable_pages: &BTreeMap<u32, u64>,
+        free_movable_pages: u64,
+    ) -> LastRun {
+        let now = Utc::now();
+        let dry_run = |free_movable_pages| LastRun {
+            forced,
+            outcome: Outcome::DryRun,
+            start_time: now,
+            end_time: now,
+            free_movable_pages_before: free_movable_pages,
+            free_movable_pages_after: free_movable_pages,
+            exec_method: None,
+            uninterruptible: false,
+        };
+
+        let mut core = self.core.blocking_write();
+        for (node, pages) in node_free_movable_pages {
+            core.nodes
+                .entry(*node)
+                .or_default()
+                .record_run(dry_run(*pages));
+        }
+        core.force_counter = 0;
+        let run = dry_run(free_movable_pages);
+        core.record_run(run.clone());
+
+        run
+    }
+
+    // Run a compaction right now without checking compact_threshold and
+    // force_counter.
+    // All the nodes that are selected by the config are compacted in
+    // per_node mode.
+    // Wait the running work to finish first.
+    pub fn compact_now(&mut self, req: &CompactRequest) -> Result<LastRun> {
+        let run_lock = self.run_lock.clone();
+        let _run = run_lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        if self.pause.is_paused() {
+            return Err(anyhow!("compact is paused"));
+        }
+
+        let config = self.core.blocking_read().config.clone();
+
+        info!("compact now {:?}", req);
+
+        let compact_order = req.compact_order.unwrap_or(config.compact_order);
+        let compact_sec_max = req.compact_sec_max.unwrap_or(config.compact_sec_max);
+        let compact_psi_percent_limit = req
+            .compact_psi_percent_limit
+            .unwrap_or(config.compact_psi_percent_limit);
+
+        if config.per_node {
+            let node_pages: BTreeMap<u32, u64> = self
+                .calculate_node_free_movable_pages_order(compact_order)
+                .map_err(|e| anyhow!("calculate_node_free_movable_pages failed: {}", e))?
+                .into_iter()
+                .filter(|(node, _)| config.node_selected(*node))
+                .collect();
+            if node_pages.is_empty() {
+                return Err(anyhow!("no node is selected"));
+            }
+
+            if config.dry_run {
+                info!(
+                    "compact now dry run, nodes free movable pages {:?}",
+                    node_pages
+                );
+                let total = node_pages.values().sum();
+                return Ok(self.record_dry_run(true, &node_pages, total));
+            }
+
+            let nodes: Vec<u32> = node_pages.into_keys().collect();
+
+            return self.run_compact_nodes(
+                true,
+                &nodes,
+                compact_order,
+                compact_sec_max,
+                compact_psi_percent_limit,
+            );
+        }
+
+        if config.dry_run {
+            let free_movable_pages = self
+                .calculate_free_movable_pages_order(compact_order)
+                .map_err(|e| anyhow!("calculate_free_movable_pages failed: {}", e))?;
+            info!(
+                "compact now dry run, free movable pages {}",
+                free_movable_pages
+            );
+            return Ok(self.record_dry_run(true, &BTreeMap::new(), free_movable_pages));
+        }
+
+        self.run_compact(
+            true,
+            compact_order,
+            compact_sec_max,
+            compact_psi_percent_limit,
+        )
+    }
+
+    // Compact the nodes one by one, they share compact_sec_max and the
+    // nodes after a killed one are not compacted.
+    // The free movable pages of the returned LastRun are the sums of the
+    // nodes.
+    fn run_compact_nodes(
+        &mut self,
+        forced: bool,
+        nodes: &[u32],
+        compact_order: u8,
+        compact_sec_max: i64,
+        compact_psi_percent_limit: u8,
+    ) -> Result<LastRun> {
+        let free_movable_pages_before = self
+            .calculate_node_free_movable_pages_order(compact_order)
+            .unwrap_or_else(|e| {
+                error!("calculate_node_free_movable_pages failed: {}", e);
+                BTreeMap::new()
+            });
+        let start_time = Utc::now();
+        self.events.send(EventKind::CompactStart { forced });
+
+        let paths: Vec<PathBuf> = nodes
+            .iter()
+            .map(|node| self.host.path(node_compact_path(*node)))
+            .collect();
+        let execs = self
+            .do_compact_chunks(&paths, compact_sec_max, compact_psi_percent_limit)
+            .map_err(|e| anyhow!("do_compact_chunks failed: {}", e))?;
+        // The execs are in the order of nodes and stop at the killed one.
+        let node_runs: Vec<(u32, Exec)> =
+            nodes.iter().cloned().zip(execs.iter().cloned()).collect();
+        let (outcome, exec_method, uninterruptible) = Exec::merge(&execs);
+
+        self.events.send(EventKind::CompactStop {
+            outcome,
+            duration: (Utc::now() - start_time).to_std().unwrap_or_default(),
+        });
+
+        // Keep the prev values of the whole system up to date too.
+        self.set_prev()?;
+
+        let config_order = self.core.blocking_read().config.compact_order;
+        let prev_free_movable_pages = self
+            .calculate_node_free_movable_pages_order(config_order)
+            .map_err(|e| anyhow!("calculate_node_free_movable_pages failed: {}", e))?;
+        let free_movable_pages_after = if compact_order == config_order {
+            prev_free_movable_pages.clone()
+        } else {
+            self.calculate_node_free_movable_pages_order(compact_order)
+                .map_err(|e| anyhow!("calculate_node_free_movable_pages failed: {}", e))?
+        };
+
+        let mut node_memfree_kb = BTreeMap::new();
+        for (node, _) in &node_runs {
+            let memfree_kb = proc::get_node_memfree_kb(&self.host, *node)
+                .map_err(|e| anyhow!("get_node_memfree_kb {} failed: {}", node, e))?;
+            node_memfree_kb.insert(*node, memfree_kb);
+        }
+
+        let run = LastRun {
+            forced,
+            outcome,
+            start_time,
+            end_time: Utc::now(),
+            free_movable_pages_before: nodes
+                .iter()
+                .filter_map(|node| free_movable_pages_before.get(node))
+                .sum(),
+            free_movable_pages_after: nodes
+                .iter()
+                .filter_map(|node| free_movable_pages_after.get(node))
+                .sum(),
+            exec_method,
+            uninterruptible,
+        };
+
+        let mut core = self.core.blocking_write();
+        for (node, exec) in node_runs {
+            let status = core.nodes.entry(node).or_default();
+            status.prev_memfree_kb = node_memfree_kb[&node];
+            status.prev_free_movable_pages_after_compact =
+                prev_free_movable_pages.get(&node).cloned().unwrap_or(0);
+            status.record_run(LastRun {
+                forced,
+                outcome: exec.outcome,
+                start_time: exec.start_time,
+                end_time: exec.end_time,
+                free_movable_pages_before: free_movable_pages_before
+                    .get(&node)
+                    .cloned()
+                    .unwrap_or(0),
+                free_movable_pages_after: free_movable_pages_after.get(&node).cloned().unwrap_or(0),
+                exec_method: exec.method,
+                uninterruptible: exec.uninterruptible,
+            });
+        }
+        core.force_counter = 0;
+        core.record_run(run.clone());
+
+        Ok(run)
+    }
+
+    // The free movable pages of the returned LastRun are the pages of the
+    // orders smaller than compact_order.
+    // The nodes are compacted one by one if they all have the compact
+    // file, so that the compaction can stop between them even if the
+    // write cannot be interrupted.
+    fn run_compact(
+        &mut self,
+        forced: bool,
+        compact_order: u8,
+        compact_sec_max: i64,
+        compact_psi_percent_limit: u8,
+    ) -> Result<LastRun> {
+        let free_movable_pages_before = self
+            .calculate_free_movable_pages_order(compact_order)
+            .unwrap_or_else(|e| {
+                error!("calculate_free_movable_pages failed: {}", e);
+                0
+            });
+        let start_time = Utc::now();
+        self.events.send(EventKind::CompactStart { forced });
+
+        let mut paths = self.node_compact_paths();
+        if paths.is_empty() {
+            paths.push(self.host.path(COMPACT_MEMORY_PATH));
+        }
+        let execs = self
+            .do_compact_chunks(&paths, compact_sec_max, compact_psi_percent_limit)
+            .map_err(|e| anyhow!("do_compact_chunks failed: {}", e))?;
+        let (outcome, exec_method, uninterruptible) = Exec::merge(&execs);
+        self.events.send(EventKind::CompactStop {
+            outcome,
+            duration: (Utc::now() - start_time).to_std().unwrap_or_default(),
+        });
+
+        let prev_free_movable_pages = self.set_prev()?;
+        let free_movable_pages_after =
+            if compact_order == self.core.blocking_read().config.compact_order {
+                prev_free_movable_pages
+            } else {
+                self.calculate_free_movable_pages_order(compact_order)
+                    .map_err(|e| anyhow!("calculate_free_movable_pages failed: {}", e))?
+            };
+
+        let run = LastRun {
+            forced,
+            outcome,
+            start_time,
+            end_time: Utc::now(),
+            free_movable_pages_before,
+            free_movable_pages_after,
+            exec_method,
+            uninterruptible,
+        };
+
+        let mut core = self.core.blocking_write();
+        core.force_counter = 0;
+        core.record_run(run.clone());
+
+        Ok(run)
+    }
+
     pub async fn set_config(&mut self, new_config: OptionConfig) -> bool {
-        self.core.write().await.set_config(new_config)
+        let reset = self.core.write().await.set_config(new_config);
+        let config = self.core.read().await.config.clone();
+        if config.disabled || config.mode != Mode::Proactive {
+            if let Err(e) = self.restore_proactiveness().await {
+                error!("restore_proactiveness failed: {}", e);
+            }
+        }
+        self.events
+            .send(EventKind::ConfigChange(ConfigTarget::Compact));
+        reset
+    }
+
+    pub fn set_events(&mut self, events: Events) {
+        self.events = events;
+    }
+
+    // The pause is shared by the clones.
+    pub fn pause(&self) -> &Pause {
+        &self.pause
+    }
+
+    pub async fn get_config(&self) -> Config {
+        self.core.read().await.config.clone()
+    }
+
+    pub async fn get_status(&self) -> Status {
+        let mut status = self.core.read().await.get_status();
+        status.pause = self.pause.status();
+        status
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::misc::TestDir;
+
+    // Write the psi files of the root cgroup. psi::Period only records
+    // the first total it reads, so a bigger total written after that
+    // makes the psi exceed the limits.
+    fn write_psi(root: &Path, total: u64) {
+        let psi_path = root.join("sys/fs/cgroup");
+        std::fs::create_dir_all(&psi_path).unwrap();
+        for psi in ["memory.pressure", "io.pressure"] {
+            std::fs::write(
+                psi_path.join(psi),
+                format!("some avg10=0.00 avg60=0.00 avg300=0.00 total={}\n", total),
+            )
+            .unwrap();
+        }
+    }
 
     #[test]
     fn test_compact() {
-        let is_cg_v2 = crate::cgroup::is_cgroup_v2().unwrap();
-        let mut c = Compact::new(is_cg_v2, Config::default()).unwrap();
-        assert!(c.work().is_ok());
+        let root = setup_nodes_root("test_compact", &[1000]);
+        let mut c = Compact::new(&HostFs::new(&root), true, Config::default()).unwrap();
+        assert_eq!(c.work().unwrap(), Outcome::ThresholdSkipped);
+
+        // The psi since the last work exceeds period_psi_percent_limit.
+        thread::sleep(Duration::from_millis(10));
+        write_psi(&root, 1 << 40);
+        assert_eq!(c.work().unwrap(), Outcome::PsiSkipped);
+        let status = c.core.blocking_read().get_status();
+        assert_eq!(status.count.psi_skipped, 1);
+        assert_eq!(status.last_outcome, Some(Outcome::PsiSkipped));
+        assert!(status.last_run.is_none());
+        assert!(status.next_run_time.is_some());
+    }
+    #[test]
+    fn test_compact_pause() {
+        let root = setup_nodes_root("test_compact_pause", &[1000]);
+        let c = Compact::new(&HostFs::new(&root), true, Config::default()).unwrap();
+        assert!(c.need_work());
+
+        c.clone().pause().pause(None);
+        assert!(!c.need_work());
+        assert_eq!(c.get_remaining_tokio_duration(), TokioDuration::MAX);
+        assert!(c.clone().compact_now(&CompactRequest::default()).is_err());
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(c.get_status());
+        assert!(status.pause.paused);
+        assert!(status.pause.until.is_none());
+
+        c.pause().resume();
+        assert!(c.need_work());
+    }
+
+    #[test]
+    fn test_compact_now() {
+        let root = TestDir::new("test_compact_now");
+        std::fs::create_dir_all(root.join("proc/sys/vm")).unwrap();
+        std::fs::write(root.join("proc/meminfo"), "MemFree:         4000000 kB\n").unwrap();
+        std::fs::write(
+            root.join("proc/pagetypeinfo"),
+            "Node    0, zone   Normal, type      Movable      4      3      2      1\n",
+        )
+        .unwrap();
+        write_psi(&root, 1);
+        std::fs::write(root.join("proc/sys/vm/compact_memory"), "").unwrap();
+
+        let mut c = Compact::new(&HostFs::new(&root), true, Config::default()).unwrap();
+        let run = c
+            .compact_now(&CompactRequest {
+                compact_order: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(run.forced);
+        assert_eq!(run.outcome, Outcome::Done);
+        assert_eq!(run.free_movable_pages_before, 4 + 3 * 2);
+        assert_eq!(run.free_movable_pages_after, 4 + 3 * 2);
+        assert_eq!(run.exec_method, Some(ExecMethod::Process));
+        assert!(!run.uninterruptible);
+        assert_eq!(
+            std::fs::read_to_string(root.join("proc/sys/vm/compact_memory")).unwrap(),
+            "1\n"
+        );
+
+        // The prev values use compact_order of the config.
+        let status = c.core.blocking_read().get_status();
+        assert_eq!(status.count.forced, 1);
+        assert_eq!(status.count.done, 1);
+        assert_eq!(
+            status.prev_free_movable_pages_after_compact,
+            4 + 3 * 2 + 2 * 4 + 8
+        );
+    }
+
+    fn setup_nodes_root(name: &str, node_memfree_kb: &[u64]) -> TestDir {
+        let root = TestDir::new(name);
+        std::fs::create_dir_all(root.join("proc/sys/vm")).unwrap();
+        std::fs::write(root.join("proc/meminfo"), "MemFree:         4000000 kB\n").unwrap();
+        let mut pagetypeinfo = String::new();
+        for (node, memfree_kb) in node_memfree_kb.iter().enumerate() {
+            pagetypeinfo += &format!(
+                "Node    {}, zone   Normal, type      Movable      {}      3      2      1\n",
+                node,
+                4 * (node + 1)
+            );
+            let node_path = root.join(format!("sys/devices/system/node/node{}", node));
+            std::fs::create_dir_all(&node_path).unwrap();
+            std::fs::write(
+                node_path.join("meminfo"),
+                format!(
+                    "Node {} MemTotal:       8000000 kB\nNode {} MemFree:        {} kB\n",
+                    node, node, memfree_kb
+                ),
+            )
+            .unwrap();
+        }
+        std::fs::write(root.join("proc/pagetypeinfo"), pagetypeinfo).unwrap();
+        std::fs::write(root.join("proc/sys/vm/compact_memory"), "").unwrap();
+        write_psi(&root, 1);
+        root
+    }
+
+    #[test]
+    fn test_compact_node_free_movable_pages() {
+        let root = setup_nodes_root("test_compact_node_free_movable_pages", &[1000, 2000]);
+
+        let c = Compact::new(&HostFs::new(&root), true, Config::default()).unwrap();
+        let nodes = c.calculate_node_free_movable_pages_order(2).unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[&0], 4 + 3 * 2);
+        assert_eq!(nodes[&1], 8 + 3 * 2);
+        assert_eq!(
+            c.calculate_free_movable_pages_order(2).unwrap(),
+            4 + 8 + 3 * 2 * 2
+        );
+        assert_eq!(proc::get_node_memfree_kb(&c.host, 1).unwrap(), 2000);
+        assert!(proc::get_node_memfree_kb(&c.host, 2).is_err());
+    }
+
+    #[test]
+    fn test_compact_now_per_node() {
+        let root = setup_nodes_root("test_compact_now_per_node", &[1000, 2000, 3000]);
+        let compact_path =
+            |node| root.join(format!("sys/devices/system/node/node{}/compact", node));
+        for node in [0, 2] {
+            std::fs::write(compact_path(node), "").unwrap();
+        }
+
+        let config = Config {
+            compact_threshold: 1,
+            per_node: true,
+            nodes_exclude: vec![1],
+            ..Default::default()
+        };
+        let mut c = Compact::new(&HostFs::new(&root), true, config).unwrap();
+        let run = c
+            .compact_now(&CompactRequest {
+                compact_order: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(run.forced);
+        assert_eq!(run.outcome, Outcome::Done);
+        assert_eq!(run.free_movable_pages_before, 4 + 12 + 3 * 2 * 2);
+
+        assert_eq!(std::fs::read_to_string(compact_path(0)).unwrap(), "1\n");
+        assert!(!compact_path(1).exists());
+        assert_eq!(std::fs::read_to_string(compact_path(2)).unwrap(), "1\n");
+        assert_eq!(
+            std::fs::read_to_string(root.join("proc/sys/vm/compact_memory")).unwrap(),
+            ""
+        );
+
+        let status = c.core.blocking_read().get_status();
+        assert_eq!(status.count.done, 1);
+        assert_eq!(status.nodes.keys().cloned().collect::<Vec<_>>(), vec![0, 2]);
+        let node = &status.nodes[&2];
+        assert_eq!(node.count.done, 1);
+        assert_eq!(node.prev_memfree_kb, 3000);
+        assert_eq!(
+            node.prev_free_movable_pages_after_compact,
+            12 + 3 * 2 + 2 * 4 + 8
+        );
+        assert_eq!(
+            node.last_run.as_ref().unwrap().free_movable_pages_before,
+            12 + 3 * 2
+        );
+
+        // The threshold is checked against the prev values of each node.
+        let core = c.core.blocking_read();
+        assert!(!core.check_node_compact_threshold(2, 3000, 12 + 3 * 2 + 2 * 4 + 8 + 1));
+        assert!(core.check_node_compact_threshold(2, 3000, 12 + 3 * 2 + 2 * 4 + 8 + 2));
+        assert!(core.check_node_compact_threshold(2, 1000, 0));
+        assert!(core.check_node_compact_threshold(1, 3000, 2));
+        drop(core);
+
+        assert_eq!(c.work().unwrap(), Outcome::ThresholdSkipped);
+        assert_eq!(c.core.blocking_read().force_counter, 1);
+        thread::sleep(Duration::from_millis(10));
+        write_psi(&root, 1 << 40);
+        assert_eq!(c.work().unwrap(), Outcome::PsiSkipped);
+        assert_eq!(c.core.blocking_read().force_counter, 2);
+    }
+
+    #[test]
+    fn test_compact_now_dry_run() {
+        let root = setup_nodes_root("test_compact_now_dry_run", &[1000, 2000]);
+        let compact_path =
+            |node| root.join(format!("sys/devices/system/node/node{}/compact", node));
+
+        let config = Config {
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut c = Compact::new(&HostFs::new(&root), true, config).unwrap();
+        let req = CompactRequest {
+            compact_order: Some(2),
+            ..Default::default()
+        };
+        let run = c.compact_now(&req).unwrap();
+        assert!(run.forced);
+        assert_eq!(run.outcome, Outcome::DryRun);
+        assert_eq!(run.free_movable_pages_before, 4 + 8 + 3 * 2 * 2);
+        assert_eq!(run.exec_method, None);
+        assert_eq!(
+            std::fs::read_to_string(root.join("proc/sys/vm/compact_memory")).unwrap(),
+            ""
+        );
+        assert_eq!(c.core.blocking_read().get_status().count.dry_run, 1);
+
+        let opt = OptionConfig {
+            per_node: Some(true),
+            nodes_exclude: Some(vec![0]),
+            ..Default::default()
+        };
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(c.set_config(opt));
+        let run = c.compact_now(&req).unwrap();
+        assert_eq!(run.outcome, Outcome::DryRun);
+        assert_eq!(run.free_movable_pages_before, 8 + 3 * 2);
+        assert!(!compact_path(0).exists());
+        assert!(!compact_path(1).exists());
+        let status = c.core.blocking_read().get_status();
+        assert_eq!(status.count.dry_run, 2);
+        assert_eq!(status.nodes.keys().cloned().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_compact_helper() {
+        let root = TestDir::new("test_compact_helper");
+
+        let wait = |helper: &mut CompactHelper| -> Result<()> {
+            let deadline = std::time::Instant::now() + Duration::from_secs(10);
+            while !helper.try_wait()? {
+                assert!(
+                    std::time::Instant::now() < deadline,
+                    "compact helper didn't stop in 10 seconds"
+                );
+                thread::sleep(Duration::from_millis(10));
+            }
+            Ok(())
+        };
+
+        let path = root.join("compact_memory");
+        std::fs::write(&path, "").unwrap();
+        let mut helper = CompactHelper::spawn(&path).unwrap();
+        assert_eq!(helper.method(), ExecMethod::Process);
+        wait(&mut helper).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n");
+
+        // The write of the helper fails, the file is not created.
+        let mut helper = CompactHelper::spawn(&root.join("compact_none")).unwrap();
+        assert!(wait(&mut helper).is_err());
+        assert!(!root.join("compact_none").exists());
+
+        // Others reaped the helper.
+        let mut helper = CompactHelper::spawn(&path).unwrap();
+        if let CompactHelper::Process(Some(pid)) = helper {
+            waitpid(pid, None).unwrap();
+        }
+        assert!(helper.try_wait().unwrap());
+
+        // The helper blocks on opening a fifo that has no reader and is
+        // stopped by kill.
+        let fifo = root.join("fifo");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+        let mut helper = CompactHelper::spawn(&fifo).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!helper.try_wait().unwrap());
+        assert!(helper.kill().unwrap());
+        wait(&mut helper).unwrap();
+
+        let path = root.join("compact");
+        std::fs::write(&path, "").unwrap();
+        let mut helper = CompactHelper::spawn_thread(path.clone());
+        assert_eq!(helper.method(), ExecMethod::Thread);
+        assert!(!helper.kill().unwrap());
+        wait(&mut helper).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n");
+
+        let mut helper = CompactHelper::spawn_thread(root.join("compact_none"));
+        assert!(wait(&mut helper).is_err());
+        assert!(!root.join("compact_none").exists());
+    }
+
+    #[test]
+    fn test_compact_now_node_chunks() {
+        let root = setup_nodes_root("test_compact_now_node_chunks", &[1000, 2000]);
+        let compact_path =
+            |node| root.join(format!("sys/devices/system/node/node{}/compact", node));
+        for node in 0..2 {
+            std::fs::write(compact_path(node), "").unwrap();
+        }
+
+        // The nodes are compacted one by one instead of compact_memory.
+        let mut c = Compact::new(&HostFs::new(&root), true, Config::default()).unwrap();
+        let run = c.compact_now(&CompactRequest::default()).unwrap();
+        assert_eq!(run.outcome, Outcome::Done);
+        assert_eq!(run.exec_method, Some(ExecMethod::Process));
+        for node in 0..2 {
+            assert_eq!(std::fs::read_to_string(compact_path(node)).unwrap(), "1\n");
+        }
+        assert_eq!(
+            std::fs::read_to_string(root.join("proc/sys/vm/compact_memory")).unwrap(),
+            ""
+        );
+        // The chunks are not the per node compaction.
+        assert!(c.core.blocking_read().get_status().nodes.is_empty());
+
+        // compact_memory is used if a node doesn't have the compact file.
+        std::fs::remove_file(compact_path(1)).unwrap();
+        assert!(c.node_compact_paths().is_empty());
+        c.compact_now(&CompactRequest::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("proc/sys/vm/compact_memory")).unwrap(),
+            "1\n"
+        );
+    }
+
+    #[test]
+    fn test_next_proactiveness() {
+        let config = Config {
+            compact_threshold: 100,
+            proactiveness_min: 10,
+            proactiveness_max: 40,
+            proactiveness_step: 20,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            next_proactiveness(&config, 20, 0, true, 101),
+            (Outcome::ProactiveRaised, 40)
+        );
+        assert_eq!(
+            next_proactiveness(&config, 40, 0, true, 101),
+            (Outcome::ProactiveRaised, 40)
+        );
+        assert_eq!(
+            next_proactiveness(&config, 20, 0, true, 100),
+            (Outcome::ProactiveLowered, 10)
+        );
+        // The original value is out of the range.
+        assert_eq!(
+            next_proactiveness(&config, 0, 0, true, 101),
+            (Outcome::ProactiveRaised, 30)
+        );
+        assert_eq!(
+            next_proactiveness(&config, 30, 0, false, 101),
+            (Outcome::PsiSkipped, 30)
+        );
+        assert_eq!(
+            next_proactiveness(&config, 30, 6, true, 101),
+            (Outcome::ProactivePsiLowered, 10)
+        );
+    }
+
+    #[test]
+    fn test_compact_proactive() {
+        let root = setup_nodes_root("test_compact_proactive", &[1000]);
+        let proactiveness_path = root.join("proc/sys/vm/compaction_proactiveness");
+        std::fs::write(&proactiveness_path, "20\n").unwrap();
+
+        let config = Config {
+            mode: Mode::Proactive,
+            proactiveness_min: 30,
+            ..Default::default()
+        };
+        let mut c = Compact::new(&HostFs::new(&root), true, config).unwrap();
+
+        // Not enough free movable pages, the value is only moved into the
+        // range.
+        assert_eq!(c.work().unwrap(), Outcome::ProactiveLowered);
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "30\n"
+        );
+        let status = c.core.blocking_read().get_status();
+        assert_eq!(status.proactiveness, Some(30));
+        assert_eq!(status.proactiveness_original, Some(20));
+        assert_eq!(status.count.proactive_lowered, 1);
+        assert!(status.last_run.is_none());
+
+        c.core.blocking_write().config.compact_threshold = 0;
+        assert_eq!(c.work().unwrap(), Outcome::ProactiveRaised);
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "40\n"
+        );
+
+        // The psi exceeds compact_psi_percent_limit.
+        thread::sleep(Duration::from_millis(10));
+        write_psi(&root, 1 << 40);
+        assert_eq!(c.work().unwrap(), Outcome::ProactivePsiLowered);
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "30\n"
+        );
+        let status = c.core.blocking_read().get_status();
+        assert_eq!(status.count.proactive_raised, 1);
+        assert_eq!(status.count.proactive_psi_lowered, 1);
+        assert_eq!(status.count.psi_skipped, 0);
+        // A smaller total makes the psi ok again.
+        write_psi(&root, 1);
+        c.core.blocking_write().config.compact_threshold = Config::default().compact_threshold;
+
+        // The original value is restored when proactive mode stops.
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(c.set_config(OptionConfig {
+            mode: Some(Mode::Trigger),
+            ..Default::default()
+        }));
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "20\n"
+        );
+        let status = rt.block_on(c.get_status());
+        assert!(status.proactiveness.is_none());
+        assert!(status.proactiveness_original.is_none());
+
+        rt.block_on(c.set_config(OptionConfig {
+            mode: Some(Mode::Proactive),
+            ..Default::default()
+        }));
+        assert_eq!(c.work().unwrap(), Outcome::ProactiveLowered);
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "30\n"
+        );
+        rt.block_on(c.restore_proactiveness()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "20\n"
+        );
+
+        // A work that runs into a pause doesn't change it.
+        c.pause().pause(None);
+        assert_eq!(c.work().unwrap(), Outcome::ProactivePaused);
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "20\n"
+        );
+        let status = rt.block_on(c.get_status());
+        assert!(status.proactiveness.is_none());
+        assert_eq!(status.count.proactive_paused, 1);
+        assert_eq!(status.count.pause_killed, 0);
+        c.pause().resume();
+
+        // Dry run doesn't change it.
+        c.core.blocking_write().config.dry_run = true;
+        assert_eq!(c.work().unwrap(), Outcome::DryRun);
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "20\n"
+        );
+        rt.block_on(c.restore_proactiveness()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&proactiveness_path).unwrap(),
+            "20\n"
+        );
+    }
+
+    #[test]
+    fn test_mode_from_str() {
+        assert_eq!("trigger".parse::<Mode>().unwrap(), Mode::Trigger);
+        assert_eq!("proactive".parse::<Mode>().unwrap(), Mode::Proactive);
+        assert!("x".parse::<Mode>().is_err());
+        assert_eq!(Mode::Proactive.to_string(), "proactive");
     }
 }
@@ -0,0 +1,106 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+// Events is the feed of the reclaim and compaction activity.
+// The events are dropped if no one subscribes them.
+
+use crate::compact;
+use crate::memcg::EvictionStopReason;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+// The subscriber that falls behind more than EVENTS_CAPACITY events
+// will lose the oldest events.
+const EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigTarget {
+    Memcg,
+    Compact,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    Aging {
+        path: String,
+        numa_id: u32,
+    },
+    EvictionStop {
+        path: String,
+        numa_id: u32,
+        reason: EvictionStopReason,
+        anon_pages: u64,
+        file_pages: u64,
+    },
+    CompactStart {
+        forced: bool,
+    },
+    CompactStop {
+        outcome: compact::Outcome,
+        duration: Duration,
+    },
+    ConfigChange(ConfigTarget),
+    CgroupAdd {
+        path: String,
+        id: usize,
+        ino: usize,
+    },
+    CgroupRemove {
+        path: String,
+        id: usize,
+        ino: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub time: DateTime<Utc>,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct Events {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl Events {
+    pub fn send(&self, kind: EventKind) {
+        // Err means there is no subscriber.
+        let _ = self.tx.send(Event {
+            time: Utc::now(),
+            kind,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let events = Events::default();
+        events.send(EventKind::CompactStart { forced: true });
+
+        let mut rx = events.subscribe();
+        let cloned = events.clone();
+        cloned.send(EventKind::ConfigChange(ConfigTarget::Memcg));
+
+        let ev = rx.try_recv().unwrap();
+        assert_eq!(ev.kind, EventKind::ConfigChange(ConfigTarget::Memcg));
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+// MemoryHigh sets memory.high of a cgroup v2 memcg to its working set
+// plus headroom.
+// The working set is the pages of the generations that are born inside
+// a window, they are the pages that were touched inside the window.
+
+use crate::mglru::MGenLRU;
+use crate::psi;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MEMORY_HIGH: &str = "memory.high";
+const MEMORY_HIGH_MAX: &str = "max";
+
+// Get the pages of the generations that are born inside window_secs.
+// The youngest generation is always counted.
+pub fn working_set_pages(hmg: &HashMap<usize, MGenLRU>, window_secs: u64) -> u64 {
+    let start = Utc::now() - Duration::seconds(window_secs as i64);
+
+    hmg.values()
+        .flat_map(|mglru| {
+            mglru
+                .lru
+                .iter()
+                .filter(move |g| g.seq == mglru.max_seq || g.birth >= start)
+        })
+        .map(|g| g.anon + g.file)
+        .sum()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    None,
+    Set(u64),
+    Rollback,
+    // dry_run computed the value but didn't set it.
+    DryRun(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryHigh {
+    dir: PathBuf,
+    pub ino: usize,
+    // the value of memory.high before mem-agent set it
+    orig: String,
+    // the value that mem-agent set
+    pub value: Option<u64>,
+    // the value that mem-agent would set if not dry_run
+    pub dry_run_value: Option<u64>,
+    psi: psi::Period,
+}
+
+impl MemoryHigh {
+    // dir is the directory of the memcg.
+    pub fn new(dir: &Path, ino: usize) -> Result<Self> {
+        let orig = read_memory_high(dir)?;
+
+        let mut psi = psi::Period::new(&dir.to_path_buf(), true);
+        // Init the psi total.
+        psi.get_percent()
+            .map_err(|e| anyhow!("psi.get_percent failed: {}", e))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            ino,
+            orig,
+            value: None,
+            dry_run_value: None,
+            psi,
+        })
+    }
+
+    fn orig_bytes(&self) -> Option<u64> {
+        if self.orig == MEMORY_HIGH_MAX {
+            None
+        } else {
+            self.orig.parse::<u64>().ok()
+        }
+    }
+
+    // Set memory.high to working set pages plus headroom_percent.
+    // Restore the original value if psi exceeds psi_percent_limit.
+    // dry_run only records the value in dry_run_value.
+    pub fn update(
+        &mut self,
+        working_set_pages: u64,
+        headroom_percent: u8,
+        psi_percent_limit: u8,
+        dry_run: bool,
+    ) -> Result<Action> {
+        let percent = self
+            .psi
+            .get_percent()
+            .map_err(|e| anyhow!("psi.get_percent failed: {}", e))?;
+        if percent > psi_percent_limit as u64 {
+            self.dry_run_value = None;
+            if self.value.is_some() {
+                self.restore()?;
+                return Ok(Action::Rollback);
+            }
+            return Ok(Action::None);
+        }
+
+        if working_set_pages == 0 {
+            return Ok(Action::None);
+        }
+
+        let mut target =
+            working_set_pages * page_size::get() as u64 * (100 + headroom_percent as u64) / 100;
+        if let Some(orig) = self.orig_bytes() {
+            if target > orig {
+                target = orig;
+            }
+        }
+
+        if dry_run {
+            // Restore the value that was set before dry_run was enabled.
+            self.restore()?;
+            if self.dry_run_value == Some(target) {
+                return Ok(Action::None);
+            }
+            self.dry_run_value = Some(target);
+            return Ok(Action::DryRun(target));
+        }
+        self.dry_run_value = None;
+
+        if self.value == Some(target) {
+            return Ok(Action::None);
+        }
+
+        write_memory_high(&self.dir, &target.to_string())?;
+        self.value = Some(target);
+
+        Ok(Action::Set(target))
+    }
+
+    pub fn restore(&mut self) -> Result<()> {
+        if self.value.is_some() {
+            write_memory_high(&self.dir, &self.orig)?;
+            self.value = None;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_memory_high(dir: &Path) -> Result<String> {
+    let path = dir.join(MEMORY_HIGH);
+    let s = fs::read_to_string(&path).map_err(|e| anyhow!("read {:?} failed: {}", path, e))?;
+    Ok(s.trim().to_string())
+}
+
+fn write_memory_high(dir: &Path, value: &str) -> Result<()> {
+    let path = dir.join(MEMORY_HIGH);
+    fs::write(&path, value).map_err(|e| anyhow!("write {:?} {} failed: {}", path, value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mglru::GenLRU;
+    use crate::misc::TestDir;
+
+    fn write_psi(dir: &Path, total: u64) {
+        let data = format!(
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total={}\n\
+             full avg10=0.00 avg60=0.00 avg300=0.00 total={}\n",
+            total, total
+        );
+        fs::write(dir.join("memory.pressure"), &data).unwrap();
+        fs::write(dir.join("io.pressure"), &data).unwrap();
+    }
+
+    #[test]
+    fn test_working_set_pages() {
+        let now = Utc::now();
+        let gen = |seq, anon, file, secs| GenLRU {
+            seq,
+            anon,
+            file,
+            birth: now - Duration::seconds(secs),
+        };
+        let mut hmg = HashMap::new();
+        hmg.insert(
+            0,
+            MGenLRU {
+                min_seq: 0,
+                max_seq: 3,
+                last_birth: now,
+                min_lru_index: 0,
+                lru: vec![
+                    gen(0, 100, 100, 400),
+                    gen(1, 10, 10, 300),
+                    gen(2, 1, 2, 100),
+                    gen(3, 3, 4, 10),
+                ],
+            },
+        );
+
+        assert_eq!(working_set_pages(&hmg, 200), 10);
+        assert_eq!(working_set_pages(&hmg, 0), 7);
+        assert_eq!(working_set_pages(&hmg, 1000), 230);
+    }
+
+    #[test]
+    fn test_memory_high() {
+        let dir = TestDir::new("test_memory_high");
+        fs::write(dir.join(MEMORY_HIGH), "max\n").unwrap();
+        write_psi(&dir, 1);
+
+        let mut mh = MemoryHigh::new(&dir, 1).unwrap();
+        let bytes = 100 * page_size::get() as u64;
+        assert_eq!(
+            mh.update(100, 20, 5, false).unwrap(),
+            Action::Set(bytes * 120 / 100)
+        );
+        assert_eq!(
+            read_memory_high(&dir).unwrap(),
+            (bytes * 120 / 100).to_string()
+        );
+        assert_eq!(mh.update(100, 20, 5, false).unwrap(), Action::None);
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        write_psi(&dir, 1_000_000_000);
+        assert_eq!(mh.update(100, 20, 5, false).unwrap(), Action::Rollback);
+        assert_eq!(read_memory_high(&dir).unwrap(), MEMORY_HIGH_MAX);
+
+        // The original value limits the target.
+        fs::write(dir.join(MEMORY_HIGH), bytes.to_string()).unwrap();
+        let mut mh = MemoryHigh::new(&dir, 1).unwrap();
+        assert_eq!(mh.update(1000, 20, 5, false).unwrap(), Action::Set(bytes));
+        mh.restore().unwrap();
+        assert_eq!(read_memory_high(&dir).unwrap(), bytes.to_string());
+    }
+
+    #[test]
+    fn test_memory_high_dry_run() {
+        let dir = TestDir::new("test_memory_high_dry_run");
+        fs::write(dir.join(MEMORY_HIGH), "max\n").unwrap();
+        write_psi(&dir, 1);
+
+        let mut mh = MemoryHigh::new(&dir, 1).unwrap();
+        let bytes = 100 * page_size::get() as u64;
+        assert_eq!(
+            mh.update(100, 20, 5, false).unwrap(),
+            Action::Set(bytes * 120 / 100)
+        );
+
+        // dry_run restores the value that was set and doesn't write the
+        // new one.
+        assert_eq!(
+            mh.update(200, 20, 5, true).unwrap(),
+            Action::DryRun(bytes * 2 * 120 / 100)
+        );
+        assert_eq!(read_memory_high(&dir).unwrap(), MEMORY_HIGH_MAX);
+        assert_eq!(mh.value, None);
+        assert_eq!(mh.dry_run_value, Some(bytes * 2 * 120 / 100));
+        assert_eq!(mh.update(200, 20, 5, true).unwrap(), Action::None);
+
+        assert_eq!(
+            mh.update(200, 20, 5, false).unwrap(),
+            Action::Set(bytes * 2 * 120 / 100)
+        );
+        assert_eq!(mh.dry_run_value, None);
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::{Path, PathBuf};
+
+// HostFs is the root of the host filesystem that has the kernel interfaces
+// /sys and /proc.
+// It is "/" by default. Set it to another directory if /sys and /proc of
+// the host are mounted somewhere else, or to run with a fake tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostFs {
+    root: PathBuf,
+}
+
+impl Default for HostFs {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("/"),
+        }
+    }
+}
+
+impl HostFs {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    pub fn is_default_root(&self) -> bool {
+        self.root == Path::new("/")
+    }
+
+    // Get the path of host path inside the root.
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let p = path.as_ref();
+        self.root.join(p.strip_prefix("/").unwrap_or(p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hostfs_path() {
+        let host = HostFs::default();
+        assert!(host.is_default_root());
+        assert_eq!(host.path("/proc/meminfo"), PathBuf::from("/proc/meminfo"));
+
+        let host = HostFs::new(Path::new("/host"));
+        assert!(!host.is_default_root());
+        assert_eq!(
+            host.path("/proc/meminfo"),
+            PathBuf::from("/host/proc/meminfo")
+        );
+        assert_eq!(
+            host.path("sys/fs/cgroup/"),
+            PathBuf::from("/host/sys/fs/cgroup/")
+        );
+    }
+}
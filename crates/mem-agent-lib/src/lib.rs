@@ -5,9 +5,16 @@
 pub mod agent;
 mod cgroup;
 pub mod compact;
+pub mod event;
+mod high;
+pub mod hostfs;
 pub mod memcg;
 mod mglru;
+#[cfg(test)]
+mod mglru_sim;
 mod misc;
+pub mod pause;
 mod proc;
 mod psi;
+mod reclaim;
 mod timer;
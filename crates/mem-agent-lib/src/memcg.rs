@@ -2,26 +2,82 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::cgroup::CGROUP_PATH;
+use crate::cgroup::{self, CGROUP_PATH};
+use crate::event::{ConfigTarget, EventKind, Events};
+use crate::high::{self, MemoryHigh};
+use crate::hostfs::HostFs;
 use crate::mglru::{self, MGenLRU};
+use crate::pause::Pause;
+use crate::reclaim::ReclaimBackend;
 use crate::timer::Timeout;
 use crate::{debug, error, info, trace, warn};
 use crate::{proc, psi};
 use anyhow::{anyhow, Context, Result};
 use chrono::{DateTime, Utc};
 use nix::sched::sched_yield;
+use nix::time::{clock_gettime, ClockId};
 use page_size;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
 use std::hash::Hash;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::time::Duration as TokioDuration;
 
 /* If last_inc_time to current_time small than IDLE_FRESH_IGNORE_SECS,
 not do idle_fresh for this memcg.  */
-const IDLE_FRESH_IGNORE_SECS: i64 = 60;
+const IDLE_FRESH_IGNORE_SECS: u64 = 60;
+
+// Same as MIN_NR_GENS + 1 of Linux kernel, the eviction needs at least
+// this generations.
+const EVICTION_NR_GENS_MIN: u64 = 3;
+
+// The longest sleep of the eviction before it checks pause and config.
+const SLEEP_SLICE: Duration = Duration::from_secs(1);
+
+// The windows of the working set estimates in the status.
+pub const WORKING_SET_WINDOW_SECS: [u64; 4] = [60, 5 * 60, 10 * 60, 30 * 60];
+
+// The backend that runs eviction.
+// lru_gen is used only when it is available, otherwise memory.reclaim is
+// used for all memcgs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EvictionBackend {
+    #[default]
+    LruGen,
+    MemoryReclaim,
+}
+
+impl FromStr for EvictionBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lru_gen" => Ok(EvictionBackend::LruGen),
+            "memory_reclaim" => Ok(EvictionBackend::MemoryReclaim),
+            _ => Err(anyhow!("eviction backend {} is invalid", s)),
+        }
+    }
+}
+
+impl fmt::Display for EvictionBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvictionBackend::LruGen => write!(f, "lru_gen"),
+            EvictionBackend::MemoryReclaim => write!(f, "memory_reclaim"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct SingleOptionConfig {
@@ -32,6 +88,19 @@ pub struct SingleOptionConfig {
     pub period_psi_percent_limit: Option<u8>,
     pub eviction_psi_percent_limit: Option<u8>,
     pub eviction_run_aging_count_min: Option<u64>,
+    pub eviction_backend: Option<EvictionBackend>,
+    pub memory_high: Option<bool>,
+    pub memory_high_window_secs: Option<u64>,
+    pub memory_high_headroom_percent: Option<u8>,
+    pub memory_high_psi_percent_limit: Option<u8>,
+    pub reclaim_target_bytes: Option<u64>,
+    pub reclaim_target_percent: Option<u8>,
+    pub reclaim_rate_limit_bytes_per_sec: Option<u64>,
+    pub cold_age_secs: Option<u64>,
+    pub aging_force_scan: Option<bool>,
+    pub aging_idle_fresh_ignore_secs: Option<u64>,
+    pub eviction_nr_gens_min: Option<u64>,
+    pub dry_run: Option<bool>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -48,6 +117,8 @@ pub struct OptionConfig {
     pub add: Vec<CgroupOptionConfig>,
     pub set: Vec<CgroupOptionConfig>,
     pub default: SingleOptionConfig,
+    pub eviction_workers: Option<usize>,
+    pub eviction_workers_cpu_percent: Option<u32>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -59,6 +130,33 @@ pub struct SingleConfig {
     pub period_psi_percent_limit: u8,
     pub eviction_psi_percent_limit: u8,
     pub eviction_run_aging_count_min: u64,
+    pub eviction_backend: EvictionBackend,
+    // Set memory.high to the working set, only available on cgroup v2.
+    pub memory_high: bool,
+    // The generations born inside this window are the working set.
+    pub memory_high_window_secs: u64,
+    pub memory_high_headroom_percent: u8,
+    // Restore memory.high if psi exceeds this limit.
+    pub memory_high_psi_percent_limit: u8,
+    // Stop the eviction of a memcg after it released these bytes in a
+    // period, 0 means no limit.
+    pub reclaim_target_bytes: u64,
+    // Same as reclaim_target_bytes but in percent of memory.current.
+    // The smaller one is used if both are set.
+    pub reclaim_target_percent: u8,
+    // The max eviction speed of a memcg, 0 means no limit.
+    pub reclaim_rate_limit_bytes_per_sec: u64,
+    // Only evict the generations that are born earlier than this,
+    // 0 means no limit.
+    pub cold_age_secs: u64,
+    pub aging_force_scan: bool,
+    // Not run aging for the memcg if its youngest generation is born
+    // inside this.
+    pub aging_idle_fresh_ignore_secs: u64,
+    // Only run eviction when the memcg has at least this generations.
+    pub eviction_nr_gens_min: u64,
+    // Run aging but not eviction, record what the eviction would do.
+    pub dry_run: bool,
 }
 
 impl Default for SingleConfig {
@@ -71,13 +169,26 @@ impl Default for SingleConfig {
             period_psi_percent_limit: 1,
             eviction_psi_percent_limit: 1,
             eviction_run_aging_count_min: 3,
+            eviction_backend: EvictionBackend::LruGen,
+            memory_high: false,
+            memory_high_window_secs: 10 * 60,
+            memory_high_headroom_percent: 20,
+            memory_high_psi_percent_limit: 5,
+            reclaim_target_bytes: 0,
+            reclaim_target_percent: 0,
+            reclaim_rate_limit_bytes_per_sec: 0,
+            cold_age_secs: 0,
+            aging_force_scan: true,
+            aging_idle_fresh_ignore_secs: IDLE_FRESH_IGNORE_SECS,
+            eviction_nr_gens_min: mglru::MAX_NR_GENS,
+            dry_run: false,
         }
     }
 }
 
 impl SingleConfig {
     // return true if need reset
-    fn set(&mut self, new_config: &SingleOptionConfig) -> bool {
+    pub fn set(&mut self, new_config: &SingleOptionConfig) -> bool {
         let mut need_reset = false;
 
         if let Some(p) = new_config.period_secs {
@@ -108,6 +219,51 @@ impl SingleConfig {
         if let Some(p) = new_config.eviction_run_aging_count_min {
             self.eviction_run_aging_count_min = p;
         }
+        if let Some(b) = new_config.eviction_backend {
+            self.eviction_backend = b;
+        }
+        if let Some(h) = new_config.memory_high {
+            if h != self.memory_high {
+                self.memory_high = h;
+                need_reset = true;
+            }
+        }
+        if let Some(w) = new_config.memory_high_window_secs {
+            self.memory_high_window_secs = w;
+        }
+        if let Some(h) = new_config.memory_high_headroom_percent {
+            self.memory_high_headroom_percent = h;
+        }
+        if let Some(p) = new_config.memory_high_psi_percent_limit {
+            self.memory_high_psi_percent_limit = p;
+        }
+        if let Some(t) = new_config.reclaim_target_bytes {
+            self.reclaim_target_bytes = t;
+        }
+        if let Some(t) = new_config.reclaim_target_percent {
+            self.reclaim_target_percent = t;
+        }
+        if let Some(r) = new_config.reclaim_rate_limit_bytes_per_sec {
+            self.reclaim_rate_limit_bytes_per_sec = r;
+        }
+        if let Some(c) = new_config.cold_age_secs {
+            self.cold_age_secs = c;
+        }
+        if let Some(f) = new_config.aging_force_scan {
+            self.aging_force_scan = f;
+        }
+        if let Some(i) = new_config.aging_idle_fresh_ignore_secs {
+            self.aging_idle_fresh_ignore_secs = i;
+        }
+        if let Some(n) = new_config.eviction_nr_gens_min {
+            self.eviction_nr_gens_min = n;
+        }
+        if let Some(d) = new_config.dry_run {
+            if d != self.dry_run {
+                self.dry_run = d;
+                need_reset = true;
+            }
+        }
 
         need_reset
     }
@@ -154,6 +310,12 @@ pub struct Config {
     pub default: SingleConfig,
     // path, numa_id_list, single_config
     pub cgroups: HashMap<String, Vec<CgroupConfig>>,
+    // The count of the workers that age and evict the memcgs at the same
+    // time, 1 means one by one.
+    pub eviction_workers: usize,
+    // The CPU time that the workers can use, in percent of a CPU.
+    // 0 means no limit.
+    pub eviction_workers_cpu_percent: u32,
 }
 
 impl Default for Config {
@@ -162,6 +324,8 @@ impl Default for Config {
             psi_path: PathBuf::from(""),
             default: SingleConfig::default(),
             cgroups: HashMap::new(),
+            eviction_workers: 1,
+            eviction_workers_cpu_percent: 0,
         }
     }
 }
@@ -182,7 +346,96 @@ fn split_path_layers(input: &str) -> Vec<String> {
     paths
 }
 
+// The prefix of the regex path of CgroupConfig.
+const REGEX_PATH_PREFIX: &str = "re:";
+
+fn is_regex_path(path: &str) -> bool {
+    path.starts_with(REGEX_PATH_PREFIX)
+}
+
+fn is_glob_path(path: &str) -> bool {
+    !is_regex_path(path) && path.contains(['*', '?', '['])
+}
+
+// Convert a glob to regex.
+// "*" and "?" don't match "/", "**" matches any layers.
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::new();
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    re.push_str(".*");
+                } else {
+                    re.push_str("[^/]*");
+                }
+            }
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                re.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    re.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        re.push('\\');
+                    }
+                    re.push(c);
+                }
+                re.push(']');
+            }
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    re
+}
+
+// The glob or regex path of CgroupConfig.
+#[derive(Debug, Clone)]
+struct PathPattern {
+    path: String,
+    re: Regex,
+}
+
+impl PathPattern {
+    // Return None if path is a normal path.
+    fn new(path: &str) -> Result<Option<Self>> {
+        let re = if is_regex_path(path) {
+            &path[REGEX_PATH_PREFIX.len()..]
+        } else if is_glob_path(path) {
+            &glob_to_regex(path)
+        } else {
+            return Ok(None);
+        };
+
+        // The pattern must match the whole path.
+        let re = Regex::new(&format!("^(?:{})$", re))
+            .map_err(|e| anyhow!("path pattern {} is invalid: {}", path, e))?;
+
+        Ok(Some(Self {
+            path: path.to_string(),
+            re,
+        }))
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        self.re.is_match(path)
+    }
+}
+
 fn format_path(path: &str) -> String {
+    if is_regex_path(path) {
+        return path.to_string();
+    }
+
     let with_prefix = if path.starts_with('/') {
         path.to_string()
     } else {
@@ -228,15 +481,50 @@ impl Config {
         }
     }
 
+    // Get the path patterns of the cgroups.
+    // The more specific pattern is in front: more path layers first, then
+    // fewer wildcards, then in lexicographic order.
+    fn path_patterns(&self) -> Result<Vec<PathPattern>> {
+        let mut patterns = Vec::new();
+        for path in self.cgroups.keys() {
+            if let Some(p) = PathPattern::new(path)? {
+                patterns.push(p);
+            }
+        }
+
+        patterns.sort_by_key(|p| {
+            let layers = p.path.matches('/').count();
+            let wildcards = p.path.matches(['*', '?', '[']).count();
+            (Reverse(layers), wildcards, p.path.clone())
+        });
+
+        Ok(patterns)
+    }
+
+    // For each layer of path from the deepest to "/", the config of the
+    // same path is used first, then the configs of the matched patterns.
     fn path_to_numa_configs(
         &self,
         path: &str,
+        patterns: &[PathPattern],
         mut numa_id: HashSet<u32>,
     ) -> Vec<(Vec<u32>, SingleConfig)> {
         let mut numa_configs = Vec::new();
 
         for curr_path in split_path_layers(path) {
+            let mut layer_ccs = Vec::new();
             if let Some(ccs) = self.cgroups.get(&curr_path) {
+                layer_ccs.push(ccs);
+            }
+            for p in patterns {
+                if p.is_match(&curr_path) {
+                    if let Some(ccs) = self.cgroups.get(&p.path) {
+                        layer_ccs.push(ccs);
+                    }
+                }
+            }
+
+            for ccs in layer_ccs {
                 for cc in ccs {
                     // check subdir
                     if cc.no_subdir && curr_path != path {
@@ -282,6 +570,46 @@ pub struct EvictionCount {
     pub other_error: u64,
     pub error: u64,
     pub psi_exceeds_limit: u64,
+    pub target_reached: u64,
+    pub not_cold: u64,
+    pub paused: u64,
+    pub cancelled: u64,
+}
+
+// What the evictions that were skipped by dry_run would do.
+#[derive(Debug, Clone, Default)]
+pub struct DryRunCount {
+    // the count of the skipped evictions
+    pub count: u64,
+    // the pages of the oldest generation that would be evicted
+    pub anon_pages: u64,
+    pub file_pages: u64,
+    // the swappiness of the last skipped eviction
+    pub last_swappiness: u8,
+}
+
+// A MGLRU generation of a numa node.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub seq: u64,
+    pub anon_bytes: u64,
+    pub file_bytes: u64,
+    pub birth: DateTime<Utc>,
+}
+
+fn mglru_to_generations(mglru: &MGenLRU) -> Vec<Generation> {
+    let page_size = page_size::get() as u64;
+
+    mglru
+        .lru
+        .iter()
+        .map(|g| Generation {
+            seq: g.seq,
+            anon_bytes: g.anon * page_size,
+            file_bytes: g.file * page_size,
+            birth: g.birth,
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -291,9 +619,13 @@ pub struct Numa {
     pub last_inc_time: DateTime<Utc>,
     pub min_lru_file: u64,
     pub min_lru_anon: u64,
+    pub min_lru_birth: DateTime<Utc>,
+    // from the oldest to the youngest
+    pub generations: Vec<Generation>,
 
     pub run_aging_count: u64,
     pub eviction_count: EvictionCount,
+    pub dry_run: DryRunCount,
 
     psi: psi::Period,
     pub sleep_psi_exceeds_limit: u64,
@@ -307,6 +639,8 @@ impl Numa {
             last_inc_time: mglru.last_birth,
             min_lru_file: mglru.lru[mglru.min_lru_index].file,
             min_lru_anon: mglru.lru[mglru.min_lru_index].anon,
+            min_lru_birth: mglru.lru[mglru.min_lru_index].birth,
+            generations: mglru_to_generations(mglru),
             run_aging_count: 0,
             eviction_count: EvictionCount {
                 page: 0,
@@ -315,7 +649,12 @@ impl Numa {
                 other_error: 0,
                 error: 0,
                 psi_exceeds_limit: 0,
+                target_reached: 0,
+                not_cold: 0,
+                paused: 0,
+                cancelled: 0,
             },
+            dry_run: DryRunCount::default(),
             psi: psi::Period::new(&psi_path.join(path.trim_start_matches('/')), false),
             sleep_psi_exceeds_limit: 0,
         }
@@ -327,6 +666,21 @@ impl Numa {
         self.last_inc_time = mglru.last_birth;
         self.min_lru_file = mglru.lru[mglru.min_lru_index].file;
         self.min_lru_anon = mglru.lru[mglru.min_lru_index].anon;
+        self.min_lru_birth = mglru.lru[mglru.min_lru_index].birth;
+        self.generations = mglru_to_generations(mglru);
+    }
+
+    // Get the bytes of the generations that are born inside window_secs,
+    // it is the memory that was touched inside the window.
+    // The youngest generation is always counted.
+    pub fn working_set_bytes(&self, window_secs: u64) -> u64 {
+        let start = Utc::now() - chrono::Duration::seconds(window_secs as i64);
+
+        self.generations
+            .iter()
+            .filter(|g| g.seq == self.max_seq || g.birth >= start)
+            .map(|g| g.anon_bytes + g.file_bytes)
+            .sum()
     }
 
     fn check_psi(&mut self, limit: u64) -> Result<bool> {
@@ -346,6 +700,11 @@ pub struct MemCgroup {
     /* get from Linux kernel static inline unsigned short mem_cgroup_id(struct mem_cgroup *memcg) */
     pub id: u16,
     pub ino: usize,
+
+    // memory.high that mem-agent set
+    pub memory_high: Option<u64>,
+    // memory.high that mem-agent would set if not dry_run
+    pub dry_run_memory_high: Option<u64>,
 }
 
 impl MemCgroup {
@@ -360,6 +719,8 @@ impl MemCgroup {
         let m = Self {
             id: *id as u16,
             ino: *ino,
+            memory_high: None,
+            dry_run_memory_high: None,
             numa: numa
                 .iter()
                 .filter_map(|numa_id| {
@@ -390,6 +751,14 @@ impl MemCgroup {
         }
     }
 
+    // The working set of all the numa nodes of this memcg.
+    pub fn working_set_bytes(&self, window_secs: u64) -> u64 {
+        self.numa
+            .values()
+            .map(|n| n.working_set_bytes(window_secs))
+            .sum()
+    }
+
     fn update_from_hostmemcg(&mut self, hmg: &HashMap<usize, MGenLRU>) {
         for (numa_id, mglru) in hmg {
             self.numa
@@ -399,13 +768,18 @@ impl MemCgroup {
     }
 }
 
-#[derive(Debug, Clone)]
-enum EvictionStopReason {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionStopReason {
     None,
     NoMinLru,
     MinLruInc,
     GetError,
     PsiExceedsLimit,
+    TargetReached,
+    NotCold,
+    Paused,
+    // The config of the memcg was changed, disabled or deleted.
+    Cancelled,
 }
 
 #[derive(Debug, Clone)]
@@ -425,6 +799,115 @@ struct EvictionInfo {
     stop_reason: EvictionStopReason,
 }
 
+// What a work did to a numa node of a memcg.
+// stop_reason is None if the eviction didn't run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumaWork {
+    pub path: String,
+    pub numa_id: u32,
+    pub anon_pages: u64,
+    pub file_pages: u64,
+    pub stop_reason: Option<EvictionStopReason>,
+    // The pages are what the eviction would release, nothing was evicted.
+    pub dry_run: bool,
+}
+
+// An on-demand reclaim of a memcg.
+#[derive(Debug, Clone, Default)]
+pub struct ReclaimRequest {
+    pub path: String,
+    // Empty means all the numa nodes of the memcg.
+    pub numa_id: Vec<u32>,
+    // Stop the eviction after released these bytes, 0 means no limit.
+    pub target_bytes: u64,
+    pub swap: bool,
+    // Stop the eviction if psi exceeds this limit.
+    pub psi_percent_limit: u8,
+}
+
+// The eviction progress of a memcg in a period.
+#[derive(Debug, Clone)]
+struct PathReclaim {
+    target_bytes: u64,
+    released_bytes: u64,
+}
+
+// Get the time to wait to keep the speed of released_bytes under
+// rate_limit bytes per second.
+fn rate_limit_wait(released_bytes: u64, rate_limit: u64, elapsed: Duration) -> Duration {
+    Duration::from_secs_f64(released_bytes as f64 / rate_limit as f64).saturating_sub(elapsed)
+}
+
+// Get the time to wait to keep the used CPU time under percent of a CPU.
+fn cpu_budget_wait(used: Duration, percent: u32, elapsed: Duration) -> Duration {
+    (used * 100 / percent).saturating_sub(elapsed)
+}
+
+fn thread_cpu_time() -> Duration {
+    match clock_gettime(ClockId::CLOCK_THREAD_CPUTIME_ID) {
+        Ok(ts) => Duration::from(ts),
+        Err(e) => {
+            warn!("clock_gettime CLOCK_THREAD_CPUTIME_ID failed: {}", e);
+            Duration::ZERO
+        }
+    }
+}
+
+// The CPU time that is shared by the eviction workers.
+#[derive(Debug)]
+struct CpuBudget {
+    percent: u32,
+    start: Instant,
+    used: Mutex<Duration>,
+}
+
+impl CpuBudget {
+    fn new(percent: u32) -> Self {
+        Self {
+            percent,
+            start: Instant::now(),
+            used: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    // Add the CPU time used by a worker and return the time that it should
+    // wait.
+    fn charge(&self, d: Duration) -> Duration {
+        let mut used = self.used.lock().unwrap();
+        *used += d;
+        cpu_budget_wait(*used, self.percent, self.start.elapsed())
+    }
+}
+
+// Split the infos to the units of the eviction workers, a unit is a numa
+// node of a memcg.
+// The numa nodes of a memcg are in a unit if the config limits the reclaim
+// of the memcg, because the limit is shared by them.
+fn split_eviction_units(
+    config_infov: Vec<(SingleConfig, Vec<Info>)>,
+) -> VecDeque<(SingleConfig, Vec<Info>)> {
+    let mut units: VecDeque<(SingleConfig, Vec<Info>)> = VecDeque::new();
+
+    for (config, infov) in config_infov {
+        let by_path = config.reclaim_target_bytes != 0
+            || config.reclaim_target_percent != 0
+            || config.reclaim_rate_limit_bytes_per_sec != 0;
+        let mut path_units: HashMap<String, usize> = HashMap::new();
+        for info in infov {
+            if by_path {
+                if let Some(i) = path_units.get(&info.path) {
+                    units[*i].1.push(info);
+                    continue;
+                }
+                path_units.insert(info.path.clone(), units.len());
+            }
+            units.push_back((config.clone(), vec![info]));
+        }
+    }
+
+    units
+}
+
 #[derive(Debug, Clone)]
 struct Info {
     memcg_id: usize,
@@ -435,6 +918,7 @@ struct Info {
     last_inc_time: DateTime<Utc>,
     min_lru_file: u64,
     min_lru_anon: u64,
+    min_lru_birth: DateTime<Utc>,
 
     eviction: Option<EvictionInfo>,
 }
@@ -450,6 +934,7 @@ impl Info {
             last_inc_time: numa.last_inc_time,
             min_lru_file: numa.min_lru_file,
             min_lru_anon: numa.min_lru_anon,
+            min_lru_birth: numa.min_lru_birth,
             eviction: None,
         }
     }
@@ -460,6 +945,7 @@ impl Info {
         self.last_inc_time = numa.last_inc_time;
         self.min_lru_file = numa.min_lru_file;
         self.min_lru_anon = numa.min_lru_anon;
+        self.min_lru_birth = numa.min_lru_birth;
     }
 }
 
@@ -483,25 +969,56 @@ type ConfigMap = HashMap<u64, PeriodSecsConfigMap>;
 
 #[derive(Debug)]
 struct MemCgroups {
-    is_cg_v2: bool,
+    backend: Arc<dyn mglru::Backend>,
     config: Config,
 
     // seconds->config->path->memcgroup->numa list
     // help to do timeout check
     config_map: ConfigMap,
+    // Increased by each set_config.
+    config_generation: u64,
 
     // path->memcgroup
     cgroups: HashMap<String, MemCgroup>,
+
+    // the glob and regex paths of config.cgroups
+    patterns: Vec<PathPattern>,
+
+    // The cgroup v2 root that has the memory.high files.
+    high_root: Option<PathBuf>,
+    // path->memory.high that is set by mem-agent
+    highs: HashMap<String, MemoryHigh>,
+
+    events: Events,
+}
+
+impl Drop for MemCgroups {
+    fn drop(&mut self) {
+        self.restore_memory_highs(&HashSet::new());
+    }
 }
 
 impl MemCgroups {
-    fn new(config: Config, is_cg_v2: bool) -> Self {
-        Self {
-            is_cg_v2,
+    fn new(
+        backend: Arc<dyn mglru::Backend>,
+        high_root: Option<PathBuf>,
+        config: Config,
+    ) -> Result<Self> {
+        let patterns = config
+            .path_patterns()
+            .map_err(|e| anyhow!("config.path_patterns failed: {}", e))?;
+
+        Ok(Self {
+            backend,
             config,
             config_map: ConfigMap::new(),
+            config_generation: 0,
             cgroups: HashMap::new(),
-        }
+            patterns,
+            high_root,
+            highs: HashMap::new(),
+            events: Events::default(),
+        })
     }
 
     fn remove_changed(
@@ -541,10 +1058,24 @@ impl MemCgroups {
                 info!(
                     "Remove cgroups {} {} {} because host changed.",
                     path, cgroup.id, cgroup.ino
-                )
+                );
+                self.events.send(EventKind::CgroupRemove {
+                    path: path.clone(),
+                    id: cgroup.id as usize,
+                    ino: cgroup.ino,
+                });
             }
             should_keep
         });
+
+        // The memcg is removed, no need to restore its memory.high.
+        self.highs.retain(|path, mh| {
+            if let Some((_, ino, _)) = mg_hash.get(path) {
+                *ino == mh.ino
+            } else {
+                false
+            }
+        });
     }
 
     fn update_and_add(
@@ -577,9 +1108,11 @@ impl MemCgroups {
             if need_insert {
                 // Create new and insert
                 // Get the configs(numa may have different configs) for this memcg
-                let numa_configs = self
-                    .config
-                    .path_to_numa_configs(path, hmg.keys().cloned().map(|k| k as u32).collect());
+                let numa_configs = self.config.path_to_numa_configs(
+                    path,
+                    &self.patterns,
+                    hmg.keys().cloned().map(|k| k as u32).collect(),
+                );
 
                 for (numa_id, config) in &numa_configs {
                     loop {
@@ -637,6 +1170,11 @@ impl MemCgroups {
                                                     &self.config.psi_path,
                                                 ),
                                             );
+                                            self.events.send(EventKind::CgroupAdd {
+                                                path: path.clone(),
+                                                id: *id,
+                                                ino: *ino,
+                                            });
                                         }
                                     }
                                 }
@@ -689,6 +1227,7 @@ impl MemCgroups {
 
                                 if !pass {
                                     info!("{} period psi exceeds limit", path);
+                                    numa.sleep_psi_exceeds_limit += 1;
                                     continue;
                                 }
 
@@ -737,13 +1276,34 @@ impl MemCgroups {
         }
     }
 
-    fn inc_run_aging_count(&mut self, infov: &mut Vec<Info>) {
+    // If check_count_min is false, keep the infos that didn't reach
+    // eviction_run_aging_count_min.
+    fn inc_run_aging_count(
+        &mut self,
+        infov: &mut Vec<Info>,
+        works: &mut Vec<NumaWork>,
+        check_count_min: bool,
+    ) {
         let mut i = 0;
         while i < infov.len() {
             if let Some(mg) = self.cgroups.get_mut(&infov[i].path) {
                 if let Some(numa) = mg.numa.get_mut(&(infov[i].numa_id as u32)) {
+                    self.events.send(EventKind::Aging {
+                        path: infov[i].path.clone(),
+                        numa_id: infov[i].numa_id as u32,
+                    });
+                    works.push(NumaWork {
+                        path: infov[i].path.clone(),
+                        numa_id: infov[i].numa_id as u32,
+                        anon_pages: 0,
+                        file_pages: 0,
+                        stop_reason: None,
+                        dry_run: false,
+                    });
                     numa.run_aging_count += 1;
-                    if numa.run_aging_count >= self.config.default.eviction_run_aging_count_min {
+                    if !check_count_min
+                        || numa.run_aging_count >= self.config.default.eviction_run_aging_count_min
+                    {
                         i += 1;
                         continue;
                     }
@@ -754,7 +1314,71 @@ impl MemCgroups {
         }
     }
 
-    fn record_eviction(&mut self, infov: &Vec<Info>) {
+    // Check if the numa node of the memcg still uses config.
+    // It doesn't if the config of the memcg was changed, disabled or deleted.
+    fn config_in_use(&self, config: &SingleConfig, path: &str, numa_id: u32) -> bool {
+        self.config_map
+            .get(&config.period_secs)
+            .and_then(|secs_config_map| secs_config_map.cgs.get(config))
+            .and_then(|path_map| path_map.get(path))
+            .is_some_and(|numa_map| numa_map.numa.contains(&numa_id))
+    }
+
+    // Get the config and the infos of a reclaim request.
+    // The numa nodes of a memcg usually share a config, the config of the
+    // smallest numa id is used for all of them.
+    // Return the config of the memcg and the config changed by req.
+    fn get_reclaim_infos(
+        &self,
+        path: &str,
+        req: &ReclaimRequest,
+    ) -> Result<(SingleConfig, SingleConfig, Vec<Info>)> {
+        let mg = self
+            .cgroups
+            .get(path)
+            .ok_or(anyhow!("memcg {} not found", path))?;
+
+        let mut numa_id: Vec<u32> = if req.numa_id.is_empty() {
+            mg.numa.keys().cloned().collect()
+        } else {
+            for id in &req.numa_id {
+                if !mg.numa.contains_key(id) {
+                    return Err(anyhow!("numa {} of memcg {} not found", id, path));
+                }
+            }
+            req.numa_id.clone()
+        };
+        numa_id.sort();
+        numa_id.dedup();
+        if numa_id.is_empty() {
+            return Err(anyhow!("memcg {} doesn't have numa node", path));
+        }
+
+        let origin = self
+            .config
+            .path_to_numa_configs(path, &self.patterns, numa_id.iter().cloned().collect())
+            .into_iter()
+            .find(|(ids, _)| ids.contains(&numa_id[0]))
+            .map(|(_, c)| c)
+            .unwrap_or(self.config.default.clone());
+        let mut config = origin.clone();
+        config.swap = req.swap;
+        config.eviction_psi_percent_limit = req.psi_percent_limit;
+        config.reclaim_target_bytes = req.target_bytes;
+        config.reclaim_target_percent = 0;
+        config.reclaim_rate_limit_bytes_per_sec = 0;
+        config.cold_age_secs = 0;
+        config.aging_idle_fresh_ignore_secs = 0;
+
+        let infov = numa_id
+            .iter()
+            .map(|id| Info::new(path, mg.id as usize, *id as usize, &mg.numa[id]))
+            .collect();
+
+        Ok((origin, config, infov))
+    }
+
+    fn record_eviction(&mut self, infov: &Vec<Info>, works: &mut [NumaWork]) {
         for info in infov {
             if let Some(mg) = self.cgroups.get_mut(&(info.path)) {
                 if let Some(numa) = mg.numa.get_mut(&(info.numa_id as u32)) {
@@ -770,11 +1394,150 @@ impl MemCgroups {
                             EvictionStopReason::PsiExceedsLimit => {
                                 numa.eviction_count.psi_exceeds_limit += 1
                             }
+                            EvictionStopReason::TargetReached => {
+                                numa.eviction_count.target_reached += 1
+                            }
+                            EvictionStopReason::NotCold => numa.eviction_count.not_cold += 1,
+                            EvictionStopReason::Paused => numa.eviction_count.paused += 1,
+                            EvictionStopReason::Cancelled => numa.eviction_count.cancelled += 1,
+                        }
+                        self.events.send(EventKind::EvictionStop {
+                            path: info.path.clone(),
+                            numa_id: info.numa_id as u32,
+                            reason: ei.stop_reason,
+                            anon_pages: ei.anon_page_count,
+                            file_pages: ei.file_page_count,
+                        });
+                        if let Some(w) = works
+                            .iter_mut()
+                            .find(|w| w.path == info.path && w.numa_id == info.numa_id as u32)
+                        {
+                            w.anon_pages = ei.anon_page_count;
+                            w.file_pages = ei.file_page_count;
+                            w.stop_reason = Some(ei.stop_reason);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Get the memcgs of sec that enable memory_high and their configs.
+    fn get_memory_high_configs(&self, sec: u64) -> HashMap<String, SingleConfig> {
+        let mut ret = HashMap::new();
+
+        if let Some(sec_config_map) = self.config_map.get(&sec) {
+            for (single_config, path_map) in &sec_config_map.cgs {
+                if single_config.disabled || !single_config.memory_high {
+                    continue;
+                }
+                for path in path_map.keys() {
+                    ret.entry(path.clone())
+                        .or_insert_with(|| single_config.clone());
+                }
+            }
+        }
+
+        ret
+    }
+
+    // Get all the memcgs that enable memory_high.
+    fn memory_high_paths(&self) -> HashSet<String> {
+        let mut ret = HashSet::new();
+
+        for sec in self.config_map.keys() {
+            ret.extend(self.get_memory_high_configs(*sec).into_keys());
+        }
+
+        ret
+    }
+
+    fn update_memory_highs(
+        &mut self,
+        mg_hash: &mglru::HostMemcgs,
+        path_configs: &HashMap<String, SingleConfig>,
+    ) {
+        let root = if let Some(r) = &self.high_root {
+            r
+        } else {
+            warn!("memory_high is only available on cgroup v2");
+            return;
+        };
+
+        for (path, config) in path_configs {
+            let (ino, hmg) = if let Some((_, ino, hmg)) = mg_hash.get(path) {
+                (*ino, hmg)
+            } else {
+                continue;
+            };
+
+            let mh = match self.highs.entry(path.clone()) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    match MemoryHigh::new(&root.join(path.trim_start_matches('/')), ino) {
+                        Ok(mh) => e.insert(mh),
+                        Err(err) => {
+                            warn!("{} MemoryHigh::new failed: {}", path, err);
+                            continue;
                         }
                     }
                 }
+            };
+
+            let pages = high::working_set_pages(hmg, config.memory_high_window_secs);
+            match mh.update(
+                pages,
+                config.memory_high_headroom_percent,
+                config.memory_high_psi_percent_limit,
+                config.dry_run,
+            ) {
+                Ok(high::Action::Set(v)) => {
+                    info!(
+                        "{} set memory.high to {} because working set is {} pages",
+                        path, v, pages
+                    )
+                }
+                Ok(high::Action::Rollback) => {
+                    info!("{} restore memory.high because psi exceeds limit", path)
+                }
+                Ok(high::Action::DryRun(v)) => {
+                    info!(
+                        "{} dry run skip setting memory.high to {} because working set is {} pages",
+                        path, v, pages
+                    )
+                }
+                Ok(high::Action::None) => {}
+                Err(e) => error!("{} update memory.high failed: {}", path, e),
+            }
+        }
+    }
+
+    // Get the status of the memcgs with their memory.high.
+    fn get_status(&self) -> HashMap<String, MemCgroup> {
+        let mut status = self.cgroups.clone();
+
+        for (path, mh) in &self.highs {
+            if let Some(mcg) = status.get_mut(path) {
+                mcg.memory_high = mh.value;
+                mcg.dry_run_memory_high = mh.dry_run_value;
             }
         }
+
+        status
+    }
+
+    // Restore memory.high of the memcgs that are not in keep.
+    fn restore_memory_highs(&mut self, keep: &HashSet<String>) {
+        self.highs.retain(|path, mh| {
+            if keep.contains(path) {
+                return true;
+            }
+            match mh.restore() {
+                Ok(_) => info!("{} restore memory.high", path),
+                Err(e) => error!("{} restore memory.high failed: {}", path, e),
+            }
+            false
+        });
     }
 
     fn get_timeout_list(&self) -> Vec<u64> {
@@ -832,8 +1595,10 @@ impl MemCgroups {
     // return if MemAgentSleep need be reset
     fn set_config(&mut self, config: OptionConfig) -> Result<bool> {
         // refresh
-        let mg_hash = mglru::host_memcgs_get(&HashSet::new(), true, self.is_cg_v2)
-            .map_err(|e| anyhow!("mglru::host_memcgs_get failed: {}", e))?;
+        let mg_hash = self
+            .backend
+            .memcgs_get(&HashSet::new(), true)
+            .map_err(|e| anyhow!("backend.memcgs_get failed: {}", e))?;
         self.remove_changed(&mg_hash);
         self.update_and_add(&mg_hash, true);
 
@@ -921,15 +1686,43 @@ impl MemCgroups {
             need_reset = true;
         }
 
-        if need_reset {
-            self.config.format();
+        if let Some(workers) = config.eviction_workers {
+            self.config.eviction_workers = workers;
+        }
+        if let Some(percent) = config.eviction_workers_cpu_percent {
+            self.config.eviction_workers_cpu_percent = percent;
+        }
+
+        self.config.format();
 
-            // remove old config_map
-            self.config_map.clear();
+        self.patterns = match self.config.path_patterns() {
+            Ok(patterns) => patterns,
+            Err(e) => {
+                self.config = orig_config;
+                return Err(anyhow!("config.path_patterns failed: {}", e));
+            }
+        };
 
-            // add new config_map
-            self.update_and_add(&mg_hash, false);
+        // Rebuild config_map even if the change doesn't need reset, the
+        // running evictions check their config against it.
+        // The timers are kept if the change doesn't need reset.
+        let mut timeouts: HashMap<u64, Timeout> = self
+            .config_map
+            .drain()
+            .map(|(secs, secs_map)| (secs, secs_map.timeout))
+            .collect();
+        self.update_and_add(&mg_hash, false);
+        if !need_reset {
+            for (secs, secs_map) in self.config_map.iter_mut() {
+                if let Some(timeout) = timeouts.remove(secs) {
+                    secs_map.timeout = timeout;
+                }
+            }
         }
+        self.config_generation += 1;
+
+        let keep = self.memory_high_paths();
+        self.restore_memory_highs(&keep);
 
         info!("new memcg config: {:#?}", self.config);
         trace!("new memcg config_map: {:#?}", self.config_map);
@@ -943,8 +1736,18 @@ impl MemCgroups {
 
 #[derive(Debug, Clone)]
 pub struct MemCG {
+    host: HostFs,
     is_cg_v2: bool,
+    backend: Arc<dyn mglru::Backend>,
+    // memory.reclaim backend for the memcgs whose eviction_backend is
+    // memory_reclaim, only available on cgroup v2.
+    reclaim: Option<Arc<dyn mglru::Backend>>,
     memcgs: Arc<RwLock<MemCgroups>>,
+    pause: Pause,
+    // Only set for the eviction workers.
+    cpu_budget: Option<Arc<CpuBudget>>,
+    // work and reclaim change the same generations, run them one by one.
+    run_lock: Arc<Mutex<()>>,
 }
 
 fn div_round(a: u64, b: u64) -> u64 {
@@ -958,23 +1761,73 @@ fn div_round(a: u64, b: u64) -> u64 {
 }
 
 impl MemCG {
-    pub fn new(is_cg_v2: bool, mut config: Config) -> Result<Self> {
-        mglru::check().map_err(|e| anyhow!("mglru::check failed: {}", e))?;
+    pub fn new(host: &HostFs, is_cg_v2: bool, config: Config) -> Result<Self> {
+        let reclaim: Option<Arc<dyn mglru::Backend>> = if is_cg_v2 {
+            let r: Arc<dyn mglru::Backend> = Arc::new(ReclaimBackend::new(host));
+            match r.check() {
+                Ok(_) => Some(r),
+                Err(e) => {
+                    warn!("memory.reclaim is not available: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let lru_gen: Arc<dyn mglru::Backend> = Arc::new(mglru::KernelBackend::new(host, is_cg_v2));
+        let backend = match lru_gen.check() {
+            Ok(_) => lru_gen,
+            Err(e) => {
+                if let Some(r) = &reclaim {
+                    warn!("lru_gen is not available: {}, use memory.reclaim", e);
+                    r.clone()
+                } else {
+                    return Err(anyhow!("mglru::check failed: {}", e));
+                }
+            }
+        };
+
+        Self::new_with_backend(host, is_cg_v2, backend, reclaim, config)
+    }
+
+    pub(crate) fn new_with_backend(
+        host: &HostFs,
+        is_cg_v2: bool,
+        backend: Arc<dyn mglru::Backend>,
+        reclaim: Option<Arc<dyn mglru::Backend>>,
+        mut config: Config,
+    ) -> Result<Self> {
+        backend
+            .check()
+            .map_err(|e| anyhow!("backend.check failed: {}", e))?;
 
         if is_cg_v2 {
-            config.psi_path = PathBuf::from(CGROUP_PATH);
+            config.psi_path = host.path(CGROUP_PATH);
         }
 
         config.psi_path =
-            psi::check(&config.psi_path).map_err(|e| anyhow!("psi::check failed: {}", e))?;
+            psi::check(host, &config.psi_path).map_err(|e| anyhow!("psi::check failed: {}", e))?;
 
         config.format();
 
         info!("memcg start with config: {:#?}", config);
 
+        let high_root = if is_cg_v2 {
+            Some(host.path(CGROUP_PATH))
+        } else {
+            None
+        };
+
         let mut memcg = Self {
+            host: host.clone(),
             is_cg_v2,
-            memcgs: Arc::new(RwLock::new(MemCgroups::new(config, is_cg_v2))),
+            backend: backend.clone(),
+            reclaim,
+            memcgs: Arc::new(RwLock::new(MemCgroups::new(backend, high_root, config)?)),
+            pause: Pause::default(),
+            cpu_budget: None,
+            run_lock: Arc::new(Mutex::new(())),
         };
 
         /* Refresh memcgroups to self.memcgs.  */
@@ -985,33 +1838,106 @@ impl MemCG {
         Ok(memcg)
     }
 
-    pub fn work(&mut self, work_list: &Vec<u64>) -> Result<()> {
+    // Return the memcgs and numa nodes that were aged in this work.
+    pub fn work(&mut self, work_list: &Vec<u64>) -> Result<Vec<NumaWork>> {
+        let run_lock = self.run_lock.clone();
+        let _run = run_lock.lock().unwrap_or_else(|e| e.into_inner());
+
         /* Refresh memcgroups to self.memcgs.  */
         self.refresh(&HashSet::new())
             .map_err(|e| anyhow!("first refresh failed: {}", e))?;
 
+        let mut works = Vec::new();
         for sec in work_list {
             let sec = *sec;
 
+            if self.pause.is_paused() {
+                info!("memcg.work stop because paused");
+                break;
+            }
+
             let mut infov = self.check_psi_get_infos(sec);
 
-            self.run_aging(&mut infov);
+            let (workers, cpu_percent) = {
+                let mgs = self.memcgs.blocking_read();
+                (
+                    mgs.config.eviction_workers,
+                    mgs.config.eviction_workers_cpu_percent,
+                )
+            };
+            if workers > 1 || cpu_percent != 0 {
+                self.run_eviction_workers(infov, &mut works, workers, cpu_percent)
+                    .map_err(|e| anyhow!("run_eviction_workers second {} failed: {}", sec, e))?;
+            } else {
+                self.run_aging(&mut infov, &mut works);
+
+                self.run_eviction(&mut infov, &mut works)
+                    .map_err(|e| anyhow!("run_eviction second {} failed: {}", sec, e))?;
+            }
 
-            self.run_eviction(&mut infov)
-                .map_err(|e| anyhow!("run_eviction second {} failed: {}", sec, e))?;
+            self.run_memory_high(sec)
+                .map_err(|e| anyhow!("run_memory_high second {} failed: {}", sec, e))?;
         }
 
-        Ok(())
+        Ok(works)
     }
 
-    /*
-     * If target_paths.len == 0,
-     * will remove the updated or not exist cgroup in the host from MemCgroups.
-     * If target_paths.len > 0, will not do that.
-     */
+    // Run aging and eviction for a memcg right now.
+    // Return what the reclaim did to each numa node.
+    // Wait the running work to finish first.
+    pub fn reclaim(&mut self, req: &ReclaimRequest) -> Result<Vec<NumaWork>> {
+        let run_lock = self.run_lock.clone();
+        let _run = run_lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        if self.pause.is_paused() {
+            return Err(anyhow!("memcg is paused"));
+        }
+
+        let path = format_path(&req.path);
+
+        self.refresh(&HashSet::from([path.clone()]))
+            .map_err(|e| anyhow!("refresh failed: {}", e))?;
+
+        let (origin, config, mut infov) =
+            self.memcgs.blocking_read().get_reclaim_infos(&path, req)?;
+
+        info!("{} reclaim start {:?}", path, req);
+
+        let mut works = Vec::new();
+        if config.dry_run {
+            // Don't age either, only report what the eviction would release.
+            works = infov
+                .iter()
+                .map(|info| NumaWork {
+                    path: info.path.clone(),
+                    numa_id: info.numa_id as u32,
+                    anon_pages: 0,
+                    file_pages: 0,
+                    stop_reason: None,
+                    dry_run: false,
+                })
+                .collect();
+        } else {
+            self.run_aging_single_config(&mut infov, &config, &mut works, false);
+        }
+        self.run_eviction_single_config(&mut infov, &config, &origin, &mut works)
+            .map_err(|e| anyhow!("run_eviction_single_config failed: {}", e))?;
+
+        info!("{} reclaim stop {:?}", path, works);
+
+        Ok(works)
+    }
+
+    /*
+     * If target_paths.len == 0,
+     * will remove the updated or not exist cgroup in the host from MemCgroups.
+     * If target_paths.len > 0, will not do that.
+     */
     fn refresh(&mut self, target_paths: &HashSet<String>) -> Result<()> {
-        let mg_hash = mglru::host_memcgs_get(target_paths, true, self.is_cg_v2)
-            .map_err(|e| anyhow!("lru_gen_parse::file_parse failed: {}", e))?;
+        let mg_hash = self
+            .backend
+            .memcgs_get(target_paths, true)
+            .map_err(|e| anyhow!("backend.memcgs_get failed: {}", e))?;
 
         let mut mgs = self.memcgs.blocking_write();
 
@@ -1023,17 +1949,145 @@ impl MemCG {
         Ok(())
     }
 
-    fn run_aging(&mut self, config_infov: &mut Vec<(SingleConfig, Vec<Info>)>) {
+    // Age and evict the units of config_infov in the workers at the same
+    // time.
+    fn run_eviction_workers(
+        &mut self,
+        config_infov: Vec<(SingleConfig, Vec<Info>)>,
+        works: &mut Vec<NumaWork>,
+        workers: usize,
+        cpu_percent: u32,
+    ) -> Result<()> {
+        let units = split_eviction_units(config_infov);
+        let workers = workers.clamp(1, std::cmp::max(units.len(), 1));
+        let budget = if cpu_percent != 0 {
+            Some(Arc::new(CpuBudget::new(cpu_percent)))
+        } else {
+            None
+        };
+
+        debug!(
+            "run_eviction_workers {} units with {} workers",
+            units.len(),
+            workers
+        );
+
+        let units = Mutex::new(units);
+        let failed = AtomicBool::new(false);
+
+        let rets: Vec<thread::Result<(Vec<NumaWork>, Result<()>)>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    let mut worker = self.clone();
+                    worker.cpu_budget = budget.clone();
+                    let units = &units;
+                    let failed = &failed;
+                    s.spawn(move || {
+                        let mut works = Vec::new();
+                        let ret = loop {
+                            if worker.pause.is_paused() || failed.load(Ordering::Relaxed) {
+                                break Ok(());
+                            }
+                            let Some((config, mut infov)) = units.lock().unwrap().pop_front()
+                            else {
+                                break Ok(());
+                            };
+
+                            let mut cpu_time = thread_cpu_time();
+                            worker.run_aging_single_config(&mut infov, &config, &mut works, true);
+                            worker.charge_cpu_budget(&mut cpu_time);
+
+                            if let Err(e) = worker.run_eviction_single_config(
+                                &mut infov, &config, &config, &mut works,
+                            ) {
+                                failed.store(true, Ordering::Relaxed);
+                                break Err(e);
+                            }
+                        };
+                        (works, ret)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join()).collect()
+        });
+
+        let mut ret = Ok(());
+        for r in rets {
+            match r {
+                Ok((w, r)) => {
+                    works.extend(w);
+                    if let Err(e) = r {
+                        if ret.is_ok() {
+                            ret = Err(e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    if ret.is_ok() {
+                        ret = Err(anyhow!("eviction worker panicked: {:?}", e));
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+
+    // Charge the CPU time that this thread used since last to the budget
+    // of the eviction workers, and wait if the budget is used up.
+    fn charge_cpu_budget(&self, last: &mut Duration) {
+        if let Some(budget) = &self.cpu_budget {
+            let now = thread_cpu_time();
+            let wait = budget.charge(now.saturating_sub(*last));
+            *last = now;
+            if !wait.is_zero() {
+                trace!("eviction worker wait {:?} because of cpu budget", wait);
+                self.interruptible_sleep(wait);
+            }
+        }
+    }
+
+    // Sleep in slices and stop early if paused or the config is changed,
+    // the eviction handles them in its next step.
+    fn interruptible_sleep(&self, wait: Duration) {
+        let config_generation = self.memcgs.blocking_read().config_generation;
+        let end = Instant::now() + wait;
+        loop {
+            let now = Instant::now();
+            if now >= end {
+                break;
+            }
+            thread::sleep(std::cmp::min(end - now, SLEEP_SLICE));
+            if self.pause.is_paused()
+                || self.memcgs.blocking_read().config_generation != config_generation
+            {
+                break;
+            }
+        }
+    }
+
+    fn run_aging(
+        &mut self,
+        config_infov: &mut Vec<(SingleConfig, Vec<Info>)>,
+        works: &mut Vec<NumaWork>,
+    ) {
         for (config, infov) in config_infov.iter_mut() {
             debug!("run_aging_single_config {:?}", config);
-            self.run_aging_single_config(infov, config.swap);
+            self.run_aging_single_config(infov, config, works, true);
         }
     }
 
-    fn run_aging_single_config(&mut self, infov: &mut Vec<Info>, swap: bool) {
+    fn run_aging_single_config(
+        &mut self,
+        infov: &mut Vec<Info>,
+        config: &SingleConfig,
+        works: &mut Vec<NumaWork>,
+        check_count_min: bool,
+    ) {
         infov.retain(|info| {
             let now = Utc::now();
-            if now.signed_duration_since(info.last_inc_time).num_seconds() < IDLE_FRESH_IGNORE_SECS
+            if now.signed_duration_since(info.last_inc_time).num_seconds()
+                < config.aging_idle_fresh_ignore_secs as i64
             {
                 info!(
                     "{} not run aging because last_inc_time {}",
@@ -1041,11 +2095,16 @@ impl MemCG {
                 );
                 false
             } else {
-                let res = if let Err(e) =
-                    mglru::run_aging(info.memcg_id, info.numa_id, info.max_seq, swap, true)
-                {
+                let res = if let Err(e) = self.backend.run_aging(
+                    &info.path,
+                    info.memcg_id,
+                    info.numa_id,
+                    info.max_seq,
+                    config.swap,
+                    config.aging_force_scan,
+                ) {
                     error!(
-                        "mglru::run_aging {} {} {} failed: {}",
+                        "backend.run_aging {} {} {} failed: {}",
                         info.path, info.memcg_id, info.numa_id, e
                     );
                     false
@@ -1061,11 +2120,32 @@ impl MemCG {
             }
         });
 
-        self.memcgs.blocking_write().inc_run_aging_count(infov);
+        self.memcgs
+            .blocking_write()
+            .inc_run_aging_count(infov, works, check_count_min);
+    }
+
+    fn run_memory_high(&mut self, sec: u64) -> Result<()> {
+        let path_configs = self.memcgs.blocking_read().get_memory_high_configs(sec);
+        if path_configs.is_empty() {
+            return Ok(());
+        }
+
+        let paths: HashSet<String> = path_configs.keys().cloned().collect();
+        let mg_hash = self
+            .backend
+            .memcgs_get(&paths, true)
+            .map_err(|e| anyhow!("backend.memcgs_get failed: {}", e))?;
+
+        self.memcgs
+            .blocking_write()
+            .update_memory_highs(&mg_hash, &path_configs);
+
+        Ok(())
     }
 
     fn swap_not_available(&self) -> Result<bool> {
-        let freeswap_kb = proc::get_freeswap_kb().context("proc::get_freeswap_kb")?;
+        let freeswap_kb = proc::get_freeswap_kb(&self.host).context("proc::get_freeswap_kb")?;
 
         if freeswap_kb > (256 * page_size::get() as u64 / 1024) {
             Ok(false)
@@ -1085,19 +2165,58 @@ impl MemCG {
         c as u8
     }
 
-    fn run_eviction(&mut self, config_infov: &mut Vec<(SingleConfig, Vec<Info>)>) -> Result<()> {
+    fn run_eviction(
+        &mut self,
+        config_infov: &mut Vec<(SingleConfig, Vec<Info>)>,
+        works: &mut [NumaWork],
+    ) -> Result<()> {
         for (config, infov) in config_infov.iter_mut() {
             debug!("run_eviction_single_config {:?}", config);
-            self.run_eviction_single_config(infov, &config)?;
+            self.run_eviction_single_config(infov, config, config, works)?;
         }
 
         Ok(())
     }
 
+    fn get_evictor(&self, config: &SingleConfig) -> Arc<dyn mglru::Backend> {
+        if config.eviction_backend == EvictionBackend::MemoryReclaim {
+            if let Some(r) = &self.reclaim {
+                return r.clone();
+            }
+            warn!("memory.reclaim is not available, use lru_gen to run eviction");
+        }
+
+        self.backend.clone()
+    }
+
+    // Get the bytes that path can release in a period, 0 means no limit.
+    fn get_reclaim_target(&self, path: &str, config: &SingleConfig) -> u64 {
+        let mut target = config.reclaim_target_bytes;
+
+        if config.reclaim_target_percent != 0 {
+            match cgroup::memory_current(&self.host, self.is_cg_v2, path) {
+                Ok(current) => {
+                    // Release at least one byte to not make it no limit.
+                    let t = std::cmp::max(current * config.reclaim_target_percent as u64 / 100, 1);
+                    if target == 0 || t < target {
+                        target = t;
+                    }
+                }
+                Err(e) => warn!("{} get memory current failed: {}", path, e),
+            }
+        }
+
+        target
+    }
+
+    // origin is the config of the infos in config_map, the eviction of
+    // an info is cancelled if its memcg doesn't use origin anymore.
     fn run_eviction_single_config(
         &mut self,
         infov: &mut Vec<Info>,
         config: &SingleConfig,
+        origin: &SingleConfig,
+        works: &mut [NumaWork],
     ) -> Result<()> {
         let mut swap = config.swap;
 
@@ -1109,6 +2228,16 @@ impl MemCG {
             swap = false;
         }
 
+        if config.dry_run {
+            return self.run_dry_eviction(infov, config, swap, works);
+        }
+
+        let evictor = self.get_evictor(config);
+
+        let nr_gens_min = config
+            .eviction_nr_gens_min
+            .clamp(EVICTION_NR_GENS_MIN, mglru::MAX_NR_GENS);
+
         let psi_path = self.memcgs.blocking_read().config.psi_path.clone();
         for info in infov.into_iter() {
             info.eviction = Some(EvictionInfo {
@@ -1122,11 +2251,77 @@ impl MemCG {
             });
         }
 
+        let page_size = page_size::get() as u64;
+        let start = Instant::now();
+        let mut path_reclaims: HashMap<String, PathReclaim> = HashMap::new();
+        for info in infov.iter() {
+            if !path_reclaims.contains_key(&info.path) {
+                let target_bytes = self.get_reclaim_target(&info.path, config);
+                path_reclaims.insert(
+                    info.path.clone(),
+                    PathReclaim {
+                        target_bytes,
+                        released_bytes: 0,
+                    },
+                );
+            }
+        }
+
         let mut removed_infov = Vec::new();
 
+        // None makes the first step check the config.
+        let mut config_generation = None;
+
+        let mut cpu_time = thread_cpu_time();
+
         let mut ret = Ok(());
 
         'main_loop: while infov.len() != 0 {
+            // The min time to wait if all the memcgs are limited by
+            // reclaim_rate_limit_bytes_per_sec.
+            let mut rate_limit_min_wait: Option<Duration> = None;
+            let mut evicted = false;
+
+            if self.pause.is_paused() {
+                for info in infov.iter_mut() {
+                    if let Some(ref mut ei) = info.eviction {
+                        info!(
+                            "{} {} run_eviction stop because paused, release {} {} pages",
+                            info.path, info.numa_id, ei.anon_page_count, ei.file_page_count,
+                        );
+                        ei.stop_reason = EvictionStopReason::Paused;
+                    }
+                }
+                break 'main_loop;
+            }
+
+            // Cancel the infos whose config was changed.
+            {
+                let memcgs = self.memcgs.blocking_read();
+                if config_generation != Some(memcgs.config_generation) {
+                    config_generation = Some(memcgs.config_generation);
+                    let mut i = 0;
+                    while i < infov.len() {
+                        if memcgs.config_in_use(origin, &infov[i].path, infov[i].numa_id as u32) {
+                            i += 1;
+                            continue;
+                        }
+                        let mut info = infov.remove(i);
+                        if let Some(ref mut ei) = info.eviction {
+                            info!(
+                                "{} {} run_eviction stop because config changed, release {} {} pages",
+                                info.path, info.numa_id, ei.anon_page_count, ei.file_page_count,
+                            );
+                            ei.stop_reason = EvictionStopReason::Cancelled;
+                        }
+                        removed_infov.push(info);
+                    }
+                }
+            }
+            if infov.is_empty() {
+                break 'main_loop;
+            }
+
             // update infov
             let path_set: HashSet<String> = infov.iter().map(|info| info.path.clone()).collect();
             match self.refresh(&path_set) {
@@ -1144,15 +2339,45 @@ impl MemCG {
 
                 trace!("{} {} run_eviction single loop start", ci.path, ci.numa_id);
 
+                if config.reclaim_rate_limit_bytes_per_sec != 0 {
+                    let wait = rate_limit_wait(
+                        path_reclaims[&ci.path].released_bytes,
+                        config.reclaim_rate_limit_bytes_per_sec,
+                        start.elapsed(),
+                    );
+                    if !wait.is_zero() {
+                        trace!("{} {} run_eviction wait {:?}", ci.path, ci.numa_id, wait);
+                        rate_limit_min_wait = Some(match rate_limit_min_wait {
+                            Some(w) if w < wait => w,
+                            _ => wait,
+                        });
+                        i += 1;
+                        continue;
+                    }
+                }
+
                 if let Some(ref mut ei) = infov[i].eviction {
-                    if ci.max_seq - ci.min_seq + 1 != mglru::MAX_NR_GENS {
-                        info!("{} {} run_eviction stop because max seq {} min seq {} not fit MAX_NR_GENS, release {} {} pages",
-                                      ci.path, ci.numa_id, ci.max_seq, ci.min_seq, ei.anon_page_count, ei.file_page_count);
+                    if ci.max_seq - ci.min_seq + 1 < nr_gens_min {
+                        info!("{} {} run_eviction stop because max seq {} min seq {} not fit eviction_nr_gens_min {}, release {} {} pages",
+                                      ci.path, ci.numa_id, ci.max_seq, ci.min_seq, nr_gens_min, ei.anon_page_count, ei.file_page_count);
                         ei.stop_reason = EvictionStopReason::None;
                         removed_infov.push(infov.remove(i));
                         continue;
                     }
 
+                    if config.cold_age_secs != 0
+                        && Utc::now()
+                            .signed_duration_since(ci.min_lru_birth)
+                            .num_seconds()
+                            < config.cold_age_secs as i64
+                    {
+                        info!("{} {} run_eviction stop because min seq {} birth {} is not older than cold_age_secs {}, release {} {} pages",
+                                      ci.path, ci.numa_id, ci.min_seq, ci.min_lru_birth, config.cold_age_secs, ei.anon_page_count, ei.file_page_count);
+                        ei.stop_reason = EvictionStopReason::NotCold;
+                        removed_infov.push(infov.remove(i));
+                        continue;
+                    }
+
                     if ei.last_min_lru_file == 0 && ei.last_min_lru_anon == 0 {
                         // First loop
                         trace!("{} {} run_eviction begin", ci.path, ci.numa_id,);
@@ -1185,23 +2410,35 @@ impl MemCG {
                             continue;
                         }
 
-                        let released = ei.last_min_lru_anon - ci.min_lru_anon;
+                        let anon_released = ei.last_min_lru_anon - ci.min_lru_anon;
                         trace!(
                             "{} {} run_eviction anon {} pages",
                             ci.path,
                             ci.numa_id,
-                            released
+                            anon_released
                         );
-                        ei.anon_page_count += released;
+                        ei.anon_page_count += anon_released;
 
-                        let released = ei.last_min_lru_file - ci.min_lru_file;
+                        let file_released = ei.last_min_lru_file - ci.min_lru_file;
                         trace!(
                             "{} {} run_eviction file {} pages",
                             ci.path,
                             ci.numa_id,
-                            released
+                            file_released
                         );
-                        ei.file_page_count += released;
+                        ei.file_page_count += file_released;
+
+                        let pr = path_reclaims.get_mut(&ci.path).unwrap();
+                        pr.released_bytes += (anon_released + file_released) * page_size;
+                        if pr.target_bytes != 0 && pr.released_bytes >= pr.target_bytes {
+                            info!(
+                                "{} {} run_eviction stop because released {} bytes reach target {}, release {} {} pages",
+                                ci.path, ci.numa_id, pr.released_bytes, pr.target_bytes, ei.anon_page_count, ei.file_page_count,
+                            );
+                            ei.stop_reason = EvictionStopReason::TargetReached;
+                            removed_infov.push(infov.remove(i));
+                            continue;
+                        }
 
                         if !ei.only_swap_mode {
                             if ci.min_lru_file == 0 {
@@ -1280,11 +2517,18 @@ impl MemCG {
                         swappiness
                     );
 
-                    match mglru::run_eviction(ci.memcg_id, ci.numa_id, ci.min_seq, swappiness, 1) {
+                    match evictor.run_eviction(
+                        &ci.path,
+                        ci.memcg_id,
+                        ci.numa_id,
+                        ci.min_seq,
+                        swappiness,
+                        1,
+                    ) {
                         Ok(_) => {}
                         Err(e) => {
                             error!(
-                                "{} {} mglru::run_eviction failed: {}, release {} {} pages",
+                                "{} {} backend.run_eviction failed: {}, release {} {} pages",
                                 ci.path, ci.numa_id, e, ei.anon_page_count, ei.file_page_count,
                             );
                             ei.stop_reason = EvictionStopReason::GetError;
@@ -1293,6 +2537,8 @@ impl MemCG {
                         }
                     }
 
+                    evicted = true;
+
                     if let Err(e) = sched_yield() {
                         error!("sched_yield failed: {:?}", e);
                     }
@@ -1302,15 +2548,106 @@ impl MemCG {
 
                 i += 1;
             }
+
+            if !evicted {
+                if let Some(wait) = rate_limit_min_wait {
+                    trace!("run_eviction wait {:?} because of rate limit", wait);
+                    self.interruptible_sleep(wait);
+                }
+            }
+
+            self.charge_cpu_budget(&mut cpu_time);
         }
 
         let mut mgs = self.memcgs.blocking_write();
-        mgs.record_eviction(&infov);
-        mgs.record_eviction(&removed_infov);
+        mgs.record_eviction(&infov, works);
+        mgs.record_eviction(&removed_infov, works);
 
         ret
     }
 
+    // Record the oldest generation and the swappiness that the eviction
+    // would use without running it.
+    fn run_dry_eviction(
+        &mut self,
+        infov: &mut Vec<Info>,
+        config: &SingleConfig,
+        swap: bool,
+        works: &mut [NumaWork],
+    ) -> Result<()> {
+        let nr_gens_min = config
+            .eviction_nr_gens_min
+            .clamp(EVICTION_NR_GENS_MIN, mglru::MAX_NR_GENS);
+
+        // Get the generations after aging.
+        let path_set: HashSet<String> = infov.iter().map(|info| info.path.clone()).collect();
+        self.refresh(&path_set)
+            .map_err(|e| anyhow!("refresh failed: {}", e))?;
+        self.update_info(infov);
+
+        for ci in infov.iter() {
+            let mut work = works
+                .iter_mut()
+                .find(|w| w.path == ci.path && w.numa_id == ci.numa_id as u32);
+            if let Some(w) = work.as_mut() {
+                w.dry_run = true;
+            }
+
+            if ci.max_seq - ci.min_seq + 1 < nr_gens_min {
+                continue;
+            }
+            if config.cold_age_secs != 0
+                && Utc::now()
+                    .signed_duration_since(ci.min_lru_birth)
+                    .num_seconds()
+                    < config.cold_age_secs as i64
+            {
+                continue;
+            }
+
+            let swappiness = if ci.min_lru_file == 0 {
+                if !swap || ci.min_lru_anon == 0 {
+                    continue;
+                }
+                200
+            } else if !swap || ci.min_lru_anon == 0 {
+                0
+            } else {
+                std::cmp::min(
+                    self.get_swappiness(ci.min_lru_anon, ci.min_lru_file),
+                    config.swappiness_max,
+                )
+            };
+            let anon_pages = if swappiness == 0 { 0 } else { ci.min_lru_anon };
+            let file_pages = if swappiness == 200 {
+                0
+            } else {
+                ci.min_lru_file
+            };
+
+            info!(
+                "{} {} dry run eviction min_seq {} swappiness {}, would release {} {} pages",
+                ci.path, ci.numa_id, ci.min_seq, swappiness, anon_pages, file_pages
+            );
+
+            let mut mgs = self.memcgs.blocking_write();
+            if let Some(mg) = mgs.cgroups.get_mut(&ci.path) {
+                if let Some(numa) = mg.numa.get_mut(&(ci.numa_id as u32)) {
+                    numa.dry_run.count += 1;
+                    numa.dry_run.anon_pages += anon_pages;
+                    numa.dry_run.file_pages += file_pages;
+                    numa.dry_run.last_swappiness = swappiness;
+                }
+            }
+            if let Some(w) = work {
+                w.anon_pages = anon_pages;
+                w.file_pages = file_pages;
+            }
+        }
+
+        Ok(())
+    }
+
     fn check_psi_get_infos(&mut self, sec: u64) -> Vec<(SingleConfig, Vec<Info>)> {
         self.memcgs.blocking_write().check_psi_get_infos(sec)
     }
@@ -1319,7 +2656,11 @@ impl MemCG {
         self.memcgs.blocking_read().update_info(infov);
     }
 
+    // Return an empty list if paused.
     pub fn get_timeout_list(&self) -> Vec<u64> {
+        if self.pause.is_paused() {
+            return Vec::new();
+        }
         self.memcgs.blocking_read().get_timeout_list()
     }
 
@@ -1328,44 +2669,1014 @@ impl MemCG {
     }
 
     pub fn get_remaining_tokio_duration(&self) -> TokioDuration {
-        self.memcgs.blocking_read().get_remaining_tokio_duration()
+        self.pause
+            .delay(self.memcgs.blocking_read().get_remaining_tokio_duration())
     }
 
     pub async fn async_get_remaining_tokio_duration(&self) -> TokioDuration {
-        self.memcgs.read().await.get_remaining_tokio_duration()
+        self.pause
+            .delay(self.memcgs.read().await.get_remaining_tokio_duration())
+    }
+
+    // The pause is shared by the clones.
+    pub fn pause(&self) -> &Pause {
+        &self.pause
     }
 
     pub async fn set_config(&mut self, new_config: OptionConfig) -> Result<bool> {
-        self.memcgs.write().await.set_config(new_config)
+        let mut mgs = self.memcgs.write().await;
+        let reset = mgs.set_config(new_config)?;
+        mgs.events
+            .send(EventKind::ConfigChange(ConfigTarget::Memcg));
+        Ok(reset)
+    }
+
+    // Same as set_config but for the caller outside of the async context.
+    pub fn blocking_set_config(&mut self, new_config: OptionConfig) -> Result<bool> {
+        let mut mgs = self.memcgs.blocking_write();
+        let reset = mgs.set_config(new_config)?;
+        mgs.events
+            .send(EventKind::ConfigChange(ConfigTarget::Memcg));
+        Ok(reset)
+    }
+
+    pub fn set_events(&mut self, events: Events) {
+        self.memcgs.blocking_write().events = events;
     }
 
     pub async fn get_status(&self) -> HashMap<String, MemCgroup> {
-        self.memcgs.read().await.cgroups.clone()
+        self.memcgs.read().await.get_status()
+    }
+
+    pub async fn get_config(&self) -> Config {
+        self.memcgs.read().await.config.clone()
+    }
+
+    // Restore memory.high of all the memcgs that mem-agent set.
+    pub async fn restore_memory_highs(&self) {
+        self.memcgs
+            .write()
+            .await
+            .restore_memory_highs(&HashSet::new());
     }
 }
 
+#[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
     use super::*;
+    use crate::mglru_sim::{SimBackend, SimOp};
+    use crate::misc::TestDir;
+    use std::path::Path;
 
     #[test]
     fn test_memcg_swap_not_available() {
-        let is_cg_v2 = crate::cgroup::is_cgroup_v2().unwrap();
-        let m = MemCG::new(is_cg_v2, Config::default()).unwrap();
+        let host = HostFs::default();
+        let is_cg_v2 = crate::cgroup::is_cgroup_v2(&host).unwrap();
+        let m = MemCG::new(&host, is_cg_v2, Config::default()).unwrap();
         assert!(m.swap_not_available().is_ok());
     }
 
     #[test]
     fn test_memcg_get_swappiness() {
-        let is_cg_v2 = crate::cgroup::is_cgroup_v2().unwrap();
-        let m = MemCG::new(is_cg_v2, Config::default()).unwrap();
+        let host = HostFs::default();
+        let is_cg_v2 = crate::cgroup::is_cgroup_v2(&host).unwrap();
+        let m = MemCG::new(&host, is_cg_v2, Config::default()).unwrap();
         assert_eq!(m.get_swappiness(100, 50), 133);
     }
 
     #[test]
     fn test_memcg_get_timeout_list() {
-        let is_cg_v2 = crate::cgroup::is_cgroup_v2().unwrap();
-        let m = MemCG::new(is_cg_v2, Config::default()).unwrap();
+        let host = HostFs::default();
+        let is_cg_v2 = crate::cgroup::is_cgroup_v2(&host).unwrap();
+        let m = MemCG::new(&host, is_cg_v2, Config::default()).unwrap();
         assert_eq!(m.get_timeout_list().len() > 0, true);
     }
+
+    // (path, memcg id, (anon, file) of generations)
+    type SimMemcgs<'a> = [(&'a str, usize, &'a [(u64, u64)])];
+
+    fn setup_sim(name: &str, memcgs: &SimMemcgs) -> (TestDir, Arc<SimBackend>) {
+        let root = TestDir::new(name);
+        std::fs::create_dir_all(root.join("proc")).unwrap();
+        std::fs::write(
+            root.join("proc/meminfo"),
+            "MemFree:         4000000 kB\nSwapFree:        4000000 kB\n",
+        )
+        .unwrap();
+
+        let sim = Arc::new(SimBackend::new(&root.join("sys/fs/cgroup")).unwrap());
+        for (path, id, gens) in memcgs {
+            sim.add_memcg(path, *id, &[0], gens, 600).unwrap();
+        }
+
+        (root, sim)
+    }
+
+    fn sim_memcg(root: &Path, sim: &Arc<SimBackend>) -> MemCG {
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        MemCG::new_with_backend(&HostFs::new(root), true, sim.clone(), None, config).unwrap()
+    }
+
+    fn sim_eviction_count(m: &MemCG, path: &str) -> (u64, EvictionCount) {
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(m.get_status());
+        let numa = status.get(path).unwrap().numa.get(&0).unwrap();
+        (numa.run_aging_count, numa.eviction_count.clone())
+    }
+
+    #[test]
+    fn test_memcg_sim_no_min_lru() {
+        let (root, sim) = setup_sim(
+            "test_sim_no_min_lru",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(64);
+        let mut m = sim_memcg(&root, &sim);
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (run_aging_count, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(run_aging_count, 1);
+        assert_eq!(count.page, 200);
+        assert_eq!(count.no_min_lru_file, 1);
+        assert_eq!(
+            sim.gens("/a", 0),
+            vec![(0, 100, 0), (1, 10, 10), (2, 10, 10), (3, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_memcg_sim_min_lru_inc() {
+        let (root, sim) = setup_sim(
+            "test_sim_min_lru_inc",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(0);
+        let mut m = sim_memcg(&root, &sim);
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(count.page, 0);
+        assert_eq!(count.min_lru_inc, 1);
+    }
+
+    #[test]
+    fn test_memcg_sim_eviction_error() {
+        let (root, sim) = setup_sim(
+            "test_sim_eviction_error",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        sim.inject_error(SimOp::Eviction, 1);
+        let mut m = sim_memcg(&root, &sim);
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(count.page, 0);
+        assert_eq!(count.error, 1);
+        assert_eq!(sim.gens("/a", 0)[0], (0, 100, 200));
+    }
+
+    #[test]
+    fn test_memcg_sim_psi_exceeds_limit() {
+        let (root, sim) = setup_sim(
+            "test_sim_psi_exceeds_limit",
+            &[("/a", 2, &[(100, 2000), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(64);
+        sim.set_eviction_psi_us("/a", 1_000_000_000);
+        let mut m = sim_memcg(&root, &sim);
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(count.psi_exceeds_limit, 1);
+        assert_eq!(count.page, 128);
+    }
+
+    #[test]
+    fn test_memcg_sim_working_set() {
+        let (root, sim) = setup_sim(
+            "test_sim_working_set",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        let m = sim_memcg(&root, &sim);
+        let page_size = page_size::get() as u64;
+
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(m.get_status());
+        let mcg = status.get("/a").unwrap();
+        let numa = mcg.numa.get(&0).unwrap();
+        let gens: Vec<(u64, u64, u64)> = numa
+            .generations
+            .iter()
+            .map(|g| (g.seq, g.anon_bytes / page_size, g.file_bytes / page_size))
+            .collect();
+        assert_eq!(gens, vec![(0, 100, 200), (1, 10, 10), (2, 10, 10)]);
+
+        // The generations are born 1800, 1200 and 600 seconds ago.
+        assert_eq!(numa.working_set_bytes(60), 20 * page_size);
+        assert_eq!(numa.working_set_bytes(1500), 40 * page_size);
+        assert_eq!(numa.working_set_bytes(3600), 340 * page_size);
+        assert_eq!(mcg.working_set_bytes(3600), 340 * page_size);
+    }
+
+    #[test]
+    fn test_memcg_sim_reclaim_target() {
+        let (root, sim) = setup_sim(
+            "test_sim_reclaim_target",
+            &[("/a", 2, &[(100, 2000), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(32);
+        let page_size = page_size::get() as u64;
+
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        config.default.reclaim_target_bytes = 64 * page_size;
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(count.page, 64);
+        assert_eq!(count.target_reached, 1);
+    }
+
+    #[test]
+    fn test_memcg_sim_reclaim_target_percent() {
+        let (root, sim) = setup_sim(
+            "test_sim_reclaim_target_percent",
+            &[("/a", 2, &[(100, 2000), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(32);
+        let page_size = page_size::get() as u64;
+        std::fs::write(
+            root.join("sys/fs/cgroup/a/memory.current"),
+            format!("{}\n", 1000 * page_size),
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        // 100 pages, smaller than reclaim_target_bytes.
+        config.default.reclaim_target_percent = 10;
+        config.default.reclaim_target_bytes = 1000 * page_size;
+        // 640 pages per second, each eviction waits 50ms.
+        config.default.reclaim_rate_limit_bytes_per_sec = 640 * page_size;
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+
+        let start = Instant::now();
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(count.page, 128);
+        assert_eq!(count.target_reached, 1);
+    }
+
+    #[test]
+    fn test_memcg_sim_reclaim() {
+        let (root, sim) = setup_sim(
+            "test_sim_reclaim",
+            &[("/a", 2, &[(100, 2000), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(32);
+        let page_size = page_size::get() as u64;
+
+        // Reclaim doesn't wait for eviction_run_aging_count_min.
+        let mut m = MemCG::new_with_backend(
+            &HostFs::new(&root),
+            true,
+            sim.clone(),
+            None,
+            Config::default(),
+        )
+        .unwrap();
+
+        let mut req = ReclaimRequest {
+            path: "/a".to_string(),
+            numa_id: vec![0],
+            target_bytes: 64 * page_size,
+            swap: false,
+            psi_percent_limit: 100,
+        };
+        let works = m.reclaim(&req).unwrap();
+        assert_eq!(
+            works,
+            vec![NumaWork {
+                path: "/a".to_string(),
+                numa_id: 0,
+                anon_pages: 0,
+                file_pages: 64,
+                stop_reason: Some(EvictionStopReason::TargetReached),
+                dry_run: false,
+            }]
+        );
+        let (run_aging_count, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(run_aging_count, 1);
+        assert_eq!(count.target_reached, 1);
+
+        req.numa_id = vec![1];
+        assert!(m.reclaim(&req).is_err());
+
+        req.path = "/b".to_string();
+        req.numa_id = Vec::new();
+        assert!(m.reclaim(&req).is_err());
+    }
+
+    #[test]
+    fn test_memcg_sim_reclaim_dry_run() {
+        let (root, sim) = setup_sim(
+            "test_sim_reclaim_dry_run",
+            &[("/a", 2, &[(100, 2000), (10, 10), (10, 10), (10, 10)])],
+        );
+        let mut config = Config::default();
+        config.default.dry_run = true;
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+        let gens = sim.gens("/a", 0);
+
+        let req = ReclaimRequest {
+            path: "/a".to_string(),
+            numa_id: vec![0],
+            target_bytes: 0,
+            swap: false,
+            psi_percent_limit: 100,
+        };
+        let works = m.reclaim(&req).unwrap();
+        assert_eq!(
+            works,
+            vec![NumaWork {
+                path: "/a".to_string(),
+                numa_id: 0,
+                anon_pages: 0,
+                file_pages: 2000,
+                stop_reason: None,
+                dry_run: true,
+            }]
+        );
+        // Neither aged nor evicted.
+        assert_eq!(sim.gens("/a", 0), gens);
+        let (run_aging_count, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(run_aging_count, 0);
+        assert_eq!(count.page, 0);
+    }
+
+    #[test]
+    fn test_memcg_sim_reclaim_with_work() {
+        let (root, sim) = setup_sim(
+            "test_sim_reclaim_with_work",
+            &[
+                ("/a", 2, &[(100, 2000), (10, 10), (10, 10)]),
+                ("/b", 3, &[(100, 2000), (10, 10), (10, 10)]),
+            ],
+        );
+        sim.set_evict_batch(32);
+        sim.set_op_delay(Duration::from_millis(1));
+
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        let m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+
+        let req = ReclaimRequest {
+            path: "/a".to_string(),
+            numa_id: vec![0],
+            target_bytes: 640 * page_size::get() as u64,
+            swap: false,
+            psi_percent_limit: 100,
+        };
+        let barrier = std::sync::Barrier::new(2);
+        let (work_ret, reclaim_ret) = thread::scope(|s| {
+            let mut work_memcg = m.clone();
+            let mut reclaim_memcg = m.clone();
+            let barrier = &barrier;
+            let work = s.spawn(move || {
+                barrier.wait();
+                work_memcg.work(&vec![SingleConfig::default().period_secs])
+            });
+            let reclaim = s.spawn(move || {
+                barrier.wait();
+                reclaim_memcg.reclaim(&req)
+            });
+            (work.join().unwrap(), reclaim.join().unwrap())
+        });
+        // Whichever runs first, work still has /b to do.
+        assert!(!work_ret.unwrap().is_empty());
+        assert_eq!(reclaim_ret.unwrap().len(), 1);
+
+        // The ops of work and reclaim are not interleaved.
+        let threads = sim.op_threads();
+        let switches = threads.windows(2).filter(|w| w[0] != w[1]).count();
+        assert_eq!(switches, 1);
+    }
+
+    #[test]
+    fn test_memcg_sim_pause() {
+        let (root, sim) = setup_sim(
+            "test_sim_pause",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        let mut m = sim_memcg(&root, &sim);
+
+        m.pause().pause(None);
+        assert!(m.get_timeout_list().is_empty());
+        assert_eq!(m.get_remaining_tokio_duration(), TokioDuration::MAX);
+        assert!(m
+            .work(&vec![SingleConfig::default().period_secs])
+            .unwrap()
+            .is_empty());
+        assert_eq!(sim_eviction_count(&m, "/a").0, 0);
+        assert!(m
+            .reclaim(&ReclaimRequest {
+                path: "/a".to_string(),
+                ..Default::default()
+            })
+            .is_err());
+
+        m.clone().pause().resume();
+        assert_eq!(
+            m.get_timeout_list(),
+            vec![SingleConfig::default().period_secs]
+        );
+        assert_eq!(m.work(&m.get_timeout_list()).unwrap().len(), 1);
+        assert_eq!(sim_eviction_count(&m, "/a").0, 1);
+    }
+
+    #[test]
+    fn test_memcg_sim_cancel() {
+        let (root, sim) = setup_sim(
+            "test_sim_cancel",
+            &[
+                ("/a", 2, &[(100, 200), (10, 10), (10, 10)]),
+                ("/b", 3, &[(100, 200), (10, 10), (10, 10)]),
+            ],
+        );
+        sim.set_evict_batch(64);
+        let mut m = sim_memcg(&root, &sim);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        m.refresh(&HashSet::new()).unwrap();
+        let mut works = Vec::new();
+        let mut config_infov = m.check_psi_get_infos(SingleConfig::default().period_secs);
+        m.run_aging(&mut config_infov, &mut works);
+
+        // Disable /b while the work is running.
+        let mut oc = OptionConfig::default();
+        oc.add.push(CgroupOptionConfig {
+            path: "/b".to_string(),
+            config: SingleOptionConfig {
+                disabled: Some(true),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert!(rt.block_on(m.set_config(oc)).unwrap());
+
+        m.run_eviction(&mut config_infov, &mut works).unwrap();
+
+        let (_, count) = sim_eviction_count(&m, "/b");
+        assert_eq!(count.page, 0);
+        assert_eq!(count.cancelled, 1);
+        let b_work = works.iter().find(|w| w.path == "/b").unwrap();
+        assert_eq!(b_work.stop_reason, Some(EvictionStopReason::Cancelled));
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert!(count.page > 0);
+        assert_eq!(count.cancelled, 0);
+    }
+
+    #[test]
+    fn test_memcg_sim_cancel_stricter_config() {
+        let (root, sim) = setup_sim(
+            "test_sim_cancel_stricter_config",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(64);
+        let mut m = sim_memcg(&root, &sim);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        m.refresh(&HashSet::new()).unwrap();
+        let mut works = Vec::new();
+        let mut config_infov = m.check_psi_get_infos(SingleConfig::default().period_secs);
+        m.run_aging(&mut config_infov, &mut works);
+
+        // Lower eviction_psi_percent_limit while the work is running, it
+        // doesn't need reset.
+        let mut oc = OptionConfig::default();
+        oc.default.eviction_psi_percent_limit = Some(0);
+        assert!(!rt.block_on(m.set_config(oc)).unwrap());
+        // The timer is kept.
+        assert!(m
+            .get_timeout_list()
+            .contains(&SingleConfig::default().period_secs));
+
+        m.run_eviction(&mut config_infov, &mut works).unwrap();
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(count.page, 0);
+        assert_eq!(count.cancelled, 1);
+
+        // The next work uses the new config.
+        let config_infov = m.check_psi_get_infos(SingleConfig::default().period_secs);
+        assert_eq!(config_infov[0].0.eviction_psi_percent_limit, 0);
+    }
+
+    #[test]
+    fn test_memcg_sim_rate_limit_pause() {
+        let (root, sim) = setup_sim(
+            "test_sim_rate_limit_pause",
+            &[("/a", 2, &[(100, 2000), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(32);
+
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        // Each eviction waits 32 seconds.
+        config.default.reclaim_rate_limit_bytes_per_sec = page_size::get() as u64;
+        let m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+
+        let mut work_memcg = m.clone();
+        let start = Instant::now();
+        let work = thread::spawn(move || {
+            work_memcg
+                .work(&vec![SingleConfig::default().period_secs])
+                .unwrap()
+        });
+        thread::sleep(Duration::from_millis(200));
+        m.pause().pause(None);
+        let works = work.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(works[0].stop_reason, Some(EvictionStopReason::Paused));
+    }
+
+    #[test]
+    fn test_memcg_sim_eviction_workers() {
+        let memcgs: &SimMemcgs = &[
+            ("/a", 2, &[(100, 200), (10, 10), (10, 10)]),
+            ("/b", 3, &[(300, 100), (10, 10), (10, 10)]),
+            ("/c", 4, &[(50, 500), (10, 10), (10, 10)]),
+        ];
+
+        // The workers evict the same pages as the serial eviction.
+        let mut pages = Vec::new();
+        for (workers, cpu_percent) in [(1, 0), (3, 0), (2, 50)] {
+            let (root, sim) = setup_sim(
+                &format!("test_sim_eviction_workers_{}_{}", workers, cpu_percent),
+                memcgs,
+            );
+            sim.set_evict_batch(64);
+            let mut config = Config::default();
+            config.default.eviction_run_aging_count_min = 1;
+            config.eviction_workers = workers;
+            config.eviction_workers_cpu_percent = cpu_percent;
+            let mut m =
+                MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config)
+                    .unwrap();
+
+            let mut works = m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+            works.sort_by(|a, b| a.path.cmp(&b.path));
+            assert_eq!(works.len(), 3);
+            assert!(works.iter().all(|w| w.stop_reason.is_some()));
+
+            pages.push(
+                ["/a", "/b", "/c"]
+                    .iter()
+                    .map(|path| sim_eviction_count(&m, path).1.page)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert!(pages[0].iter().all(|p| *p > 0));
+        assert_eq!(pages[0], pages[1]);
+        assert_eq!(pages[0], pages[2]);
+    }
+
+    #[test]
+    fn test_cpu_budget_wait() {
+        assert_eq!(
+            cpu_budget_wait(Duration::from_millis(100), 50, Duration::from_millis(50)),
+            Duration::from_millis(150)
+        );
+        assert!(
+            cpu_budget_wait(Duration::from_millis(100), 200, Duration::from_millis(50)).is_zero()
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_wait() {
+        assert_eq!(
+            rate_limit_wait(100, 100, Duration::from_millis(200)),
+            Duration::from_millis(800)
+        );
+        assert!(rate_limit_wait(100, 100, Duration::from_secs(2)).is_zero());
+        assert!(rate_limit_wait(0, 100, Duration::ZERO).is_zero());
+    }
+
+    #[test]
+    fn test_memcg_sim_cold_age() {
+        let (root, sim) = setup_sim(
+            "test_sim_cold_age",
+            &[
+                ("/a", 2, &[(100, 200), (10, 10), (10, 10)]),
+                ("/b", 3, &[(100, 200), (10, 10), (10, 10)]),
+            ],
+        );
+        sim.set_evict_batch(64);
+
+        // The oldest generations are born 1800 seconds ago.
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        config.default.cold_age_secs = 3600;
+        let mut b_config = CgroupConfig::default();
+        b_config.config.cold_age_secs = 1000;
+        b_config.no_subdir = true;
+        config.cgroups.insert("/b".to_string(), vec![b_config]);
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(count.page, 0);
+        assert_eq!(count.not_cold, 1);
+        assert_eq!(sim.gens("/a", 0)[0], (0, 100, 200));
+
+        let (_, count) = sim_eviction_count(&m, "/b");
+        assert_eq!(count.page, 200);
+        assert_eq!(count.not_cold, 0);
+    }
+
+    #[test]
+    fn test_memcg_sim_aging_idle_fresh_ignore() {
+        let (root, sim) = setup_sim(
+            "test_sim_aging_idle_fresh_ignore",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+
+        // The youngest generation is born 600 seconds ago.
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        config.default.aging_idle_fresh_ignore_secs = 3600;
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (run_aging_count, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(run_aging_count, 0);
+        assert_eq!(count.page, 0);
+        assert_eq!(sim.gens("/a", 0).len(), 3);
+    }
+
+    #[test]
+    fn test_memcg_sim_aging_error() {
+        let (root, sim) = setup_sim(
+            "test_sim_aging_error",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        sim.inject_error(SimOp::Aging, 1);
+        let mut m = sim_memcg(&root, &sim);
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (run_aging_count, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(run_aging_count, 0);
+        assert_eq!(count.page, 0);
+        assert_eq!(sim.gens("/a", 0).len(), 3);
+    }
+
+    fn setup_reclaim(root: &Path, path: &str) {
+        let cgroup = root.join("sys/fs/cgroup");
+        std::fs::write(cgroup.join("cgroup.controllers"), "memory\n").unwrap();
+        std::fs::write(cgroup.join("memory.reclaim"), "").unwrap();
+        let dir = cgroup.join(path.trim_start_matches('/'));
+        std::fs::write(dir.join("memory.reclaim"), "").unwrap();
+        std::fs::write(
+            dir.join("memory.stat"),
+            format!(
+                "inactive_anon {}\nactive_anon 0\ninactive_file {}\nactive_file 0\n",
+                100 * page_size::get(),
+                200 * page_size::get()
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_memcg_reclaim_backend() {
+        // Just use the sim to create the psi files.
+        let (root, _) = setup_sim("test_memcg_reclaim_backend", &[("/a", 2, &[])]);
+        setup_reclaim(&root, "/a");
+
+        let host = HostFs::new(&root);
+        let backend = Arc::new(ReclaimBackend::new(&host));
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        let mut m = MemCG::new_with_backend(&host, true, backend, None, config).unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (run_aging_count, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(run_aging_count, 1);
+        // The fake memory.stat is not changed by the reclaim.
+        assert_eq!(count.min_lru_inc, 1);
+        let cmd = std::fs::read_to_string(root.join("sys/fs/cgroup/a/memory.reclaim")).unwrap();
+        assert!(cmd.ends_with(" swappiness=0"));
+    }
+
+    #[test]
+    fn test_memcg_sim_eviction_backend() {
+        let (root, sim) = setup_sim(
+            "test_sim_eviction_backend",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        setup_reclaim(&root, "/a");
+
+        let host = HostFs::new(&root);
+        let reclaim: Arc<dyn mglru::Backend> = Arc::new(ReclaimBackend::new(&host));
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        config.default.eviction_backend = EvictionBackend::MemoryReclaim;
+        let mut m =
+            MemCG::new_with_backend(&host, true, sim.clone(), Some(reclaim), config).unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        // The eviction is not run by the sim.
+        assert_eq!(sim.gens("/a", 0)[0], (0, 100, 200));
+        let cmd = std::fs::read_to_string(root.join("sys/fs/cgroup/a/memory.reclaim")).unwrap();
+        assert!(cmd.ends_with(" swappiness=0"));
+    }
+
+    #[test]
+    fn test_memcg_sim_memory_high() {
+        let (root, sim) = setup_sim(
+            "test_sim_memory_high",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        let high_path = root.join("sys/fs/cgroup/a/memory.high");
+        std::fs::write(&high_path, "max\n").unwrap();
+
+        let mut config = Config::default();
+        config.default.memory_high = true;
+        // Only the youngest generation that is born 600 seconds ago and the
+        // new one from aging are inside the window.
+        config.default.memory_high_window_secs = 900;
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+        let high = 20 * page_size::get() as u64 * 120 / 100;
+        assert_eq!(
+            std::fs::read_to_string(&high_path).unwrap(),
+            high.to_string()
+        );
+
+        // Disable memory_high restores the original value.
+        let mut oc = OptionConfig::default();
+        oc.default.memory_high = Some(false);
+        assert!(rt.block_on(m.set_config(oc)).unwrap());
+        assert_eq!(std::fs::read_to_string(&high_path).unwrap(), "max");
+
+        // Exit restores the original value.
+        let mut oc = OptionConfig::default();
+        oc.default.memory_high = Some(true);
+        rt.block_on(m.set_config(oc)).unwrap();
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+        assert_ne!(std::fs::read_to_string(&high_path).unwrap(), "max");
+        drop(m);
+        assert_eq!(std::fs::read_to_string(&high_path).unwrap(), "max");
+    }
+
+    #[test]
+    fn test_memcg_sim_memory_high_dry_run() {
+        let (root, sim) = setup_sim(
+            "test_sim_memory_high_dry_run",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        let high_path = root.join("sys/fs/cgroup/a/memory.high");
+        std::fs::write(&high_path, "max\n").unwrap();
+
+        let mut config = Config::default();
+        config.default.memory_high = true;
+        config.default.memory_high_window_secs = 900;
+        config.default.dry_run = true;
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+        assert_eq!(std::fs::read_to_string(&high_path).unwrap(), "max\n");
+
+        let high = 20 * page_size::get() as u64 * 120 / 100;
+        let status = rt.block_on(m.get_status());
+        assert_eq!(status["/a"].memory_high, None);
+        assert_eq!(status["/a"].dry_run_memory_high, Some(high));
+
+        // Disable dry_run sets the value.
+        let mut oc = OptionConfig::default();
+        oc.default.dry_run = Some(false);
+        rt.block_on(m.set_config(oc)).unwrap();
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+        let status = rt.block_on(m.get_status());
+        assert!(status["/a"].memory_high.is_some());
+        assert_eq!(status["/a"].dry_run_memory_high, None);
+        assert_eq!(
+            std::fs::read_to_string(&high_path).unwrap(),
+            status["/a"].memory_high.unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_eviction_backend_from_str() {
+        assert_eq!(
+            "lru_gen".parse::<EvictionBackend>().unwrap(),
+            EvictionBackend::LruGen
+        );
+        assert_eq!(
+            "memory_reclaim".parse::<EvictionBackend>().unwrap(),
+            EvictionBackend::MemoryReclaim
+        );
+        assert_eq!(EvictionBackend::MemoryReclaim.to_string(), "memory_reclaim");
+        assert!("debugfs".parse::<EvictionBackend>().is_err());
+    }
+
+    #[test]
+    fn test_memcg_sim_refresh() {
+        let (root, sim) = setup_sim(
+            "test_sim_refresh",
+            &[
+                ("/a", 2, &[(100, 200), (10, 10), (10, 10)]),
+                ("/b", 3, &[(100, 200), (10, 10), (10, 10)]),
+            ],
+        );
+        let mut m = sim_memcg(&root, &sim);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        assert_eq!(rt.block_on(m.get_status()).len(), 2);
+
+        sim.inject_error(SimOp::MemcgsGet, 1);
+        assert!(m.work(&vec![SingleConfig::default().period_secs]).is_err());
+
+        sim.remove_memcg("/b");
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+        let status = rt.block_on(m.get_status());
+        assert!(status.contains_key("/a"));
+        assert!(!status.contains_key("/b"));
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        assert_eq!(glob_to_regex("/kubepods/*"), "/kubepods/[^/]*");
+        assert_eq!(glob_to_regex("/a/**"), "/a/.*");
+        assert_eq!(glob_to_regex("/a?.b"), "/a[^/]\\.b");
+        assert_eq!(glob_to_regex("/a[!0-9]"), "/a[^0-9]");
+
+        let p = PathPattern::new("/kubepods/*/pod*").unwrap().unwrap();
+        assert!(p.is_match("/kubepods/burstable/pod1"));
+        assert!(!p.is_match("/kubepods/burstable/pod1/c1"));
+        assert!(!p.is_match("/kubepods/pod1"));
+
+        let p = PathPattern::new("re:/a/[0-9]+").unwrap().unwrap();
+        assert!(p.is_match("/a/123"));
+        assert!(!p.is_match("/a/123b"));
+
+        assert!(PathPattern::new("/a/b").unwrap().is_none());
+        assert!(PathPattern::new("re:/a/(").is_err());
+        assert_eq!(format_path("re:/a/.*/"), "re:/a/.*/");
+    }
+
+    #[test]
+    fn test_path_to_numa_configs_pattern() {
+        let cc = |period_secs| {
+            let mut cc = CgroupConfig::default();
+            cc.config.period_secs = period_secs;
+            vec![cc]
+        };
+        let mut config = Config::default();
+        config.cgroups.insert("/a/b".to_string(), cc(1));
+        config.cgroups.insert("/a/*".to_string(), cc(2));
+        config.cgroups.insert("/a/**".to_string(), cc(3));
+        config.cgroups.insert("re:/c/[0-9]+".to_string(), cc(4));
+        config.cgroups.insert("/c".to_string(), cc(5));
+        let patterns = config.path_patterns().unwrap();
+        let period_secs = |path: &str| {
+            let configs = config.path_to_numa_configs(path, &patterns, HashSet::from([0]));
+            configs[0].1.period_secs
+        };
+
+        // The exact path is in front of the patterns.
+        assert_eq!(period_secs("/a/b"), 1);
+        // The pattern with fewer wildcards is in front.
+        assert_eq!(period_secs("/a/c"), 2);
+        // The deeper layer is in front of the upper layer.
+        assert_eq!(period_secs("/a/c/d"), 3);
+        assert_eq!(period_secs("/c/12"), 4);
+        assert_eq!(period_secs("/c/x"), 5);
+        assert_eq!(period_secs("/d"), SingleConfig::default().period_secs);
+
+        config.cgroups.insert("re:(".to_string(), cc(6));
+        assert!(config.path_patterns().is_err());
+    }
+
+    #[test]
+    fn test_memcg_sim_events() {
+        let (root, sim) = setup_sim(
+            "test_sim_events",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(64);
+        let mut m = sim_memcg(&root, &sim);
+        let events = Events::default();
+        let mut rx = events.subscribe();
+        m.set_events(events);
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let mut kinds = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            kinds.push(ev.kind);
+        }
+        assert!(kinds.contains(&EventKind::Aging {
+            path: "/a".to_string(),
+            numa_id: 0,
+        }));
+        assert!(kinds.iter().any(|k| matches!(
+            k,
+            EventKind::EvictionStop { path, numa_id: 0, anon_pages: 0, file_pages, .. }
+                if path == "/a" && *file_pages > 0
+        )));
+
+        sim.add_memcg("/b", 3, &[0], &[(10, 10), (10, 10), (10, 10)], 600)
+            .unwrap();
+        sim.remove_memcg("/a");
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let mut kinds = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            kinds.push(ev.kind);
+        }
+        assert!(kinds
+            .iter()
+            .any(|k| matches!(k, EventKind::CgroupRemove { path, id: 2, .. } if path == "/a")));
+        assert!(kinds
+            .iter()
+            .any(|k| matches!(k, EventKind::CgroupAdd { path, id: 3, .. } if path == "/b")));
+    }
+
+    #[test]
+    fn test_memcg_sim_work_report() {
+        let (root, sim) = setup_sim(
+            "test_sim_work_report",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        sim.set_evict_batch(64);
+        let mut m = sim_memcg(&root, &sim);
+
+        let works = m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+        assert_eq!(works.len(), 1);
+        assert_eq!(works[0].path, "/a");
+        assert_eq!(works[0].numa_id, 0);
+        let (_, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(works[0].anon_pages + works[0].file_pages, count.page);
+        assert!(works[0].stop_reason.is_some());
+    }
+
+    #[test]
+    fn test_memcg_sim_dry_run() {
+        let (root, sim) = setup_sim(
+            "test_sim_dry_run",
+            &[("/a", 2, &[(100, 200), (10, 10), (10, 10)])],
+        );
+        let mut config = Config::default();
+        config.default.eviction_run_aging_count_min = 1;
+        config.default.dry_run = true;
+        let mut m =
+            MemCG::new_with_backend(&HostFs::new(&root), true, sim.clone(), None, config).unwrap();
+
+        m.work(&vec![SingleConfig::default().period_secs]).unwrap();
+
+        let (run_aging_count, count) = sim_eviction_count(&m, "/a");
+        assert_eq!(run_aging_count, 1);
+        assert_eq!(count.page, 0);
+        // The oldest generation is not evicted.
+        assert_eq!(sim.gens("/a", 0)[0], (0, 100, 200));
+
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(m.get_status());
+        let dry_run = &status["/a"].numa[&0].dry_run;
+        assert_eq!(dry_run.count, 1);
+        assert_eq!(dry_run.anon_pages, 0);
+        assert_eq!(dry_run.file_pages, 200);
+        assert_eq!(dry_run.last_swappiness, 0);
+    }
 }
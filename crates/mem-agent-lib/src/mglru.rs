@@ -4,15 +4,16 @@
 
 use crate::cgroup::CGROUP_PATH;
 use crate::cgroup::MEMCGS_V1_PATH;
+use crate::hostfs::HostFs;
 use crate::{debug, trace, warn};
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Duration, Utc};
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt::Debug;
 use std::fs::{self, File, OpenOptions};
 use std::io::{BufRead, BufReader};
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
 
 const WORKINGSET_ANON: usize = 0;
 const WORKINGSET_FILE: usize = 1;
@@ -223,11 +224,13 @@ fn lru_gen_file_parse(
 }
 
 fn file_parse(
+    host: &HostFs,
     target_patchs: &HashSet<String>,
     parse_line: bool,
 ) -> Result<HashMap<String, (usize, HashMap<usize, MGenLRU>)>> {
-    let file = File::open(LRU_GEN_PATH)
-        .map_err(|e| anyhow!("open file {} failed: {}", LRU_GEN_PATH, e))?;
+    let lru_gen_path = host.path(LRU_GEN_PATH);
+    let file = File::open(&lru_gen_path)
+        .map_err(|e| anyhow!("open file {:?} failed: {}", lru_gen_path, e))?;
 
     let mut reader = BufReader::new(file);
 
@@ -237,19 +240,20 @@ fn file_parse(
 //result:
 // HashMap<path, (id, ino, HashMap<node_id, MGenLRU>)>
 pub fn host_memcgs_get(
+    host: &HostFs,
     target_patchs: &HashSet<String>,
     parse_line: bool,
     is_cg_v2: bool,
 ) -> Result<HashMap<String, (usize, usize, HashMap<usize, MGenLRU>)>> {
-    let mgs = file_parse(target_patchs, parse_line)
+    let mgs = file_parse(host, target_patchs, parse_line)
         .map_err(|e| anyhow!("mglru file_parse failed: {}", e))?;
 
     let mut host_mgs = HashMap::new();
     for (path, (id, mglru)) in mgs {
         let host_path = if is_cg_v2 {
-            PathBuf::from(CGROUP_PATH).join(path.trim_start_matches('/'))
+            host.path(CGROUP_PATH).join(path.trim_start_matches('/'))
         } else {
-            PathBuf::from(MEMCGS_V1_PATH).join(path.trim_start_matches('/'))
+            host.path(MEMCGS_V1_PATH).join(path.trim_start_matches('/'))
         };
 
         let metadata = match fs::metadata(host_path.clone()) {
@@ -268,35 +272,34 @@ pub fn host_memcgs_get(
     Ok(host_mgs)
 }
 
-pub fn check() -> Result<()> {
-    if crate::misc::is_test_environment() {
-        return Ok(());
-    }
-
-    let content = fs::read_to_string(LRU_GEN_ENABLED_PATH)
-        .map_err(|e| anyhow!("open file {} failed: {}", LRU_GEN_ENABLED_PATH, e))?;
+pub fn check(host: &HostFs) -> Result<()> {
+    let enabled_path = host.path(LRU_GEN_ENABLED_PATH);
+    let content = fs::read_to_string(&enabled_path)
+        .map_err(|e| anyhow!("open file {:?} failed: {}", enabled_path, e))?;
     let content = content.trim();
     let r = if content.starts_with("0x") {
         u32::from_str_radix(&content[2..], 16)
     } else {
         content.parse()
     };
-    let enabled = r.map_err(|e| anyhow!("parse file {} failed: {}", LRU_GEN_ENABLED_PATH, e))?;
+    let enabled = r.map_err(|e| anyhow!("parse file {:?} failed: {}", enabled_path, e))?;
     if enabled != 7 {
-        fs::write(LRU_GEN_ENABLED_PATH, "7")
-            .map_err(|e| anyhow!("write file {} failed: {}", LRU_GEN_ENABLED_PATH, e))?;
+        fs::write(&enabled_path, "7")
+            .map_err(|e| anyhow!("write file {:?} failed: {}", enabled_path, e))?;
     }
 
+    let lru_gen_path = host.path(LRU_GEN_PATH);
     let _ = OpenOptions::new()
         .read(true)
         .write(true)
-        .open(LRU_GEN_PATH)
-        .map_err(|e| anyhow!("open file {} failed: {}", LRU_GEN_PATH, e))?;
+        .open(&lru_gen_path)
+        .map_err(|e| anyhow!("open file {:?} failed: {}", lru_gen_path, e))?;
 
     Ok(())
 }
 
 pub fn run_aging(
+    host: &HostFs,
     memcg_id: usize,
     numa_id: usize,
     max_seq: u64,
@@ -307,13 +310,15 @@ pub fn run_aging(
         "+ {} {} {} {} {}",
         memcg_id, numa_id, max_seq, can_swap as i32, force_scan as i32
     );
-    trace!("send cmd {} to {}", cmd, LRU_GEN_PATH);
-    fs::write(LRU_GEN_PATH, &cmd)
-        .map_err(|e| anyhow!("write file {} cmd {} failed: {}", LRU_GEN_PATH, cmd, e))?;
+    let lru_gen_path = host.path(LRU_GEN_PATH);
+    trace!("send cmd {} to {:?}", cmd, lru_gen_path);
+    fs::write(&lru_gen_path, &cmd)
+        .map_err(|e| anyhow!("write file {:?} cmd {} failed: {}", lru_gen_path, cmd, e))?;
     Ok(())
 }
 
 pub fn run_eviction(
+    host: &HostFs,
     memcg_id: usize,
     numa_id: usize,
     min_seq: u64,
@@ -324,12 +329,101 @@ pub fn run_eviction(
         "- {} {} {} {} {}",
         memcg_id, numa_id, min_seq, swappiness, nr_to_reclaim
     );
-    trace!("send cmd {} to {}", cmd, LRU_GEN_PATH);
-    fs::write(LRU_GEN_PATH, &cmd)
-        .map_err(|e| anyhow!("write file {} cmd {} failed: {}", LRU_GEN_PATH, cmd, e))?;
+    let lru_gen_path = host.path(LRU_GEN_PATH);
+    trace!("send cmd {} to {:?}", cmd, lru_gen_path);
+    fs::write(&lru_gen_path, &cmd)
+        .map_err(|e| anyhow!("write file {:?} cmd {} failed: {}", lru_gen_path, cmd, e))?;
     Ok(())
 }
 
+// HashMap<path, (id, ino, HashMap<node_id, MGenLRU>)>
+pub type HostMemcgs = HashMap<String, (usize, usize, HashMap<usize, MGenLRU>)>;
+
+// Backend is the MGLRU interface that MemCG works with.
+// path and memcg_id of run_aging and run_eviction are got from memcgs_get.
+pub trait Backend: Debug + Send + Sync {
+    fn check(&self) -> Result<()>;
+
+    fn memcgs_get(&self, target_patchs: &HashSet<String>, parse_line: bool) -> Result<HostMemcgs>;
+
+    fn run_aging(
+        &self,
+        path: &str,
+        memcg_id: usize,
+        numa_id: usize,
+        max_seq: u64,
+        can_swap: bool,
+        force_scan: bool,
+    ) -> Result<()>;
+
+    fn run_eviction(
+        &self,
+        path: &str,
+        memcg_id: usize,
+        numa_id: usize,
+        min_seq: u64,
+        swappiness: u8,
+        nr_to_reclaim: usize,
+    ) -> Result<()>;
+}
+
+// KernelBackend works with the lru_gen debugfs of Linux kernel.
+#[derive(Debug)]
+pub struct KernelBackend {
+    host: HostFs,
+    is_cg_v2: bool,
+}
+
+impl KernelBackend {
+    pub fn new(host: &HostFs, is_cg_v2: bool) -> Self {
+        Self {
+            host: host.clone(),
+            is_cg_v2,
+        }
+    }
+}
+
+impl Backend for KernelBackend {
+    fn check(&self) -> Result<()> {
+        check(&self.host)
+    }
+
+    fn memcgs_get(&self, target_patchs: &HashSet<String>, parse_line: bool) -> Result<HostMemcgs> {
+        host_memcgs_get(&self.host, target_patchs, parse_line, self.is_cg_v2)
+    }
+
+    fn run_aging(
+        &self,
+        _path: &str,
+        memcg_id: usize,
+        numa_id: usize,
+        max_seq: u64,
+        can_swap: bool,
+        force_scan: bool,
+    ) -> Result<()> {
+        run_aging(&self.host, memcg_id, numa_id, max_seq, can_swap, force_scan)
+    }
+
+    fn run_eviction(
+        &self,
+        _path: &str,
+        memcg_id: usize,
+        numa_id: usize,
+        min_seq: u64,
+        swappiness: u8,
+        nr_to_reclaim: usize,
+    ) -> Result<()> {
+        run_eviction(
+            &self.host,
+            memcg_id,
+            numa_id,
+            min_seq,
+            swappiness,
+            nr_to_reclaim,
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,369 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+// SimBackend is an in-memory simulator of MGLRU to test MemCG without
+// a MGLRU kernel.
+
+use crate::mglru::{Backend, GenLRU, HostMemcgs, MGenLRU, MAX_NR_GENS};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+use std::time::Duration as StdDuration;
+
+// Same as MIN_NR_GENS of Linux kernel.
+const MIN_NR_GENS: u64 = 2;
+const MEM_PSI: &str = "memory.pressure";
+const IO_PSI: &str = "io.pressure";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SimOp {
+    MemcgsGet,
+    Aging,
+    Eviction,
+}
+
+#[derive(Debug, Clone)]
+struct SimGen {
+    seq: u64,
+    anon: u64,
+    file: u64,
+    birth: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct SimMemcg {
+    id: usize,
+    ino: usize,
+    // numa_id -> generations from the oldest to the youngest
+    nodes: BTreeMap<usize, Vec<SimGen>>,
+    // psi some total that is increased by each eviction
+    eviction_psi_us: u64,
+    psi_total: u64,
+}
+
+#[derive(Debug)]
+struct SimState {
+    psi_root: PathBuf,
+    memcgs: BTreeMap<String, SimMemcg>,
+    // op -> how many times it will fail
+    errors: HashMap<SimOp, usize>,
+    evict_batch: u64,
+    next_ino: usize,
+    // the threads that ran the aging and eviction in order
+    op_threads: Vec<ThreadId>,
+    // how long each aging and eviction takes
+    op_delay: StdDuration,
+}
+
+impl SimState {
+    fn take_error(&mut self, op: SimOp) -> Result<()> {
+        if let Some(times) = self.errors.get_mut(&op) {
+            if *times > 0 {
+                *times -= 1;
+                return Err(anyhow!("injected {:?} error", op));
+            }
+        }
+        Ok(())
+    }
+
+    fn memcg_by_id(&mut self, memcg_id: usize) -> Result<(String, &mut SimMemcg)> {
+        self.memcgs
+            .iter_mut()
+            .find(|(_, m)| m.id == memcg_id)
+            .map(|(p, m)| (p.clone(), m))
+            .ok_or_else(|| anyhow!("memcg {} is not exist", memcg_id))
+    }
+
+    fn write_psi(&self, path: &str, total: u64) -> Result<()> {
+        let dir = self.psi_root.join(path.trim_start_matches('/'));
+        fs::create_dir_all(&dir).map_err(|e| anyhow!("create_dir_all {:?} failed: {}", dir, e))?;
+        let data = format!(
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total={}\n\
+             full avg10=0.00 avg60=0.00 avg300=0.00 total={}\n",
+            total, total
+        );
+        for psi in [MEM_PSI, IO_PSI] {
+            fs::write(dir.join(psi), &data)
+                .map_err(|e| anyhow!("write {:?} failed: {}", dir.join(psi), e))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SimBackend {
+    state: Mutex<SimState>,
+}
+
+impl SimBackend {
+    // The psi files of the root and the memcgs are written to psi_root.
+    pub fn new(psi_root: &Path) -> Result<Self> {
+        let state = SimState {
+            psi_root: psi_root.to_path_buf(),
+            memcgs: BTreeMap::new(),
+            errors: HashMap::new(),
+            evict_batch: 64,
+            next_ino: 1000,
+            op_threads: Vec::new(),
+            op_delay: StdDuration::ZERO,
+        };
+        state.write_psi("/", 0)?;
+
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    // gens is the (anon, file) pages of each generation from the oldest
+    // to the youngest.
+    // The youngest generation is born age_secs ago and each older one is
+    // born age_secs earlier than the next one.
+    pub fn add_memcg(
+        &self,
+        path: &str,
+        id: usize,
+        numa: &[usize],
+        gens: &[(u64, u64)],
+        age_secs: i64,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = Utc::now();
+        let gens: Vec<SimGen> = gens
+            .iter()
+            .enumerate()
+            .map(|(i, (anon, file))| SimGen {
+                seq: i as u64,
+                anon: *anon,
+                file: *file,
+                birth: now - Duration::seconds(age_secs * (gens.len() - i) as i64),
+            })
+            .collect();
+
+        state.write_psi(path, 0)?;
+
+        let ino = state.next_ino;
+        state.next_ino += 1;
+        state.memcgs.insert(
+            path.to_string(),
+            SimMemcg {
+                id,
+                ino,
+                nodes: numa.iter().map(|n| (*n, gens.clone())).collect(),
+                eviction_psi_us: 0,
+                psi_total: 0,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn remove_memcg(&self, path: &str) {
+        self.state.lock().unwrap().memcgs.remove(path);
+    }
+
+    // Set the max pages that are reclaimed by one eviction.
+    pub fn set_evict_batch(&self, pages: u64) {
+        self.state.lock().unwrap().evict_batch = pages;
+    }
+
+    pub fn set_op_delay(&self, delay: StdDuration) {
+        self.state.lock().unwrap().op_delay = delay;
+    }
+
+    pub fn op_threads(&self) -> Vec<ThreadId> {
+        self.state.lock().unwrap().op_threads.clone()
+    }
+
+    // Record the thread of an op and let the op take op_delay without
+    // holding the state.
+    fn op_start(&self) {
+        let delay = {
+            let mut state = self.state.lock().unwrap();
+            state.op_threads.push(thread::current().id());
+            state.op_delay
+        };
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+
+    // Each eviction of the memcg increases its psi some total by us.
+    pub fn set_eviction_psi_us(&self, path: &str, us: u64) {
+        if let Some(m) = self.state.lock().unwrap().memcgs.get_mut(path) {
+            m.eviction_psi_us = us;
+        }
+    }
+
+    // Make the next times calls of op fail.
+    pub fn inject_error(&self, op: SimOp, times: usize) {
+        self.state.lock().unwrap().errors.insert(op, times);
+    }
+
+    // Get the (seq, anon, file) of the generations from the oldest to the
+    // youngest.
+    pub fn gens(&self, path: &str, numa_id: usize) -> Vec<(u64, u64, u64)> {
+        self.state
+            .lock()
+            .unwrap()
+            .memcgs
+            .get(path)
+            .and_then(|m| m.nodes.get(&numa_id))
+            .map(|gens| gens.iter().map(|g| (g.seq, g.anon, g.file)).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Backend for SimBackend {
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn memcgs_get(&self, target_patchs: &HashSet<String>, parse_line: bool) -> Result<HostMemcgs> {
+        let mut state = self.state.lock().unwrap();
+        state.take_error(SimOp::MemcgsGet)?;
+
+        let mut ret = HashMap::new();
+        for (path, m) in &state.memcgs {
+            if !target_patchs.is_empty() && !target_patchs.contains(path) {
+                continue;
+            }
+
+            let mut nodes = HashMap::new();
+            if parse_line {
+                for (numa_id, gens) in &m.nodes {
+                    if gens.is_empty() {
+                        continue;
+                    }
+                    let lru: Vec<GenLRU> = gens
+                        .iter()
+                        .map(|g| GenLRU {
+                            seq: g.seq,
+                            anon: g.anon,
+                            file: g.file,
+                            birth: g.birth,
+                        })
+                        .collect();
+                    nodes.insert(
+                        *numa_id,
+                        MGenLRU {
+                            min_seq: gens[0].seq,
+                            max_seq: gens[gens.len() - 1].seq,
+                            last_birth: gens.iter().map(|g| g.birth).max().unwrap(),
+                            min_lru_index: 0,
+                            lru,
+                        },
+                    );
+                }
+            }
+
+            ret.insert(path.clone(), (m.id, m.ino, nodes));
+        }
+
+        Ok(ret)
+    }
+
+    // Like inc_max_seq of Linux kernel, the oldest generation is moved into
+    // the next one if the generations are full.
+    fn run_aging(
+        &self,
+        _path: &str,
+        memcg_id: usize,
+        numa_id: usize,
+        max_seq: u64,
+        _can_swap: bool,
+        _force_scan: bool,
+    ) -> Result<()> {
+        self.op_start();
+        let mut state = self.state.lock().unwrap();
+        state.take_error(SimOp::Aging)?;
+
+        let (_, m) = state.memcg_by_id(memcg_id)?;
+        let gens = m
+            .nodes
+            .get_mut(&numa_id)
+            .ok_or_else(|| anyhow!("memcg {} numa {} is not exist", memcg_id, numa_id))?;
+
+        let cur_max_seq = gens.last().map(|g| g.seq).unwrap_or(0);
+        if cur_max_seq != max_seq {
+            return Err(anyhow!(
+                "max_seq {} is not current max_seq {}",
+                max_seq,
+                cur_max_seq
+            ));
+        }
+
+        if gens.len() as u64 >= MAX_NR_GENS {
+            let oldest = gens.remove(0);
+            gens[0].anon += oldest.anon;
+            gens[0].file += oldest.file;
+        }
+        gens.push(SimGen {
+            seq: max_seq + 1,
+            anon: 0,
+            file: 0,
+            birth: Utc::now(),
+        });
+
+        Ok(())
+    }
+
+    // Reclaim at most evict_batch pages from the oldest generation.
+    // swappiness decides the part of anon pages.
+    fn run_eviction(
+        &self,
+        _path: &str,
+        memcg_id: usize,
+        numa_id: usize,
+        min_seq: u64,
+        swappiness: u8,
+        _nr_to_reclaim: usize,
+    ) -> Result<()> {
+        self.op_start();
+        let mut state = self.state.lock().unwrap();
+        state.take_error(SimOp::Eviction)?;
+
+        let batch = state.evict_batch;
+        let (path, m) = state.memcg_by_id(memcg_id)?;
+        let gens = m
+            .nodes
+            .get_mut(&numa_id)
+            .ok_or_else(|| anyhow!("memcg {} numa {} is not exist", memcg_id, numa_id))?;
+
+        let max_seq = gens.last().map(|g| g.seq).unwrap_or(0);
+        if min_seq + MIN_NR_GENS > max_seq {
+            return Err(anyhow!(
+                "min_seq {} max_seq {} is not right",
+                min_seq,
+                max_seq
+            ));
+        }
+
+        if gens[0].seq <= min_seq {
+            let anon_batch = batch * swappiness as u64 / 200;
+            let file_batch = batch - anon_batch;
+            let oldest = &mut gens[0];
+            oldest.anon -= std::cmp::min(oldest.anon, anon_batch);
+            oldest.file -= std::cmp::min(oldest.file, file_batch);
+            if oldest.anon == 0 && oldest.file == 0 && gens.len() as u64 > MIN_NR_GENS {
+                gens.remove(0);
+            }
+        }
+
+        if m.eviction_psi_us > 0 {
+            m.psi_total += m.eviction_psi_us;
+            let total = m.psi_total;
+            state.write_psi(&path, total)?;
+            // psi percent is got from the wall time, let the eviction take
+            // some time.
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        Ok(())
+    }
+}
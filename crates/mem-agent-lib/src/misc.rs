@@ -41,25 +41,41 @@ macro_rules! debug {
     }
 }
 
+// The directory of the fake files of a test. It is a temporary directory
+// that is removed when dropped, even if the test panics.
 #[cfg(test)]
-pub fn is_test_environment() -> bool {
-    true
-}
+pub struct TestDir(tempfile::TempDir);
 
-#[cfg(not(test))]
-pub fn is_test_environment() -> bool {
-    false
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        TestDir(
+            tempfile::Builder::new()
+                .prefix(&format!("{}.", name))
+                .tempdir()
+                .unwrap(),
+        )
+    }
 }
 
 #[cfg(test)]
-mod tests {
-    use super::*;
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
 
-    #[test]
-    fn test_environment_check() {
-        assert!(is_test_environment());
+    fn deref(&self) -> &std::path::Path {
+        self.0.path()
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TestDir {
+    fn as_ref(&self) -> &std::path::Path {
+        self.0.path()
     }
+}
 
+#[cfg(test)]
+mod tests {
     #[test]
     fn test_log_macro() {
         error!("error");
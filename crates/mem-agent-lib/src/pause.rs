@@ -0,0 +1,144 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+// Pause stops the work of memcg or compact until it is resumed or
+// expires. The config is kept.
+
+use crate::info;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration as TokioDuration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseTarget {
+    All,
+    Memcg,
+    Compact,
+}
+
+impl PauseTarget {
+    pub fn memcg(&self) -> bool {
+        *self != PauseTarget::Compact
+    }
+
+    pub fn compact(&self) -> bool {
+        *self != PauseTarget::Memcg
+    }
+}
+
+impl FromStr for PauseTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "all" => Ok(PauseTarget::All),
+            "memcg" => Ok(PauseTarget::Memcg),
+            "compact" => Ok(PauseTarget::Compact),
+            _ => Err(anyhow!("pause target {} is invalid", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PauseStatus {
+    pub paused: bool,
+    // None means paused until resumed
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Pause {
+    status: Arc<Mutex<PauseStatus>>,
+}
+
+impl Pause {
+    pub fn pause(&self, until: Option<DateTime<Utc>>) {
+        info!("pause until {:?}", until);
+        *self.status.lock().unwrap() = PauseStatus {
+            paused: true,
+            until,
+        };
+    }
+
+    pub fn resume(&self) {
+        info!("resume");
+        *self.status.lock().unwrap() = PauseStatus::default();
+    }
+
+    pub fn status(&self) -> PauseStatus {
+        let mut status = self.status.lock().unwrap();
+        if let Some(until) = status.until {
+            if status.paused && Utc::now() >= until {
+                info!("pause expired at {}", until);
+                *status = PauseStatus::default();
+            }
+        }
+        *status
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.status().paused
+    }
+
+    // Delay d until the pause expires.
+    pub fn delay(&self, d: TokioDuration) -> TokioDuration {
+        let status = self.status();
+        if !status.paused {
+            return d;
+        }
+
+        match status.until {
+            Some(until) => {
+                let wait = (until - Utc::now()).to_std().unwrap_or_default();
+                std::cmp::max(d, wait)
+            }
+            None => TokioDuration::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration as ChronoDuration;
+
+    #[test]
+    fn test_pause() {
+        let pause = Pause::default();
+        let cloned = pause.clone();
+        assert!(!pause.is_paused());
+        assert_eq!(pause.delay(TokioDuration::ZERO), TokioDuration::ZERO);
+
+        cloned.pause(None);
+        assert!(pause.is_paused());
+        assert_eq!(pause.delay(TokioDuration::ZERO), TokioDuration::MAX);
+
+        cloned.pause(Some(Utc::now() + ChronoDuration::seconds(60)));
+        assert!(pause.status().until.is_some());
+        let d = pause.delay(TokioDuration::ZERO);
+        assert!(d > TokioDuration::from_secs(50) && d <= TokioDuration::from_secs(60));
+        assert_eq!(
+            pause.delay(TokioDuration::from_secs(120)),
+            TokioDuration::from_secs(120)
+        );
+
+        cloned.resume();
+        assert!(!pause.is_paused());
+
+        // Expired
+        cloned.pause(Some(Utc::now() - ChronoDuration::seconds(1)));
+        assert_eq!(pause.status(), PauseStatus::default());
+    }
+
+    #[test]
+    fn test_pause_target_from_str() {
+        assert_eq!(PauseTarget::from_str("all").unwrap(), PauseTarget::All);
+        assert!(PauseTarget::from_str("memcg").unwrap().memcg());
+        assert!(!PauseTarget::from_str("memcg").unwrap().compact());
+        assert!(PauseTarget::from_str("compact").unwrap().compact());
+        assert!(PauseTarget::from_str("foo").is_err());
+    }
+}
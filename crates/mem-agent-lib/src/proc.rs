@@ -2,12 +2,15 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::hostfs::HostFs;
 use anyhow::{anyhow, Result};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-fn get_meminfo(opt: &str) -> Result<u64> {
-    let file = File::open("/proc/meminfo")?;
+const MEMINFO_PATH: &str = "/proc/meminfo";
+
+fn get_meminfo(host: &HostFs, opt: &str) -> Result<u64> {
+    let file = File::open(host.path(MEMINFO_PATH))?;
     let reader = BufReader::new(file);
 
     for line in reader.lines() {
@@ -24,12 +27,31 @@ fn get_meminfo(opt: &str) -> Result<u64> {
     Err(anyhow!("no {} found", opt))
 }
 
-pub fn get_memfree_kb() -> Result<u64> {
-    get_meminfo("MemFree:")
+pub fn get_memfree_kb(host: &HostFs) -> Result<u64> {
+    get_meminfo(host, "MemFree:")
+}
+
+// The lines of the meminfo of a numa node are like
+// "Node 0 MemFree:         123 kB".
+pub fn get_node_memfree_kb(host: &HostFs, node: u32) -> Result<u64> {
+    let path = format!("/sys/devices/system/node/node{}/meminfo", node);
+    let file = File::open(host.path(&path))?;
+    let reader = BufReader::new(file);
+
+    for line in reader.lines() {
+        let line = line?;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 4 && parts[2] == "MemFree:" {
+            let kb = parts[3].parse::<u64>()?;
+            return Ok(kb);
+        }
+    }
+
+    Err(anyhow!("no MemFree: found in {}", path))
 }
 
-pub fn get_freeswap_kb() -> Result<u64> {
-    get_meminfo("SwapFree:")
+pub fn get_freeswap_kb(host: &HostFs) -> Result<u64> {
+    get_meminfo(host, "SwapFree:")
 }
 
 #[cfg(test)]
@@ -38,7 +60,7 @@ mod tests {
 
     #[test]
     fn test_get_memfree_kb() {
-        let memfree_kb = get_memfree_kb().unwrap();
+        let memfree_kb = get_memfree_kb(&HostFs::default()).unwrap();
         assert!(memfree_kb > 0);
     }
 }
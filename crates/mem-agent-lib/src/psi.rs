@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::cgroup::CGROUP_PATH;
+use crate::hostfs::HostFs;
 use crate::info;
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
@@ -15,9 +16,10 @@ use std::path::PathBuf;
 const MEM_PSI: &str = "memory.pressure";
 const IO_PSI: &str = "io.pressure";
 
-fn find_psi_subdirs() -> Result<PathBuf> {
-    if PathBuf::from(CGROUP_PATH).is_dir() {
-        for entry in fs::read_dir(CGROUP_PATH)? {
+fn find_psi_subdirs(host: &HostFs) -> Result<PathBuf> {
+    let cgroup_path = host.path(CGROUP_PATH);
+    if cgroup_path.is_dir() {
+        for entry in fs::read_dir(&cgroup_path)? {
             let entry = entry?;
             let path = entry.path();
             if path.is_dir() {
@@ -27,19 +29,16 @@ fn find_psi_subdirs() -> Result<PathBuf> {
             }
         }
 
-        Err(anyhow!("cannot find cpuacct dir in {}", CGROUP_PATH))
+        Err(anyhow!("cannot find cpuacct dir in {:?}", cgroup_path))
     } else {
-        Err(anyhow!("{} is not a directory", CGROUP_PATH))
+        Err(anyhow!("{:?} is not a directory", cgroup_path))
     }
 }
 
-pub fn check(psi_path: &PathBuf) -> Result<PathBuf> {
-    if crate::misc::is_test_environment() {
-        return Ok(psi_path.clone());
-    }
-
+// psi_path is the path inside host.
+pub fn check(host: &HostFs, psi_path: &PathBuf) -> Result<PathBuf> {
     let p = if psi_path.as_os_str().is_empty() {
-        find_psi_subdirs().map_err(|e| anyhow!("find_psi_subdirs failed: {}", e))?
+        find_psi_subdirs(host).map_err(|e| anyhow!("find_psi_subdirs failed: {}", e))?
     } else {
         psi_path.clone()
     };
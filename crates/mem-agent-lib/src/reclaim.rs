@@ -0,0 +1,391 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+// ReclaimBackend does proactive reclaim through memory.reclaim of cgroup v2.
+// It is used when lru_gen debugfs is not available.
+// There are no generations, so the cold memory size is got from memory.stat:
+// the oldest generation is the inactive pages and the youngest one is the
+// active pages.
+
+use crate::cgroup::CGROUP_PATH;
+use crate::hostfs::HostFs;
+use crate::mglru::{Backend, GenLRU, HostMemcgs, MGenLRU, MAX_NR_GENS};
+use crate::{info, trace, warn};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use nix::errno::Errno;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Mutex;
+
+const MEMORY_RECLAIM: &str = "memory.reclaim";
+const MEMORY_STAT: &str = "memory.stat";
+// Pages that memory.reclaim is asked to reclaim for each nr_to_reclaim.
+const RECLAIM_BATCH_PAGES: u64 = 256;
+
+#[derive(Debug)]
+struct ReclaimState {
+    // path -> memcg id
+    // memory.reclaim doesn't need memcg id, just give each path a unique id.
+    ids: HashMap<String, usize>,
+    next_id: usize,
+    // path -> time of last run_aging
+    aging_time: HashMap<String, DateTime<Utc>>,
+    // If the kernel accepts "swappiness=" of memory.reclaim.
+    // None means not tried yet.
+    swappiness_supported: Option<bool>,
+}
+
+impl ReclaimState {
+    fn get_id(&mut self, path: &str) -> usize {
+        if let Some(id) = self.ids.get(path) {
+            return *id;
+        }
+
+        // Skip the ids that are still used by other paths after next_id
+        // wrapped around.
+        // Give up after a full round, that needs u16::MAX live paths.
+        let mut id = self.next_id;
+        for _ in 0..u16::MAX {
+            id = self.next_id;
+            self.advance_next_id();
+            if !self.ids.values().any(|v| *v == id) {
+                break;
+            }
+        }
+        self.ids.insert(path.to_string(), id);
+        id
+    }
+
+    fn advance_next_id(&mut self) {
+        // MemCgroup keeps the id as u16 and 0 is the id of disabled memcg.
+        self.next_id = if self.next_id >= u16::MAX as usize {
+            1
+        } else {
+            self.next_id + 1
+        };
+    }
+}
+
+#[derive(Debug)]
+pub struct ReclaimBackend {
+    host: HostFs,
+    state: Mutex<ReclaimState>,
+}
+
+fn memory_stat_parse(content: &str) -> HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let key = words.next()?;
+            let val = words.next()?.parse::<u64>().ok()?;
+            Some((key.to_string(), val))
+        })
+        .collect()
+}
+
+impl ReclaimBackend {
+    pub fn new(host: &HostFs) -> Self {
+        Self {
+            host: host.clone(),
+            state: Mutex::new(ReclaimState {
+                ids: HashMap::new(),
+                next_id: 1,
+                aging_time: HashMap::new(),
+                swappiness_supported: None,
+            }),
+        }
+    }
+
+    fn cgroup_dir(&self, path: &str) -> std::path::PathBuf {
+        self.host
+            .path(CGROUP_PATH)
+            .join(path.trim_start_matches('/'))
+    }
+
+    fn find_memcgs(&self, dir: &Path, path: &str, paths: &mut Vec<String>) -> Result<()> {
+        if dir.join(MEMORY_RECLAIM).is_file() {
+            paths.push(path.to_string());
+        }
+
+        for entry in fs::read_dir(dir).map_err(|e| anyhow!("read_dir {:?} failed: {}", dir, e))? {
+            let entry = entry.map_err(|e| anyhow!("read_dir {:?} failed: {}", dir, e))?;
+            if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            let child = if path == "/" {
+                format!("/{}", name)
+            } else {
+                format!("{}/{}", path, name)
+            };
+            self.find_memcgs(&entry.path(), &child, paths)?;
+        }
+
+        Ok(())
+    }
+
+    fn memcg_mglru(&self, path: &str, last_birth: DateTime<Utc>) -> Result<MGenLRU> {
+        let stat_path = self.cgroup_dir(path).join(MEMORY_STAT);
+        let content = fs::read_to_string(&stat_path)
+            .map_err(|e| anyhow!("read file {:?} failed: {}", stat_path, e))?;
+        let stat = memory_stat_parse(&content);
+        let page_size = page_size::get() as u64;
+        let get = |key: &str| stat.get(key).cloned().unwrap_or(0) / page_size;
+
+        let mut lru = Vec::new();
+        for seq in 0..MAX_NR_GENS {
+            let (anon, file) = if seq == 0 {
+                (get("inactive_anon"), get("inactive_file"))
+            } else if seq == MAX_NR_GENS - 1 {
+                (get("active_anon"), get("active_file"))
+            } else {
+                (0, 0)
+            };
+            lru.push(GenLRU {
+                seq,
+                anon,
+                file,
+                birth: last_birth,
+            });
+        }
+
+        Ok(MGenLRU {
+            min_seq: 0,
+            max_seq: MAX_NR_GENS - 1,
+            last_birth,
+            min_lru_index: 0,
+            lru,
+        })
+    }
+
+    fn write_reclaim(&self, path: &str, cmd: &str) -> std::io::Result<()> {
+        let reclaim_path = self.cgroup_dir(path).join(MEMORY_RECLAIM);
+        trace!("send cmd {} to {:?}", cmd, reclaim_path);
+        fs::write(reclaim_path, cmd)
+    }
+}
+
+impl Backend for ReclaimBackend {
+    fn check(&self) -> Result<()> {
+        let controllers_path = self.host.path(CGROUP_PATH).join("cgroup.controllers");
+        let controllers = fs::read_to_string(&controllers_path)
+            .map_err(|e| anyhow!("read file {:?} failed: {}", controllers_path, e))?;
+        if !controllers.split_whitespace().any(|c| c == "memory") {
+            return Err(anyhow!("memory controller is not available"));
+        }
+        // memory.reclaim was added in Linux 5.19.
+        let reclaim_path = self.host.path(CGROUP_PATH).join(MEMORY_RECLAIM);
+        if !reclaim_path.exists() {
+            return Err(anyhow!("{:?} is not available", reclaim_path));
+        }
+
+        Ok(())
+    }
+
+    fn memcgs_get(&self, target_patchs: &HashSet<String>, parse_line: bool) -> Result<HostMemcgs> {
+        let paths = if target_patchs.is_empty() {
+            let mut paths = Vec::new();
+            self.find_memcgs(&self.host.path(CGROUP_PATH), "/", &mut paths)
+                .map_err(|e| anyhow!("find_memcgs failed: {}", e))?;
+            paths
+        } else {
+            target_patchs.iter().cloned().collect()
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let mut host_mgs = HashMap::new();
+        for path in paths {
+            let metadata = match fs::metadata(self.cgroup_dir(&path)) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+
+            let mut hmg = HashMap::new();
+            if parse_line {
+                let last_birth = state
+                    .aging_time
+                    .get(&path)
+                    .cloned()
+                    .unwrap_or_else(|| Utc.timestamp_opt(0, 0).unwrap());
+                match self.memcg_mglru(&path, last_birth) {
+                    // memory.reclaim cannot reclaim a special numa node.
+                    Ok(mglru) => {
+                        hmg.insert(0, mglru);
+                    }
+                    Err(e) => {
+                        warn!("memcg_mglru {} failed: {}", path, e);
+                        continue;
+                    }
+                }
+            }
+
+            let id = state.get_id(&path);
+            host_mgs.insert(path, (id, metadata.ino() as usize, hmg));
+        }
+
+        let found: HashSet<&String> = host_mgs.keys().collect();
+        if target_patchs.is_empty() {
+            state.ids.retain(|path, _| found.contains(path));
+            state.aging_time.retain(|path, _| found.contains(path));
+        }
+
+        Ok(host_mgs)
+    }
+
+    // The kernel ages the LRU when reclaiming, just record the time to keep
+    // the interval of aging same as lru_gen.
+    fn run_aging(
+        &self,
+        path: &str,
+        _memcg_id: usize,
+        _numa_id: usize,
+        _max_seq: u64,
+        _can_swap: bool,
+        _force_scan: bool,
+    ) -> Result<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .aging_time
+            .insert(path.to_string(), Utc::now());
+        Ok(())
+    }
+
+    fn run_eviction(
+        &self,
+        path: &str,
+        _memcg_id: usize,
+        _numa_id: usize,
+        _min_seq: u64,
+        swappiness: u8,
+        nr_to_reclaim: usize,
+    ) -> Result<()> {
+        let bytes = nr_to_reclaim as u64 * RECLAIM_BATCH_PAGES * page_size::get() as u64;
+
+        let swappiness_supported = self.state.lock().unwrap().swappiness_supported;
+        let ret = if swappiness_supported != Some(false) {
+            let cmd = format!("{} swappiness={}", bytes, swappiness);
+            match self.write_reclaim(path, &cmd) {
+                Err(e) if e.raw_os_error() == Some(Errno::EINVAL as i32) => {
+                    if swappiness_supported.is_none() {
+                        info!("memory.reclaim doesn't support swappiness");
+                        self.state.lock().unwrap().swappiness_supported = Some(false);
+                        self.write_reclaim(path, &bytes.to_string())
+                    } else {
+                        Err(e)
+                    }
+                }
+                r => {
+                    if r.is_ok() && swappiness_supported.is_none() {
+                        self.state.lock().unwrap().swappiness_supported = Some(true);
+                    }
+                    r
+                }
+            }
+        } else {
+            self.write_reclaim(path, &bytes.to_string())
+        };
+
+        match ret {
+            // memory.reclaim returns EAGAIN if it cannot reclaim all the
+            // bytes, the caller will find it from memory.stat.
+            Err(e) if e.raw_os_error() == Some(Errno::EAGAIN as i32) => Ok(()),
+            r => r.map_err(|e| anyhow!("write {} {} failed: {}", path, MEMORY_RECLAIM, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::TestDir;
+
+    fn setup_fake_cgroup(name: &str) -> TestDir {
+        let root = TestDir::new(name);
+        let cgroup = root.join("sys/fs/cgroup");
+        fs::create_dir_all(cgroup.join("a/b")).unwrap();
+        fs::write(cgroup.join("cgroup.controllers"), "cpu io memory\n").unwrap();
+        fs::write(cgroup.join(MEMORY_RECLAIM), "").unwrap();
+        let page_size = page_size::get() as u64;
+        for (dir, n) in [(cgroup.join("a"), 1), (cgroup.join("a/b"), 2)] {
+            fs::write(dir.join(MEMORY_RECLAIM), "").unwrap();
+            fs::write(
+                dir.join(MEMORY_STAT),
+                format!(
+                    "anon 0\ninactive_anon {}\nactive_anon {}\ninactive_file {}\nactive_file {}\n",
+                    n * page_size,
+                    2 * n * page_size,
+                    3 * n * page_size,
+                    4 * n * page_size
+                ),
+            )
+            .unwrap();
+        }
+
+        root
+    }
+
+    #[test]
+    fn test_reclaim_backend() {
+        let root = setup_fake_cgroup("test_reclaim_backend");
+        let rb = ReclaimBackend::new(&HostFs::new(&root));
+        rb.check().unwrap();
+
+        let mgs = rb.memcgs_get(&HashSet::new(), true).unwrap();
+        assert_eq!(mgs.len(), 2);
+        let (id, _, hmg) = mgs.get("/a/b").unwrap();
+        assert_ne!(*id, 0);
+        let mglru = hmg.get(&0).unwrap();
+        assert_eq!(mglru.max_seq - mglru.min_seq + 1, MAX_NR_GENS);
+        assert_eq!((mglru.lru[0].anon, mglru.lru[0].file), (2, 6));
+        assert_eq!((mglru.lru[3].anon, mglru.lru[3].file), (4, 8));
+
+        let targets = ["/a".to_string()].iter().cloned().collect();
+        let mgs_a = rb.memcgs_get(&targets, true).unwrap();
+        assert_eq!(mgs_a.len(), 1);
+        assert_eq!(mgs_a.get("/a").unwrap().0, mgs.get("/a").unwrap().0);
+
+        rb.run_aging("/a", 0, 0, 3, false, true).unwrap();
+        let mgs_a = rb.memcgs_get(&targets, true).unwrap();
+        assert!(
+            mgs_a.get("/a").unwrap().2.get(&0).unwrap().last_birth
+                > Utc.timestamp_opt(0, 0).unwrap()
+        );
+
+        rb.run_eviction("/a", 0, 0, 0, 10, 1).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("sys/fs/cgroup/a").join(MEMORY_RECLAIM)).unwrap(),
+            format!(
+                "{} swappiness=10",
+                RECLAIM_BATCH_PAGES * page_size::get() as u64
+            )
+        );
+        assert!(rb.run_eviction("/c", 0, 0, 0, 10, 1).is_err());
+
+        // The kernel doesn't have memory.reclaim.
+        fs::remove_file(root.join("sys/fs/cgroup").join(MEMORY_RECLAIM)).unwrap();
+        assert!(rb.check().is_err());
+    }
+
+    #[test]
+    fn test_reclaim_state_id_wrap() {
+        let mut state = ReclaimState {
+            ids: HashMap::new(),
+            next_id: u16::MAX as usize,
+            aging_time: HashMap::new(),
+            swappiness_supported: None,
+        };
+        state.ids.insert("/a".to_string(), 1);
+        state.ids.insert("/b".to_string(), 2);
+
+        assert_eq!(state.get_id("/c"), u16::MAX as usize);
+        // 1 and 2 are still used by /a and /b.
+        assert_eq!(state.get_id("/d"), 3);
+        assert_eq!(state.get_id("/a"), 1);
+    }
+}
@@ -51,6 +51,10 @@ impl Timeout {
         chrono_to_tokio_duration(self.start_wait_time + self.sleep_duration - now)
     }
 
+    pub fn timeout_time(&self) -> DateTime<Utc> {
+        self.start_wait_time + self.sleep_duration
+    }
+
     pub fn set_sleep_duration(&mut self, secs: u64) {
         self.sleep_duration = ChronoDuration::microseconds(secs as i64 * 1000000);
     }
@@ -0,0 +1,615 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::option::{CompactSetOption, MemcgSetupOption};
+use anyhow::{anyhow, Result};
+use mem_agent_lib::{agent, compact, memcg};
+use serde::Deserialize;
+use slog_scope::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The drop-in directory that is placed next to the config file.
+const CONF_D_DIR: &str = "conf.d";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MemcgCgroupFileConfig {
+    path: String,
+    numa_id: Vec<u32>,
+    no_subdir: Option<bool>,
+    disabled: Option<bool>,
+    swap: Option<bool>,
+    swappiness_max: Option<u8>,
+    period_secs: Option<u64>,
+    period_psi_percent_limit: Option<u8>,
+    eviction_psi_percent_limit: Option<u8>,
+    eviction_run_aging_count_min: Option<u64>,
+    eviction_backend: Option<String>,
+    memory_high: Option<bool>,
+    memory_high_window_secs: Option<u64>,
+    memory_high_headroom_percent: Option<u8>,
+    memory_high_psi_percent_limit: Option<u8>,
+    reclaim_target_bytes: Option<u64>,
+    reclaim_target_percent: Option<u8>,
+    reclaim_rate_limit_bytes_per_sec: Option<u64>,
+    cold_age_secs: Option<u64>,
+    aging_force_scan: Option<bool>,
+    aging_idle_fresh_ignore_secs: Option<u64>,
+    eviction_nr_gens_min: Option<u64>,
+    dry_run: Option<bool>,
+}
+
+impl MemcgCgroupFileConfig {
+    fn to_single_option_config(&self) -> Result<memcg::SingleOptionConfig> {
+        Ok(memcg::SingleOptionConfig {
+            disabled: self.disabled,
+            swap: self.swap,
+            swappiness_max: self.swappiness_max,
+            period_secs: self.period_secs,
+            period_psi_percent_limit: self.period_psi_percent_limit,
+            eviction_psi_percent_limit: self.eviction_psi_percent_limit,
+            eviction_run_aging_count_min: self.eviction_run_aging_count_min,
+            eviction_backend: self
+                .eviction_backend
+                .as_deref()
+                .map(str::parse)
+                .transpose()?,
+            memory_high: self.memory_high,
+            memory_high_window_secs: self.memory_high_window_secs,
+            memory_high_headroom_percent: self.memory_high_headroom_percent,
+            memory_high_psi_percent_limit: self.memory_high_psi_percent_limit,
+            reclaim_target_bytes: self.reclaim_target_bytes,
+            reclaim_target_percent: self.reclaim_target_percent,
+            reclaim_rate_limit_bytes_per_sec: self.reclaim_rate_limit_bytes_per_sec,
+            cold_age_secs: self.cold_age_secs,
+            aging_force_scan: self.aging_force_scan,
+            aging_idle_fresh_ignore_secs: self.aging_idle_fresh_ignore_secs,
+            eviction_nr_gens_min: self.eviction_nr_gens_min,
+            dry_run: self.dry_run,
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MemcgFileConfig {
+    disabled: Option<bool>,
+    swap: Option<bool>,
+    swappiness_max: Option<u8>,
+    period_secs: Option<u64>,
+    period_psi_percent_limit: Option<u8>,
+    eviction_psi_percent_limit: Option<u8>,
+    eviction_run_aging_count_min: Option<u64>,
+    eviction_backend: Option<String>,
+    memory_high: Option<bool>,
+    memory_high_window_secs: Option<u64>,
+    memory_high_headroom_percent: Option<u8>,
+    memory_high_psi_percent_limit: Option<u8>,
+    reclaim_target_bytes: Option<u64>,
+    reclaim_target_percent: Option<u8>,
+    reclaim_rate_limit_bytes_per_sec: Option<u64>,
+    cold_age_secs: Option<u64>,
+    aging_force_scan: Option<bool>,
+    aging_idle_fresh_ignore_secs: Option<u64>,
+    eviction_nr_gens_min: Option<u64>,
+    dry_run: Option<bool>,
+    eviction_workers: Option<usize>,
+    eviction_workers_cpu_percent: Option<u32>,
+    cgroups: Vec<MemcgCgroupFileConfig>,
+}
+
+impl MemcgFileConfig {
+    fn to_single_option_config(&self) -> Result<memcg::SingleOptionConfig> {
+        Ok(memcg::SingleOptionConfig {
+            disabled: self.disabled,
+            swap: self.swap,
+            swappiness_max: self.swappiness_max,
+            period_secs: self.period_secs,
+            period_psi_percent_limit: self.period_psi_percent_limit,
+            eviction_psi_percent_limit: self.eviction_psi_percent_limit,
+            eviction_run_aging_count_min: self.eviction_run_aging_count_min,
+            eviction_backend: self
+                .eviction_backend
+                .as_deref()
+                .map(str::parse)
+                .transpose()?,
+            memory_high: self.memory_high,
+            memory_high_window_secs: self.memory_high_window_secs,
+            memory_high_headroom_percent: self.memory_high_headroom_percent,
+            memory_high_psi_percent_limit: self.memory_high_psi_percent_limit,
+            reclaim_target_bytes: self.reclaim_target_bytes,
+            reclaim_target_percent: self.reclaim_target_percent,
+            reclaim_rate_limit_bytes_per_sec: self.reclaim_rate_limit_bytes_per_sec,
+            cold_age_secs: self.cold_age_secs,
+            aging_force_scan: self.aging_force_scan,
+            aging_idle_fresh_ignore_secs: self.aging_idle_fresh_ignore_secs,
+            eviction_nr_gens_min: self.eviction_nr_gens_min,
+            dry_run: self.dry_run,
+        })
+    }
+
+    // The values of self override the values already in config.
+    // A cgroup entry with the same path and numa_id replaces the old one.
+    fn apply(&self, config: &mut memcg::Config) -> Result<()> {
+        config.default.set(&self.to_single_option_config()?);
+        if let Some(v) = self.eviction_workers {
+            config.eviction_workers = v;
+        }
+        if let Some(v) = self.eviction_workers_cpu_percent {
+            config.eviction_workers_cpu_percent = v;
+        }
+
+        for cg in &self.cgroups {
+            if cg.path.is_empty() {
+                return Err(anyhow!("path of memcg cgroups is required"));
+            }
+
+            let mut cc = memcg::CgroupConfig::default();
+            if let Some(no_subdir) = cg.no_subdir {
+                cc.no_subdir = no_subdir;
+            }
+            cc.numa_id = cg.numa_id.clone();
+            cc.numa_id.sort_unstable();
+            cc.config.set(&cg.to_single_option_config()?);
+
+            let ccs = config.cgroups.entry(cg.path.clone()).or_default();
+            ccs.retain(|c| c.numa_id != cc.numa_id);
+            ccs.push(cc);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CompactFileConfig {
+    disabled: Option<bool>,
+    period_secs: Option<u64>,
+    period_psi_percent_limit: Option<u8>,
+    compact_psi_percent_limit: Option<u8>,
+    compact_sec_max: Option<i64>,
+    compact_order: Option<u8>,
+    compact_threshold: Option<u64>,
+    compact_force_times: Option<u64>,
+    dry_run: Option<bool>,
+    per_node: Option<bool>,
+    nodes_include: Option<Vec<u32>>,
+    nodes_exclude: Option<Vec<u32>>,
+    mode: Option<String>,
+    proactiveness_min: Option<u8>,
+    proactiveness_max: Option<u8>,
+    proactiveness_step: Option<u8>,
+}
+
+impl CompactFileConfig {
+    fn apply(&self, config: &mut compact::Config) -> Result<()> {
+        if let Some(v) = self.disabled {
+            config.disabled = v;
+        }
+        if let Some(v) = self.period_secs {
+            config.period_secs = v;
+        }
+        if let Some(v) = self.period_psi_percent_limit {
+            config.period_psi_percent_limit = v;
+        }
+        if let Some(v) = self.compact_psi_percent_limit {
+            config.compact_psi_percent_limit = v;
+        }
+        if let Some(v) = self.compact_sec_max {
+            config.compact_sec_max = v;
+        }
+        if let Some(v) = self.compact_order {
+            config.compact_order = v;
+        }
+        if let Some(v) = self.compact_threshold {
+            config.compact_threshold = v;
+        }
+        if let Some(v) = self.compact_force_times {
+            config.compact_force_times = v;
+        }
+        if let Some(v) = self.dry_run {
+            config.dry_run = v;
+        }
+        if let Some(v) = self.per_node {
+            config.per_node = v;
+        }
+        if let Some(v) = &self.nodes_include {
+            config.nodes_include = v.clone();
+        }
+        if let Some(v) = &self.nodes_exclude {
+            config.nodes_exclude = v.clone();
+        }
+        if let Some(v) = &self.mode {
+            config.mode = v.parse()?;
+        }
+        if let Some(v) = self.proactiveness_min {
+            config.proactiveness_min = v;
+        }
+        if let Some(v) = self.proactiveness_max {
+            config.proactiveness_max = v;
+        }
+        if let Some(v) = self.proactiveness_step {
+            config.proactiveness_step = v;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    memcg: MemcgFileConfig,
+    compact: CompactFileConfig,
+}
+
+fn parse_file(path: &Path) -> Result<FileConfig> {
+    let content =
+        fs::read_to_string(path).map_err(|e| anyhow!("read file {:?} fail: {}", path, e))?;
+    toml::from_str(&content).map_err(|e| anyhow!("parse file {:?} fail: {}", path, e))
+}
+
+// Return the config file and the toml files inside its conf.d directory.
+// The files inside conf.d are sorted by name.
+fn config_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![path.to_path_buf()];
+
+    let conf_d = path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(CONF_D_DIR);
+    if conf_d.is_dir() {
+        let mut drop_ins = Vec::new();
+        for entry in
+            fs::read_dir(&conf_d).map_err(|e| anyhow!("read_dir {:?} fail: {}", conf_d, e))?
+        {
+            let entry = entry.map_err(|e| anyhow!("read_dir {:?} fail: {}", conf_d, e))?;
+            let p = entry.path();
+            if p.is_file() && p.extension().is_some_and(|ext| ext == "toml") {
+                drop_ins.push(p);
+            }
+        }
+        drop_ins.sort();
+        files.extend(drop_ins);
+    }
+
+    Ok(files)
+}
+
+fn single_config_to_single_option_config(sc: &memcg::SingleConfig) -> memcg::SingleOptionConfig {
+    memcg::SingleOptionConfig {
+        disabled: Some(sc.disabled),
+        swap: Some(sc.swap),
+        swappiness_max: Some(sc.swappiness_max),
+        period_secs: Some(sc.period_secs),
+        period_psi_percent_limit: Some(sc.period_psi_percent_limit),
+        eviction_psi_percent_limit: Some(sc.eviction_psi_percent_limit),
+        eviction_run_aging_count_min: Some(sc.eviction_run_aging_count_min),
+        eviction_backend: Some(sc.eviction_backend),
+        memory_high: Some(sc.memory_high),
+        memory_high_window_secs: Some(sc.memory_high_window_secs),
+        memory_high_headroom_percent: Some(sc.memory_high_headroom_percent),
+        memory_high_psi_percent_limit: Some(sc.memory_high_psi_percent_limit),
+        reclaim_target_bytes: Some(sc.reclaim_target_bytes),
+        reclaim_target_percent: Some(sc.reclaim_target_percent),
+        reclaim_rate_limit_bytes_per_sec: Some(sc.reclaim_rate_limit_bytes_per_sec),
+        cold_age_secs: Some(sc.cold_age_secs),
+        aging_force_scan: Some(sc.aging_force_scan),
+        aging_idle_fresh_ignore_secs: Some(sc.aging_idle_fresh_ignore_secs),
+        eviction_nr_gens_min: Some(sc.eviction_nr_gens_min),
+        dry_run: Some(sc.dry_run),
+    }
+}
+
+fn cgroup_config_to_cgroup_option_config(
+    path: &str,
+    cc: &memcg::CgroupConfig,
+) -> memcg::CgroupOptionConfig {
+    memcg::CgroupOptionConfig {
+        path: path.to_string(),
+        numa_id: cc.numa_id.clone(),
+        no_subdir: Some(cc.no_subdir),
+        config: single_config_to_single_option_config(&cc.config),
+    }
+}
+
+// Get the memcg::OptionConfig that changes old to new.
+fn memcg_config_diff(old: &memcg::Config, new: &memcg::Config) -> memcg::OptionConfig {
+    let mut oc = memcg::OptionConfig {
+        default: single_config_to_single_option_config(&new.default),
+        eviction_workers: Some(new.eviction_workers),
+        eviction_workers_cpu_percent: Some(new.eviction_workers_cpu_percent),
+        ..Default::default()
+    };
+
+    for (path, old_ccs) in &old.cgroups {
+        let new_ccs = new.cgroups.get(path);
+        for old_cc in old_ccs {
+            if !new_ccs.is_some_and(|ccs| ccs.iter().any(|c| c.numa_id == old_cc.numa_id)) {
+                oc.del.push((path.clone(), old_cc.numa_id.clone()));
+            }
+        }
+    }
+
+    for (path, new_ccs) in &new.cgroups {
+        let old_ccs = old.cgroups.get(path);
+        for new_cc in new_ccs {
+            match old_ccs.and_then(|ccs| ccs.iter().find(|c| c.numa_id == new_cc.numa_id)) {
+                Some(old_cc) => {
+                    if old_cc != new_cc {
+                        oc.set
+                            .push(cgroup_config_to_cgroup_option_config(path, new_cc));
+                    }
+                }
+                None => oc
+                    .add
+                    .push(cgroup_config_to_cgroup_option_config(path, new_cc)),
+            }
+        }
+    }
+
+    oc
+}
+
+fn compact_config_to_compact_option_config(cc: &compact::Config) -> compact::OptionConfig {
+    compact::OptionConfig {
+        disabled: Some(cc.disabled),
+        period_secs: Some(cc.period_secs),
+        period_psi_percent_limit: Some(cc.period_psi_percent_limit),
+        compact_psi_percent_limit: Some(cc.compact_psi_percent_limit),
+        compact_sec_max: Some(cc.compact_sec_max),
+        compact_order: Some(cc.compact_order),
+        compact_threshold: Some(cc.compact_threshold),
+        compact_force_times: Some(cc.compact_force_times),
+        dry_run: Some(cc.dry_run),
+        per_node: Some(cc.per_node),
+        nodes_include: Some(cc.nodes_include.clone()),
+        nodes_exclude: Some(cc.nodes_exclude.clone()),
+        mode: Some(cc.mode),
+        proactiveness_min: Some(cc.proactiveness_min),
+        proactiveness_max: Some(cc.proactiveness_max),
+        proactiveness_step: Some(cc.proactiveness_step),
+        ..Default::default()
+    }
+}
+
+// ConfigFile loads the memcg and compact config from a toml file and
+// the drop-in files of its conf.d directory.
+// The command line options of mem-agent-srv override the values of the
+// files each time the files are loaded.
+#[derive(Debug)]
+pub struct ConfigFile {
+    path: PathBuf,
+    memcg_opt: MemcgSetupOption,
+    compact_opt: CompactSetOption,
+
+    // The configs that were applied last time.
+    memcg: memcg::Config,
+    compact: compact::Config,
+}
+
+impl ConfigFile {
+    pub fn load(
+        path: &Path,
+        memcg_opt: MemcgSetupOption,
+        compact_opt: CompactSetOption,
+    ) -> Result<Self> {
+        let mut cf = Self {
+            path: path.to_path_buf(),
+            memcg_opt,
+            compact_opt,
+            memcg: memcg::Config::default(),
+            compact: compact::Config::default(),
+        };
+
+        (cf.memcg, cf.compact) = cf.read()?;
+
+        Ok(cf)
+    }
+
+    fn read(&self) -> Result<(memcg::Config, compact::Config)> {
+        let mut memcg_config = memcg::Config::default();
+        let mut compact_config = compact::Config::default();
+
+        for f in config_files(&self.path)? {
+            info!("load config file {:?}", f);
+            let fc = parse_file(&f)?;
+            fc.memcg
+                .apply(&mut memcg_config)
+                .map_err(|e| anyhow!("file {:?}: {}", f, e))?;
+            fc.compact
+                .apply(&mut compact_config)
+                .map_err(|e| anyhow!("file {:?}: {}", f, e))?;
+        }
+
+        self.memcg_opt
+            .apply_to_mem_agent_memcg_config(&mut memcg_config);
+        self.compact_opt
+            .apply_to_mem_agent_compact_config(&mut compact_config);
+
+        Ok((memcg_config, compact_config))
+    }
+
+    pub fn memcg_config(&self) -> memcg::Config {
+        self.memcg.clone()
+    }
+
+    pub fn compact_config(&self) -> compact::Config {
+        self.compact.clone()
+    }
+
+    // Read the files again and apply the changes to agent.
+    // The memcg changes are diffed against the live config of agent
+    // because ctl might have changed it since the last load.
+    pub async fn reload(&mut self, agent: &agent::MemAgent) -> Result<()> {
+        let (memcg_config, compact_config) = self.read()?;
+
+        let live_memcg_config = agent
+            .memcg_get_config_async()
+            .await
+            .map_err(|e| anyhow!("agent.memcg_get_config_async fail: {}", e))?;
+        agent
+            .memcg_set_config_async(memcg_config_diff(&live_memcg_config, &memcg_config))
+            .await
+            .map_err(|e| anyhow!("agent.memcg_set_config_async fail: {}", e))?;
+        self.memcg = memcg_config;
+
+        agent
+            .compact_set_config_async(compact_config_to_compact_option_config(&compact_config))
+            .await
+            .map_err(|e| anyhow!("agent.compact_set_config_async fail: {}", e))?;
+        self.compact = compact_config;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let data = r#"
+[memcg]
+swap = true
+period_secs = 300
+cold_age_secs = 3600
+aging_force_scan = false
+eviction_workers = 4
+
+[[memcg.cgroups]]
+path = "/system.slice/ModemManager.service"
+numa_id = [2, 1]
+period_secs = 120
+reclaim_target_bytes = 268435456
+reclaim_rate_limit_bytes_per_sec = 20971520
+
+[[memcg.cgroups]]
+path = "/system.slice/bolt.service"
+disabled = true
+dry_run = true
+eviction_backend = "memory_reclaim"
+memory_high = true
+memory_high_headroom_percent = 10
+
+[compact]
+compact_order = 10
+dry_run = true
+per_node = true
+nodes_exclude = [1]
+mode = "proactive"
+proactiveness_max = 80
+"#;
+        let fc: FileConfig = toml::from_str(data).unwrap();
+        let mut memcg_config = memcg::Config::default();
+        fc.memcg.apply(&mut memcg_config).unwrap();
+        let mut compact_config = compact::Config::default();
+        fc.compact.apply(&mut compact_config).unwrap();
+
+        assert!(memcg_config.default.swap);
+        assert_eq!(memcg_config.default.period_secs, 300);
+        assert_eq!(memcg_config.default.cold_age_secs, 3600);
+        assert!(!memcg_config.default.aging_force_scan);
+        assert_eq!(memcg_config.eviction_workers, 4);
+        assert_eq!(memcg_config.eviction_workers_cpu_percent, 0);
+        let ccs = &memcg_config.cgroups["/system.slice/ModemManager.service"];
+        assert_eq!(ccs.len(), 1);
+        assert_eq!(ccs[0].numa_id, vec![1, 2]);
+        assert_eq!(ccs[0].config.period_secs, 120);
+        assert_eq!(ccs[0].config.reclaim_target_bytes, 256 << 20);
+        assert_eq!(ccs[0].config.reclaim_rate_limit_bytes_per_sec, 20 << 20);
+        assert!(
+            memcg_config.cgroups["/system.slice/bolt.service"][0]
+                .config
+                .disabled
+        );
+        assert_eq!(
+            memcg_config.cgroups["/system.slice/bolt.service"][0]
+                .config
+                .eviction_backend,
+            memcg::EvictionBackend::MemoryReclaim
+        );
+        let bolt = &memcg_config.cgroups["/system.slice/bolt.service"][0].config;
+        assert!(bolt.memory_high);
+        assert!(bolt.dry_run);
+        assert_eq!(bolt.memory_high_headroom_percent, 10);
+        assert_eq!(bolt.memory_high_window_secs, 600);
+        assert_eq!(compact_config.compact_order, 10);
+        assert!(compact_config.dry_run);
+        assert!(compact_config.per_node);
+        assert!(compact_config.nodes_include.is_empty());
+        assert_eq!(compact_config.nodes_exclude, vec![1]);
+        assert_eq!(compact_config.mode, compact::Mode::Proactive);
+        assert_eq!(compact_config.proactiveness_max, 80);
+
+        assert!(toml::from_str::<FileConfig>("[memcg]\nunknown = 1\n").is_err());
+        let fc: FileConfig = toml::from_str("[memcg]\neviction_backend = \"x\"\n").unwrap();
+        assert!(fc.memcg.apply(&mut memcg::Config::default()).is_err());
+        let fc: FileConfig = toml::from_str("[compact]\nmode = \"x\"\n").unwrap();
+        assert!(fc.compact.apply(&mut compact::Config::default()).is_err());
+    }
+
+    #[test]
+    fn test_memcg_config_diff() {
+        let mut old = memcg::Config::default();
+        old.cgroups
+            .insert("/a".to_string(), vec![memcg::CgroupConfig::default()]);
+        old.cgroups
+            .insert("/b".to_string(), vec![memcg::CgroupConfig::default()]);
+
+        let mut new = old.clone();
+        new.cgroups.remove("/a");
+        new.cgroups.get_mut("/b").unwrap()[0].config.swap = true;
+        new.cgroups
+            .insert("/c".to_string(), vec![memcg::CgroupConfig::default()]);
+
+        let oc = memcg_config_diff(&old, &new);
+        assert_eq!(oc.del, vec![("/a".to_string(), vec![])]);
+        assert_eq!(oc.add.len(), 1);
+        assert_eq!(oc.add[0].path, "/c");
+        assert_eq!(oc.set.len(), 1);
+        assert_eq!(oc.set[0].path, "/b");
+        assert_eq!(oc.set[0].config.swap, Some(true));
+    }
+
+    #[test]
+    fn test_config_file_conf_d() {
+        let dir = tempfile::Builder::new()
+            .prefix("test_config.")
+            .tempdir()
+            .unwrap();
+        fs::create_dir_all(dir.path().join("conf.d")).unwrap();
+        fs::write(
+            dir.path().join("config.toml"),
+            "[memcg]\nperiod_secs = 300\n[compact]\ndisabled = true\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("conf.d/10-memcg.toml"),
+            "[memcg]\nperiod_secs = 200\n[[memcg.cgroups]]\npath = \"/a\"\nswap = true\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("conf.d/20-memcg.toml"),
+            "[[memcg.cgroups]]\npath = \"/a\"\nswap = false\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("conf.d/ignore.conf"), "not toml").unwrap();
+
+        let cf = ConfigFile::load(
+            &dir.path().join("config.toml"),
+            MemcgSetupOption::default(),
+            CompactSetOption::default(),
+        )
+        .unwrap();
+        let memcg_config = cf.memcg_config();
+        assert_eq!(memcg_config.default.period_secs, 200);
+        assert_eq!(memcg_config.cgroups["/a"].len(), 1);
+        assert!(!memcg_config.cgroups["/a"][0].config.swap);
+        assert!(cf.compact_config().disabled);
+    }
+}
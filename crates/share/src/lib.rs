@@ -2,7 +2,9 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod config;
 pub mod logger;
+pub mod metrics;
 pub mod misc;
 pub mod option;
 pub mod rpc;
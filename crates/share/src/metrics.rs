@@ -0,0 +1,683 @@
+// Copyright (C) 2026 Ant group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::option::MetricsOption;
+use anyhow::{anyhow, Result};
+use mem_agent_lib::{agent, compact, memcg};
+use slog_scope::{debug, error, info};
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+const METRICS_PATH: &str = "/metrics";
+const HTTP_REQUEST_MAX: usize = 8192;
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    // For the HTTP listener.
+    OpenMetrics,
+    // For the node_exporter textfile collector.
+    Prometheus,
+}
+
+struct Encoder {
+    format: Format,
+    out: String,
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Encoder {
+    fn new(format: Format) -> Self {
+        Self {
+            format,
+            out: String::new(),
+        }
+    }
+
+    // The name of the samples of the family.
+    fn sample_name(&self, name: &str, metric_type: &str) -> String {
+        if metric_type == "counter" {
+            format!("{}_total", name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn family(&mut self, name: &str, metric_type: &str, help: &str) {
+        // The family name of a counter doesn't include the _total suffix in
+        // OpenMetrics but includes it in the Prometheus text format.
+        let family_name = if self.format == Format::OpenMetrics {
+            name.to_string()
+        } else {
+            self.sample_name(name, metric_type)
+        };
+        let _ = writeln!(self.out, "# TYPE {} {}", family_name, metric_type);
+        let _ = writeln!(self.out, "# HELP {} {}", family_name, help);
+    }
+
+    fn sample(&mut self, name: &str, metric_type: &str, labels: &[(&str, &str)], value: u64) {
+        let sample_name = self.sample_name(name, metric_type);
+        if labels.is_empty() {
+            let _ = writeln!(self.out, "{} {}", sample_name, value);
+        } else {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+                .collect();
+            let _ = writeln!(
+                self.out,
+                "{}{{{}}} {}",
+                sample_name,
+                labels.join(","),
+                value
+            );
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if self.format == Format::OpenMetrics {
+            self.out.push_str("# EOF\n");
+        }
+        self.out
+    }
+}
+
+// Sort the memcg status by path and numa id to keep the output stable.
+fn sorted_numa(mgs: &HashMap<String, memcg::MemCgroup>) -> Vec<(&String, String, &memcg::Numa)> {
+    let mut ret = Vec::new();
+    for (path, mcg) in mgs {
+        for (numa_id, n) in &mcg.numa {
+            ret.push((path, *numa_id, n));
+        }
+    }
+    ret.sort_by(|a, b| a.0.cmp(b.0).then(a.1.cmp(&b.1)));
+    ret.into_iter()
+        .map(|(path, numa_id, n)| (path, numa_id.to_string(), n))
+        .collect()
+}
+
+fn encode_memcg(e: &mut Encoder, mgs: &HashMap<String, memcg::MemCgroup>) {
+    let numas = sorted_numa(mgs);
+
+    let name = "mem_agent_memcg_eviction_pages";
+    e.family(name, "counter", "Pages released by eviction.");
+    for (path, numa, n) in &numas {
+        e.sample(
+            name,
+            "counter",
+            &[("path", path), ("numa", numa)],
+            n.eviction_count.page,
+        );
+    }
+
+    let name = "mem_agent_memcg_eviction_stop";
+    e.family(name, "counter", "Eviction stops by reason.");
+    for (path, numa, n) in &numas {
+        let ec = &n.eviction_count;
+        for (reason, value) in [
+            ("no_min_lru_file", ec.no_min_lru_file),
+            ("min_lru_inc", ec.min_lru_inc),
+            ("other_error", ec.other_error),
+            ("error", ec.error),
+            ("psi_exceeds_limit", ec.psi_exceeds_limit),
+            ("target_reached", ec.target_reached),
+            ("not_cold", ec.not_cold),
+            ("paused", ec.paused),
+            ("cancelled", ec.cancelled),
+        ] {
+            e.sample(
+                name,
+                "counter",
+                &[("path", path), ("numa", numa), ("reason", reason)],
+                value,
+            );
+        }
+    }
+
+    let name = "mem_agent_memcg_dry_run_eviction";
+    e.family(name, "counter", "Evictions skipped by dry run.");
+    for (path, numa, n) in &numas {
+        e.sample(
+            name,
+            "counter",
+            &[("path", path), ("numa", numa)],
+            n.dry_run.count,
+        );
+    }
+
+    let name = "mem_agent_memcg_dry_run_pages";
+    e.family(
+        name,
+        "counter",
+        "Pages of the oldest generation that dry run evictions would release.",
+    );
+    for (path, numa, n) in &numas {
+        for (lru, value) in [
+            ("anon", n.dry_run.anon_pages),
+            ("file", n.dry_run.file_pages),
+        ] {
+            e.sample(
+                name,
+                "counter",
+                &[("path", path), ("numa", numa), ("type", lru)],
+                value,
+            );
+        }
+    }
+
+    let name = "mem_agent_memcg_dry_run_swappiness";
+    e.family(
+        name,
+        "gauge",
+        "Swappiness that the last dry run eviction would use.",
+    );
+    for (path, numa, n) in &numas {
+        e.sample(
+            name,
+            "gauge",
+            &[("path", path), ("numa", numa)],
+            n.dry_run.last_swappiness as u64,
+        );
+    }
+
+    let mut paths: Vec<&String> = mgs.keys().collect();
+    paths.sort();
+
+    let name = "mem_agent_memcg_memory_high_bytes";
+    e.family(name, "gauge", "memory.high that mem-agent set.");
+    for path in &paths {
+        if let Some(v) = mgs[*path].memory_high {
+            e.sample(name, "gauge", &[("path", path)], v);
+        }
+    }
+
+    let name = "mem_agent_memcg_dry_run_memory_high_bytes";
+    e.family(
+        name,
+        "gauge",
+        "memory.high that mem-agent would set if not dry run.",
+    );
+    for path in &paths {
+        if let Some(v) = mgs[*path].dry_run_memory_high {
+            e.sample(name, "gauge", &[("path", path)], v);
+        }
+    }
+
+    let name = "mem_agent_memcg_run_aging";
+    e.family(name, "counter", "Aging runs.");
+    for (path, numa, n) in &numas {
+        e.sample(
+            name,
+            "counter",
+            &[("path", path), ("numa", numa)],
+            n.run_aging_count,
+        );
+    }
+
+    let name = "mem_agent_memcg_sleep_psi_exceeds_limit";
+    e.family(
+        name,
+        "counter",
+        "Run periods skipped because period psi exceeds limit.",
+    );
+    for (path, numa, n) in &numas {
+        e.sample(
+            name,
+            "counter",
+            &[("path", path), ("numa", numa)],
+            n.sleep_psi_exceeds_limit,
+        );
+    }
+}
+
+fn encode_compact(e: &mut Encoder, status: &compact::Status) {
+    let name = "mem_agent_compact_outcome";
+    e.family(name, "counter", "Compact run periods by outcome.");
+    let c = &status.count;
+    for (outcome, value) in [
+        ("psi_skipped", c.psi_skipped),
+        ("threshold_skipped", c.threshold_skipped),
+        ("done", c.done),
+        ("sec_max_killed", c.sec_max_killed),
+        ("psi_killed", c.psi_killed),
+        ("dry_run", c.dry_run),
+        ("pause_killed", c.pause_killed),
+        ("proactive_raised", c.proactive_raised),
+        ("proactive_lowered", c.proactive_lowered),
+        ("proactive_psi_lowered", c.proactive_psi_lowered),
+        ("proactive_paused", c.proactive_paused),
+    ] {
+        e.sample(name, "counter", &[("outcome", outcome)], value);
+    }
+
+    let name = "mem_agent_compact_forced";
+    e.family(name, "counter", "Forced compactions.");
+    e.sample(name, "counter", &[], c.forced);
+
+    if let Some(r) = &status.last_run {
+        let name = "mem_agent_compact_last_duration_milliseconds";
+        e.family(name, "gauge", "Duration of the last compaction.");
+        e.sample(
+            name,
+            "gauge",
+            &[],
+            (r.end_time - r.start_time).num_milliseconds().max(0) as u64,
+        );
+
+        let name = "mem_agent_compact_last_free_movable_pages";
+        e.family(
+            name,
+            "gauge",
+            "Free movable pages before and after the last compaction.",
+        );
+        e.sample(
+            name,
+            "gauge",
+            &[("when", "before")],
+            r.free_movable_pages_before,
+        );
+        e.sample(
+            name,
+            "gauge",
+            &[("when", "after")],
+            r.free_movable_pages_after,
+        );
+    }
+}
+
+fn encode(
+    format: Format,
+    mgs: &HashMap<String, memcg::MemCgroup>,
+    compact_status: &compact::Status,
+) -> String {
+    let mut e = Encoder::new(format);
+    encode_memcg(&mut e, mgs);
+    encode_compact(&mut e, compact_status);
+    e.finish()
+}
+
+async fn gather(agent: &agent::MemAgent, format: Format) -> Result<String> {
+    let mgs = agent
+        .memcg_status_async()
+        .await
+        .map_err(|e| anyhow!("agent.memcg_status_async fail: {}", e))?;
+    let compact_status = agent
+        .compact_status_async()
+        .await
+        .map_err(|e| anyhow!("agent.compact_status_async fail: {}", e))?;
+
+    Ok(encode(format, &mgs, &compact_status))
+}
+
+async fn handle_http<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    agent: agent::MemAgent,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buf.len() >= HTTP_REQUEST_MAX {
+            return Err(anyhow!("http request is too big"));
+        }
+        let n = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|e| anyhow!("stream.read fail: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&buf);
+    let words: Vec<&str> = request
+        .lines()
+        .next()
+        .unwrap_or("")
+        .split_whitespace()
+        .collect();
+
+    let (status, content_type, body) = if words.len() < 2 || words[0] != "GET" {
+        ("405 Method Not Allowed", "text/plain", String::new())
+    } else if words[1] != METRICS_PATH {
+        ("404 Not Found", "text/plain", String::new())
+    } else {
+        match gather(&agent, Format::OpenMetrics).await {
+            Ok(body) => ("200 OK", OPENMETRICS_CONTENT_TYPE, body),
+            Err(e) => {
+                error!("metrics gather fail: {}", e);
+                (
+                    "500 Internal Server Error",
+                    "text/plain",
+                    format!("{}\n", e),
+                )
+            }
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| anyhow!("stream.write_all fail: {}", e))?;
+    stream
+        .shutdown()
+        .await
+        .map_err(|e| anyhow!("stream.shutdown fail: {}", e))?;
+
+    Ok(())
+}
+
+fn spawn_handle_http<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    stream: S,
+    agent: agent::MemAgent,
+) {
+    tokio::spawn(async move {
+        if let Err(e) = handle_http(stream, agent).await {
+            debug!("metrics handle_http fail: {}", e);
+        }
+    });
+}
+
+async fn write_textfile(agent: &agent::MemAgent, path: &Path) -> Result<()> {
+    let data = gather(agent, Format::Prometheus).await?;
+
+    // Write to a temporary file and rename it to make sure the collector
+    // never reads a partial file.
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{:?} is not a file", path))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    tokio::fs::write(&tmp_path, data)
+        .await
+        .map_err(|e| anyhow!("write {:?} fail: {}", tmp_path, e))?;
+    tokio::fs::rename(&tmp_path, path)
+        .await
+        .map_err(|e| anyhow!("rename {:?} to {:?} fail: {}", tmp_path, path, e))?;
+
+    Ok(())
+}
+
+// Exporter serves the metrics of agent over HTTP and writes them to the
+// textfile according to MetricsOption.
+pub struct Exporter {
+    unix_path: Option<PathBuf>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Exporter {
+    pub async fn start(agent: &agent::MemAgent, opt: &MetricsOption) -> Result<Self> {
+        let mut exporter = Self {
+            unix_path: None,
+            tasks: Vec::new(),
+        };
+
+        if let Some(addr) = &opt.metrics_addr {
+            if let Some(path) = addr.strip_prefix("unix://") {
+                if Path::new(path).exists() {
+                    return Err(anyhow!("metrics addr {} is exist", addr));
+                }
+                let listener = UnixListener::bind(path)
+                    .map_err(|e| anyhow!("UnixListener::bind {} fail: {}", path, e))?;
+                exporter.unix_path = Some(PathBuf::from(path));
+
+                let agent = agent.clone();
+                exporter.tasks.push(tokio::spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => spawn_handle_http(stream, agent.clone()),
+                            Err(e) => error!("metrics listener.accept fail: {}", e),
+                        }
+                    }
+                }));
+            } else if let Some(tcp_addr) = addr.strip_prefix("tcp://") {
+                let listener = TcpListener::bind(tcp_addr)
+                    .await
+                    .map_err(|e| anyhow!("TcpListener::bind {} fail: {}", tcp_addr, e))?;
+
+                let agent = agent.clone();
+                exporter.tasks.push(tokio::spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => spawn_handle_http(stream, agent.clone()),
+                            Err(e) => error!("metrics listener.accept fail: {}", e),
+                        }
+                    }
+                }));
+            } else {
+                return Err(anyhow!("format of metrics addr {} is not right", addr));
+            }
+
+            info!("metrics listen on {}", addr);
+        }
+
+        if let Some(path) = &opt.metrics_textfile {
+            let path = path.clone();
+            let interval = Duration::from_secs(opt.metrics_textfile_interval_secs);
+            let agent = agent.clone();
+            exporter.tasks.push(tokio::spawn(async move {
+                loop {
+                    if let Err(e) = write_textfile(&agent, &path).await {
+                        error!("metrics write_textfile fail: {}", e);
+                    }
+                    sleep(interval).await;
+                }
+            }));
+
+            info!("metrics write to textfile {:?}", opt.metrics_textfile);
+        }
+
+        Ok(exporter)
+    }
+
+    pub fn stop(self) -> Result<()> {
+        for task in &self.tasks {
+            task.abort();
+        }
+
+        if let Some(path) = &self.unix_path {
+            fs::remove_file(path).map_err(|e| anyhow!("fs::remove_file {:?} fail: {}", path, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn test_compact_status() -> compact::Status {
+        let now = Utc::now();
+        compact::Status {
+            count: compact::OutcomeCount {
+                psi_skipped: 3,
+                done: 1,
+                forced: 1,
+                ..Default::default()
+            },
+            force_counter: 0,
+            prev_free_movable_pages_after_compact: 0,
+            prev_memfree_kb: 0,
+            last_outcome: Some(compact::Outcome::Done),
+            last_run: Some(compact::LastRun {
+                forced: true,
+                outcome: compact::Outcome::Done,
+                start_time: now - chrono::Duration::seconds(2),
+                end_time: now,
+                free_movable_pages_before: 100,
+                free_movable_pages_after: 20,
+                exec_method: Some(compact::ExecMethod::Process),
+                uninterruptible: false,
+            }),
+            next_run_time: None,
+            pause: Default::default(),
+            nodes: Default::default(),
+            proactiveness: None,
+            proactiveness_original: None,
+        }
+    }
+
+    // MemCgroup is only built by MemCG, get it from a fake host that has a
+    // memcg with two numa nodes.
+    fn test_memcg_status() -> HashMap<String, memcg::MemCgroup> {
+        let root = tempfile::tempdir().unwrap();
+        let cgroup = root.path().join("sys/fs/cgroup");
+        fs::create_dir_all(cgroup.join("a")).unwrap();
+        fs::write(cgroup.join("cgroup.controllers"), "memory\n").unwrap();
+        for dir in [cgroup.clone(), cgroup.join("a")] {
+            fs::write(
+                dir.join("memory.pressure"),
+                "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n\
+                 full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n",
+            )
+            .unwrap();
+        }
+        fs::create_dir_all(root.path().join("sys/kernel/mm/lru_gen")).unwrap();
+        fs::write(
+            root.path().join("sys/kernel/mm/lru_gen/enabled"),
+            "0x0007\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.path().join("sys/kernel/debug")).unwrap();
+        fs::write(
+            root.path().join("sys/kernel/debug/lru_gen"),
+            "memcg     2 /a\n\
+             node     0\n\
+             \x20     0  1000  10  20\n\
+             \x20     1  1000  10  20\n\
+             node     1\n\
+             \x20     0  1000  10  20\n\
+             \x20     1  1000  10  20\n",
+        )
+        .unwrap();
+
+        let m = memcg::MemCG::new(
+            &mem_agent_lib::hostfs::HostFs::new(root.path()),
+            true,
+            memcg::Config::default(),
+        )
+        .unwrap();
+        let mut mgs = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(m.get_status());
+
+        let mcg = mgs.get_mut("/a").unwrap();
+        mcg.memory_high = Some(4096);
+        mcg.dry_run_memory_high = Some(8192);
+        let n0 = mcg.numa.get_mut(&0).unwrap();
+        n0.run_aging_count = 2;
+        n0.eviction_count.page = 100;
+        n0.eviction_count.target_reached = 1;
+        let n1 = mcg.numa.get_mut(&1).unwrap();
+        n1.run_aging_count = 3;
+        n1.eviction_count.psi_exceeds_limit = 1;
+        n1.dry_run.count = 1;
+        n1.dry_run.anon_pages = 10;
+        n1.dry_run.file_pages = 20;
+        n1.dry_run.last_swappiness = 60;
+
+        mgs
+    }
+
+    #[test]
+    fn test_encode_memcg() {
+        let out = encode(
+            Format::OpenMetrics,
+            &test_memcg_status(),
+            &test_compact_status(),
+        );
+
+        assert!(out.contains("# TYPE mem_agent_memcg_eviction_pages counter\n"));
+        assert!(out.contains("mem_agent_memcg_eviction_pages_total{path=\"/a\",numa=\"0\"} 100\n"));
+        assert!(out.contains("mem_agent_memcg_eviction_pages_total{path=\"/a\",numa=\"1\"} 0\n"));
+        assert!(out.contains(
+            "mem_agent_memcg_eviction_stop_total{path=\"/a\",numa=\"0\",reason=\"target_reached\"} 1\n"
+        ));
+        assert!(out.contains(
+            "mem_agent_memcg_eviction_stop_total{path=\"/a\",numa=\"1\",reason=\"psi_exceeds_limit\"} 1\n"
+        ));
+        assert!(out.contains(
+            "mem_agent_memcg_eviction_stop_total{path=\"/a\",numa=\"1\",reason=\"target_reached\"} 0\n"
+        ));
+        assert!(out.contains("mem_agent_memcg_run_aging_total{path=\"/a\",numa=\"0\"} 2\n"));
+        assert!(out.contains("mem_agent_memcg_run_aging_total{path=\"/a\",numa=\"1\"} 3\n"));
+        assert!(out.contains("mem_agent_memcg_dry_run_eviction_total{path=\"/a\",numa=\"1\"} 1\n"));
+        assert!(out.contains(
+            "mem_agent_memcg_dry_run_pages_total{path=\"/a\",numa=\"1\",type=\"anon\"} 10\n"
+        ));
+        assert!(out.contains(
+            "mem_agent_memcg_dry_run_pages_total{path=\"/a\",numa=\"1\",type=\"file\"} 20\n"
+        ));
+        assert!(out.contains("mem_agent_memcg_dry_run_swappiness{path=\"/a\",numa=\"1\"} 60\n"));
+        assert!(out.contains("mem_agent_memcg_memory_high_bytes{path=\"/a\"} 4096\n"));
+        assert!(out.contains("mem_agent_memcg_dry_run_memory_high_bytes{path=\"/a\"} 8192\n"));
+        // The samples are sorted by numa id.
+        assert!(
+            out.find("mem_agent_memcg_run_aging_total{path=\"/a\",numa=\"0\"}")
+                < out.find("mem_agent_memcg_run_aging_total{path=\"/a\",numa=\"1\"}")
+        );
+    }
+
+    #[test]
+    fn test_encode_openmetrics() {
+        let out = encode(Format::OpenMetrics, &HashMap::new(), &test_compact_status());
+
+        assert!(out.contains("# TYPE mem_agent_compact_outcome counter\n"));
+        assert!(out.contains("mem_agent_compact_outcome_total{outcome=\"psi_skipped\"} 3\n"));
+        assert!(out.contains("mem_agent_compact_forced_total 1\n"));
+        assert!(out.contains("mem_agent_compact_last_duration_milliseconds 2000\n"));
+        assert!(out.contains("mem_agent_compact_last_free_movable_pages{when=\"after\"} 20\n"));
+        assert!(out.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_encode_prometheus() {
+        let out = encode(Format::Prometheus, &HashMap::new(), &test_compact_status());
+
+        assert!(out.contains("# TYPE mem_agent_compact_outcome_total counter\n"));
+        assert!(out.contains("mem_agent_compact_outcome_total{outcome=\"done\"} 1\n"));
+        assert!(!out.contains("# EOF"));
+    }
+
+    #[test]
+    fn test_metrics_option() {
+        use structopt::StructOpt;
+
+        let opt = MetricsOption::from_iter_safe(["srv"]).unwrap();
+        assert_eq!(opt.metrics_textfile_interval_secs, 60);
+        let opt = MetricsOption::from_iter_safe(["srv", "--metrics-textfile-interval-secs", "30"])
+            .unwrap();
+        assert_eq!(opt.metrics_textfile_interval_secs, 30);
+        // 0 would rewrite the textfile without a pause.
+        assert!(
+            MetricsOption::from_iter_safe(["srv", "--metrics-textfile-interval-secs", "0"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value("/a\"b\\c\n"), "/a\\\"b\\\\c\\n");
+    }
+}
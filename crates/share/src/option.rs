@@ -2,8 +2,16 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::rpc::{
+    compact_mode_to_rpc_compact_mode, eviction_backend_to_rpc_eviction_backend,
+    pause_target_to_rpc_pause_target,
+};
 use anyhow::{anyhow, Result};
+use mem_agent_lib::compact::Mode as CompactMode;
+use mem_agent_lib::memcg::EvictionBackend;
+use mem_agent_lib::pause::PauseTarget;
 use protocols::mem_agent as rpc;
+use std::path::PathBuf;
 use std::str::FromStr;
 use structopt::StructOpt;
 
@@ -18,6 +26,19 @@ pub struct CgroupMemcgSetOption {
     memcg_period_psi_percent_limit: Option<u8>,
     memcg_eviction_psi_percent_limit: Option<u8>,
     memcg_eviction_run_aging_count_min: Option<u64>,
+    memcg_eviction_backend: Option<EvictionBackend>,
+    memcg_memory_high: Option<bool>,
+    memcg_memory_high_window_secs: Option<u64>,
+    memcg_memory_high_headroom_percent: Option<u8>,
+    memcg_memory_high_psi_percent_limit: Option<u8>,
+    memcg_reclaim_target_bytes: Option<u64>,
+    memcg_reclaim_target_percent: Option<u8>,
+    memcg_reclaim_rate_limit_bytes_per_sec: Option<u64>,
+    memcg_cold_age_secs: Option<u64>,
+    memcg_aging_force_scan: Option<bool>,
+    memcg_aging_idle_fresh_ignore_secs: Option<u64>,
+    memcg_eviction_nr_gens_min: Option<u64>,
+    memcg_dry_run: Option<bool>,
     no_subdir: Option<bool>,
 }
 
@@ -57,6 +78,37 @@ impl FromStr for CgroupMemcgSetOption {
                 "eviction-run-aging-count-min" => {
                     cg.memcg_eviction_run_aging_count_min = Some(value.parse::<u64>()?)
                 }
+                "eviction-backend" => {
+                    cg.memcg_eviction_backend = Some(value.parse::<EvictionBackend>()?)
+                }
+                "memory-high" => cg.memcg_memory_high = Some(value.parse::<bool>()?),
+                "memory-high-window-secs" => {
+                    cg.memcg_memory_high_window_secs = Some(value.parse::<u64>()?)
+                }
+                "memory-high-headroom-percent" => {
+                    cg.memcg_memory_high_headroom_percent = Some(value.parse::<u8>()?)
+                }
+                "memory-high-psi-percent-limit" => {
+                    cg.memcg_memory_high_psi_percent_limit = Some(value.parse::<u8>()?)
+                }
+                "reclaim-target-bytes" => {
+                    cg.memcg_reclaim_target_bytes = Some(value.parse::<u64>()?)
+                }
+                "reclaim-target-percent" => {
+                    cg.memcg_reclaim_target_percent = Some(value.parse::<u8>()?)
+                }
+                "reclaim-rate-limit-bytes-per-sec" => {
+                    cg.memcg_reclaim_rate_limit_bytes_per_sec = Some(value.parse::<u64>()?)
+                }
+                "cold-age-secs" => cg.memcg_cold_age_secs = Some(value.parse::<u64>()?),
+                "aging-force-scan" => cg.memcg_aging_force_scan = Some(value.parse::<bool>()?),
+                "aging-idle-fresh-ignore-secs" => {
+                    cg.memcg_aging_idle_fresh_ignore_secs = Some(value.parse::<u64>()?)
+                }
+                "eviction-nr-gens-min" => {
+                    cg.memcg_eviction_nr_gens_min = Some(value.parse::<u64>()?)
+                }
+                "dry-run" => cg.memcg_dry_run = Some(value.parse::<bool>()?),
                 "no-subdir" => cg.no_subdir = Some(value.parse::<bool>()?),
                 _ => return Err(anyhow!("{} is invalid", key)),
             }
@@ -84,6 +136,25 @@ impl CgroupMemcgSetOption {
                 period_psi_percent_limit: self.memcg_period_psi_percent_limit.map(|v| v as u32),
                 eviction_psi_percent_limit: self.memcg_eviction_psi_percent_limit.map(|v| v as u32),
                 eviction_run_aging_count_min: self.memcg_eviction_run_aging_count_min,
+                eviction_backend: self
+                    .memcg_eviction_backend
+                    .map(|b| eviction_backend_to_rpc_eviction_backend(b).into()),
+                memory_high: self.memcg_memory_high,
+                memory_high_window_secs: self.memcg_memory_high_window_secs,
+                memory_high_headroom_percent: self
+                    .memcg_memory_high_headroom_percent
+                    .map(|v| v as u32),
+                memory_high_psi_percent_limit: self
+                    .memcg_memory_high_psi_percent_limit
+                    .map(|v| v as u32),
+                reclaim_target_bytes: self.memcg_reclaim_target_bytes,
+                reclaim_target_percent: self.memcg_reclaim_target_percent.map(|v| v as u32),
+                reclaim_rate_limit_bytes_per_sec: self.memcg_reclaim_rate_limit_bytes_per_sec,
+                cold_age_secs: self.memcg_cold_age_secs,
+                aging_force_scan: self.memcg_aging_force_scan,
+                aging_idle_fresh_ignore_secs: self.memcg_aging_idle_fresh_ignore_secs,
+                eviction_nr_gens_min: self.memcg_eviction_nr_gens_min,
+                dry_run: self.memcg_dry_run,
                 ..Default::default()
             })
             .into(),
@@ -92,7 +163,7 @@ impl CgroupMemcgSetOption {
     }
 }
 
-#[derive(Debug, StructOpt)]
+#[derive(Debug, Default, StructOpt)]
 pub struct MemcgSetupOption {
     #[structopt(long)]
     memcg_disabled: Option<bool>,
@@ -109,6 +180,36 @@ pub struct MemcgSetupOption {
     #[structopt(long)]
     memcg_eviction_run_aging_count_min: Option<u64>,
     #[structopt(long)]
+    memcg_eviction_backend: Option<EvictionBackend>,
+    #[structopt(long)]
+    memcg_memory_high: Option<bool>,
+    #[structopt(long)]
+    memcg_memory_high_window_secs: Option<u64>,
+    #[structopt(long)]
+    memcg_memory_high_headroom_percent: Option<u8>,
+    #[structopt(long)]
+    memcg_memory_high_psi_percent_limit: Option<u8>,
+    #[structopt(long)]
+    memcg_reclaim_target_bytes: Option<u64>,
+    #[structopt(long)]
+    memcg_reclaim_target_percent: Option<u8>,
+    #[structopt(long)]
+    memcg_reclaim_rate_limit_bytes_per_sec: Option<u64>,
+    #[structopt(long)]
+    memcg_cold_age_secs: Option<u64>,
+    #[structopt(long)]
+    memcg_aging_force_scan: Option<bool>,
+    #[structopt(long)]
+    memcg_aging_idle_fresh_ignore_secs: Option<u64>,
+    #[structopt(long)]
+    memcg_eviction_nr_gens_min: Option<u64>,
+    #[structopt(long)]
+    memcg_dry_run: Option<bool>,
+    #[structopt(long)]
+    memcg_eviction_workers: Option<usize>,
+    #[structopt(long)]
+    memcg_eviction_workers_cpu_percent: Option<u32>,
+    #[structopt(long)]
     memcg_cgroups: Vec<CgroupMemcgSetOption>,
 }
 
@@ -131,6 +232,13 @@ impl MemcgSetupOption {
             ..Default::default()
         };
 
+        self.apply_to_mem_agent_memcg_config(&mut config);
+
+        config
+    }
+
+    // The options override the values already in config.
+    pub fn apply_to_mem_agent_memcg_config(&self, config: &mut mem_agent_lib::memcg::Config) {
         set_fields!(self, config.default, [
             memcg_disabled => disabled,
             memcg_swap => swap,
@@ -138,7 +246,25 @@ impl MemcgSetupOption {
             memcg_period_secs => period_secs,
             memcg_period_psi_percent_limit => period_psi_percent_limit,
             memcg_eviction_psi_percent_limit => eviction_psi_percent_limit,
-            memcg_eviction_run_aging_count_min => eviction_run_aging_count_min
+            memcg_eviction_run_aging_count_min => eviction_run_aging_count_min,
+            memcg_eviction_backend => eviction_backend,
+            memcg_memory_high => memory_high,
+            memcg_memory_high_window_secs => memory_high_window_secs,
+            memcg_memory_high_headroom_percent => memory_high_headroom_percent,
+            memcg_memory_high_psi_percent_limit => memory_high_psi_percent_limit,
+            memcg_reclaim_target_bytes => reclaim_target_bytes,
+            memcg_reclaim_target_percent => reclaim_target_percent,
+            memcg_reclaim_rate_limit_bytes_per_sec => reclaim_rate_limit_bytes_per_sec,
+            memcg_cold_age_secs => cold_age_secs,
+            memcg_aging_force_scan => aging_force_scan,
+            memcg_aging_idle_fresh_ignore_secs => aging_idle_fresh_ignore_secs,
+            memcg_eviction_nr_gens_min => eviction_nr_gens_min,
+            memcg_dry_run => dry_run
+        ]);
+
+        set_fields!(self, config, [
+            memcg_eviction_workers => eviction_workers,
+            memcg_eviction_workers_cpu_percent => eviction_workers_cpu_percent
         ]);
 
         for cg in self.memcg_cgroups.iter() {
@@ -147,6 +273,7 @@ impl MemcgSetupOption {
                 cc.no_subdir = *val;
             }
             cc.numa_id = cg.memcg_numa_id.clone();
+            cc.numa_id.sort_unstable();
             set_fields!(cg, cc.config, [
                 memcg_disabled => disabled,
                 memcg_swap => swap,
@@ -154,17 +281,29 @@ impl MemcgSetupOption {
                 memcg_period_secs => period_secs,
                 memcg_period_psi_percent_limit => period_psi_percent_limit,
                 memcg_eviction_psi_percent_limit => eviction_psi_percent_limit,
-                memcg_eviction_run_aging_count_min => eviction_run_aging_count_min
+                memcg_eviction_run_aging_count_min => eviction_run_aging_count_min,
+                memcg_eviction_backend => eviction_backend,
+                memcg_memory_high => memory_high,
+                memcg_memory_high_window_secs => memory_high_window_secs,
+                memcg_memory_high_headroom_percent => memory_high_headroom_percent,
+                memcg_memory_high_psi_percent_limit => memory_high_psi_percent_limit,
+                memcg_reclaim_target_bytes => reclaim_target_bytes,
+                memcg_reclaim_target_percent => reclaim_target_percent,
+                memcg_reclaim_rate_limit_bytes_per_sec => reclaim_rate_limit_bytes_per_sec,
+                memcg_cold_age_secs => cold_age_secs,
+                memcg_aging_force_scan => aging_force_scan,
+                memcg_aging_idle_fresh_ignore_secs => aging_idle_fresh_ignore_secs,
+                memcg_eviction_nr_gens_min => eviction_nr_gens_min,
+                memcg_dry_run => dry_run
             ]);
 
             let ccs = config
                 .cgroups
                 .entry(cg.memcg_path.clone())
                 .or_insert_with(|| Vec::new());
+            ccs.retain(|c| c.numa_id != cc.numa_id);
             ccs.push(cc);
         }
-
-        config
     }
 }
 
@@ -236,6 +375,36 @@ pub struct MemcgSetOption {
     #[structopt(long)]
     memcg_eviction_run_aging_count_min: Option<u64>,
     #[structopt(long)]
+    memcg_eviction_backend: Option<EvictionBackend>,
+    #[structopt(long)]
+    memcg_memory_high: Option<bool>,
+    #[structopt(long)]
+    memcg_memory_high_window_secs: Option<u64>,
+    #[structopt(long)]
+    memcg_memory_high_headroom_percent: Option<u8>,
+    #[structopt(long)]
+    memcg_memory_high_psi_percent_limit: Option<u8>,
+    #[structopt(long)]
+    memcg_reclaim_target_bytes: Option<u64>,
+    #[structopt(long)]
+    memcg_reclaim_target_percent: Option<u8>,
+    #[structopt(long)]
+    memcg_reclaim_rate_limit_bytes_per_sec: Option<u64>,
+    #[structopt(long)]
+    memcg_cold_age_secs: Option<u64>,
+    #[structopt(long)]
+    memcg_aging_force_scan: Option<bool>,
+    #[structopt(long)]
+    memcg_aging_idle_fresh_ignore_secs: Option<u64>,
+    #[structopt(long)]
+    memcg_eviction_nr_gens_min: Option<u64>,
+    #[structopt(long)]
+    memcg_dry_run: Option<bool>,
+    #[structopt(long)]
+    memcg_eviction_workers: Option<usize>,
+    #[structopt(long)]
+    memcg_eviction_workers_cpu_percent: Option<u32>,
+    #[structopt(long)]
     memcg_add: Vec<CgroupMemcgSetOption>,
     #[structopt(long)]
     memcg_set: Vec<CgroupMemcgSetOption>,
@@ -257,9 +426,28 @@ impl MemcgSetOption {
             period_psi_percent_limit: self.memcg_period_psi_percent_limit.map(|v| v as u32),
             eviction_psi_percent_limit: self.memcg_eviction_psi_percent_limit.map(|v| v as u32),
             eviction_run_aging_count_min: self.memcg_eviction_run_aging_count_min,
+            eviction_backend: self
+                .memcg_eviction_backend
+                .map(|b| eviction_backend_to_rpc_eviction_backend(b).into()),
+            memory_high: self.memcg_memory_high,
+            memory_high_window_secs: self.memcg_memory_high_window_secs,
+            memory_high_headroom_percent: self.memcg_memory_high_headroom_percent.map(|v| v as u32),
+            memory_high_psi_percent_limit: self
+                .memcg_memory_high_psi_percent_limit
+                .map(|v| v as u32),
+            reclaim_target_bytes: self.memcg_reclaim_target_bytes,
+            reclaim_target_percent: self.memcg_reclaim_target_percent.map(|v| v as u32),
+            reclaim_rate_limit_bytes_per_sec: self.memcg_reclaim_rate_limit_bytes_per_sec,
+            cold_age_secs: self.memcg_cold_age_secs,
+            aging_force_scan: self.memcg_aging_force_scan,
+            aging_idle_fresh_ignore_secs: self.memcg_aging_idle_fresh_ignore_secs,
+            eviction_nr_gens_min: self.memcg_eviction_nr_gens_min,
+            dry_run: self.memcg_dry_run,
             ..Default::default()
         })
         .into();
+        config.eviction_workers = self.memcg_eviction_workers.map(|v| v as u32);
+        config.eviction_workers_cpu_percent = self.memcg_eviction_workers_cpu_percent;
 
         for pn in &self.memcg_del {
             config.del.push(pn.to_rpc_path_numa());
@@ -277,7 +465,55 @@ impl MemcgSetOption {
     }
 }
 
-#[derive(Debug, StructOpt)]
+#[derive(Debug, Default, StructOpt)]
+pub struct MemcgReclaimOption {
+    #[structopt(long)]
+    path: String,
+    // The NUMA node ids separated by :, all the nodes if not set.
+    #[structopt(long, value_delimiter = ":")]
+    numa_id: Vec<u32>,
+    // 0 means no limit.
+    #[structopt(long, default_value = "0")]
+    target_bytes: u64,
+    #[structopt(long)]
+    swap: bool,
+    #[structopt(long, default_value = "1")]
+    psi_percent_limit: u8,
+}
+
+impl MemcgReclaimOption {
+    pub fn to_rpc_memcg_reclaim_request(&self) -> rpc::MemcgReclaimRequest {
+        rpc::MemcgReclaimRequest {
+            path: self.path.clone(),
+            numa: self.numa_id.clone(),
+            target_bytes: self.target_bytes,
+            swap: self.swap,
+            psi_percent_limit: self.psi_percent_limit as u32,
+            ..Default::default()
+        }
+    }
+}
+
+// A ":" separated list of numa node ids, an empty string means an empty
+// list.
+#[derive(Debug, Default, Clone)]
+pub struct NodeList(Vec<u32>);
+
+impl FromStr for NodeList {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(NodeList(
+            s.split(':')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.trim().parse::<u32>())
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|e| anyhow!("node list {} is invalid: {}", s, e))?,
+        ))
+    }
+}
+
+#[derive(Debug, Default, StructOpt)]
 pub struct CompactSetOption {
     #[structopt(long)]
     compact_disabled: Option<bool>,
@@ -295,6 +531,24 @@ pub struct CompactSetOption {
     compact_threshold: Option<u64>,
     #[structopt(long)]
     compact_force_times: Option<u64>,
+    #[structopt(long)]
+    compact_dry_run: Option<bool>,
+    #[structopt(long)]
+    compact_per_node: Option<bool>,
+    // The nodes that per node compaction works on, like "0:1".
+    #[structopt(long)]
+    compact_nodes_include: Option<NodeList>,
+    #[structopt(long)]
+    compact_nodes_exclude: Option<NodeList>,
+    // trigger or proactive
+    #[structopt(long)]
+    compact_mode: Option<CompactMode>,
+    #[structopt(long)]
+    compact_proactiveness_min: Option<u8>,
+    #[structopt(long)]
+    compact_proactiveness_max: Option<u8>,
+    #[structopt(long)]
+    compact_proactiveness_step: Option<u8>,
 }
 
 impl CompactSetOption {
@@ -309,6 +563,30 @@ impl CompactSetOption {
             compact_order: self.compact_order.map(|v| v as u32),
             compact_threshold: self.compact_threshold,
             compact_force_times: self.compact_force_times,
+            dry_run: self.compact_dry_run,
+            per_node: self.compact_per_node,
+            nodes_include: self
+                .compact_nodes_include
+                .as_ref()
+                .map(|l| rpc::CompactNodeList {
+                    node: l.0.clone(),
+                    ..Default::default()
+                })
+                .into(),
+            nodes_exclude: self
+                .compact_nodes_exclude
+                .as_ref()
+                .map(|l| rpc::CompactNodeList {
+                    node: l.0.clone(),
+                    ..Default::default()
+                })
+                .into(),
+            mode: self
+                .compact_mode
+                .map(|m| compact_mode_to_rpc_compact_mode(m).into()),
+            proactiveness_min: self.compact_proactiveness_min.map(|v| v as u32),
+            proactiveness_max: self.compact_proactiveness_max.map(|v| v as u32),
+            proactiveness_step: self.compact_proactiveness_step.map(|v| v as u32),
             ..Default::default()
         };
 
@@ -321,6 +599,13 @@ impl CompactSetOption {
             ..Default::default()
         };
 
+        self.apply_to_mem_agent_compact_config(&mut config);
+
+        config
+    }
+
+    // The options override the values already in config.
+    pub fn apply_to_mem_agent_compact_config(&self, config: &mut mem_agent_lib::compact::Config) {
         if let Some(v) = self.compact_disabled {
             config.disabled = v;
         }
@@ -345,7 +630,109 @@ impl CompactSetOption {
         if let Some(v) = self.compact_force_times {
             config.compact_force_times = v;
         }
+        if let Some(v) = self.compact_dry_run {
+            config.dry_run = v;
+        }
+        if let Some(v) = self.compact_per_node {
+            config.per_node = v;
+        }
+        if let Some(v) = &self.compact_nodes_include {
+            config.nodes_include = v.0.clone();
+        }
+        if let Some(v) = &self.compact_nodes_exclude {
+            config.nodes_exclude = v.0.clone();
+        }
+        if let Some(v) = self.compact_mode {
+            config.mode = v;
+        }
+        if let Some(v) = self.compact_proactiveness_min {
+            config.proactiveness_min = v;
+        }
+        if let Some(v) = self.compact_proactiveness_max {
+            config.proactiveness_max = v;
+        }
+        if let Some(v) = self.compact_proactiveness_step {
+            config.proactiveness_step = v;
+        }
+    }
+}
 
-        config
+#[derive(Debug, Default, StructOpt)]
+pub struct CompactNowOption {
+    #[structopt(long)]
+    compact_order: Option<u8>,
+    #[structopt(long)]
+    compact_sec_max: Option<i64>,
+    #[structopt(long)]
+    compact_psi_percent_limit: Option<u8>,
+}
+
+impl CompactNowOption {
+    pub fn to_rpc_compact_now_request(&self) -> rpc::CompactNowRequest {
+        rpc::CompactNowRequest {
+            compact_order: self.compact_order.map(|v| v as u32),
+            compact_sec_max: self.compact_sec_max,
+            compact_psi_percent_limit: self.compact_psi_percent_limit.map(|v| v as u32),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct PauseOption {
+    // all, memcg or compact
+    #[structopt(long, default_value = "all")]
+    target: PauseTarget,
+    // 0 means pause until resumed.
+    #[structopt(long, default_value = "0")]
+    duration_secs: u64,
+}
+
+impl PauseOption {
+    pub fn to_rpc_pause_request(&self) -> rpc::PauseRequest {
+        rpc::PauseRequest {
+            target: pause_target_to_rpc_pause_target(self.target).into(),
+            duration_secs: self.duration_secs,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ResumeOption {
+    // all, memcg or compact
+    #[structopt(long, default_value = "all")]
+    target: PauseTarget,
+}
+
+impl ResumeOption {
+    pub fn to_rpc_resume_request(&self) -> rpc::ResumeRequest {
+        rpc::ResumeRequest {
+            target: pause_target_to_rpc_pause_target(self.target).into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default, StructOpt)]
+pub struct MetricsOption {
+    // tcp://host:port or unix:///path
+    #[structopt(long)]
+    pub metrics_addr: Option<String>,
+    // The file for the node_exporter textfile collector.
+    #[structopt(long, parse(from_os_str))]
+    pub metrics_textfile: Option<PathBuf>,
+    #[structopt(long, default_value = "60", parse(try_from_str = parse_interval_secs))]
+    pub metrics_textfile_interval_secs: u64,
+}
+
+// 0 would rewrite the textfile in a busy loop.
+fn parse_interval_secs(s: &str) -> Result<u64> {
+    let secs = s
+        .parse::<u64>()
+        .map_err(|e| anyhow!("interval {} is invalid: {}", s, e))?;
+    if secs == 0 {
+        return Err(anyhow!("interval must be greater than 0"));
     }
+    Ok(secs)
 }
@@ -4,15 +4,17 @@
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use mem_agent_lib::{agent, compact, memcg};
+use chrono::{DateTime, Utc};
+use mem_agent_lib::{agent, compact, event, memcg, pause};
 use protocols::mem_agent as rpc_mem_agent;
 use protocols::{empty, mem_agent_ttrpc};
-use slog_scope::{error, info};
+use slog_scope::{error, info, warn};
 use std::collections::HashMap;
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::sync::Arc;
 use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast;
 use ttrpc::asynchronous::Server;
 use ttrpc::error::Error;
 use ttrpc::proto::Code;
@@ -29,7 +31,35 @@ impl MyControl {
     }
 }
 
+fn generation_to_rpc_generation(
+    g: &memcg::Generation,
+    now: DateTime<Utc>,
+) -> rpc_mem_agent::Generation {
+    rpc_mem_agent::Generation {
+        seq: g.seq,
+        anon_bytes: g.anon_bytes,
+        file_bytes: g.file_bytes,
+        age_secs: now.signed_duration_since(g.birth).num_seconds().max(0) as u64,
+        ..Default::default()
+    }
+}
+
+fn working_set_to_rpc_working_set<F: Fn(u64) -> u64>(
+    working_set_bytes: F,
+) -> Vec<rpc_mem_agent::WorkingSet> {
+    memcg::WORKING_SET_WINDOW_SECS
+        .iter()
+        .map(|w| rpc_mem_agent::WorkingSet {
+            window_secs: *w,
+            bytes: working_set_bytes(*w),
+            ..Default::default()
+        })
+        .collect()
+}
+
 fn mem_cgroup_to_rpc_mem_cgroup(path: &str, mcg: &memcg::MemCgroup) -> rpc_mem_agent::MemCgroup {
+    let now = Utc::now();
+
     rpc_mem_agent::MemCgroup {
         id: mcg.id as u32,
         ino: mcg.ino as u64,
@@ -55,21 +85,68 @@ fn mem_cgroup_to_rpc_mem_cgroup(path: &str, mcg: &memcg::MemCgroup) -> rpc_mem_a
                                 other_error: n.eviction_count.other_error,
                                 error: n.eviction_count.error,
                                 psi_exceeds_limit: n.eviction_count.psi_exceeds_limit,
+                                target_reached: n.eviction_count.target_reached,
+                                not_cold: n.eviction_count.not_cold,
+                                paused: n.eviction_count.paused,
+                                cancelled: n.eviction_count.cancelled,
                                 ..Default::default()
                             },
                         ),
                         sleep_psi_exceeds_limit: n.sleep_psi_exceeds_limit,
+                        dry_run: protobuf::MessageField::some(rpc_mem_agent::DryRunCount {
+                            count: n.dry_run.count,
+                            anon_pages: n.dry_run.anon_pages,
+                            file_pages: n.dry_run.file_pages,
+                            last_swappiness: n.dry_run.last_swappiness as u32,
+                            ..Default::default()
+                        }),
+                        generations: n
+                            .generations
+                            .iter()
+                            .map(|g| generation_to_rpc_generation(g, now))
+                            .collect(),
+                        working_set: working_set_to_rpc_working_set(|w| n.working_set_bytes(w)),
                         ..Default::default()
                     },
                 )
             })
             .collect(),
+        working_set: working_set_to_rpc_working_set(|w| mcg.working_set_bytes(w)),
+        memory_high: mcg.memory_high,
+        dry_run_memory_high: mcg.dry_run_memory_high,
         ..Default::default()
     }
 }
 
+fn pause_status_to_rpc_pause_status(s: pause::PauseStatus) -> rpc_mem_agent::PauseStatus {
+    rpc_mem_agent::PauseStatus {
+        paused: s.paused,
+        until: s.until.map(crate::misc::datatime_to_timestamp).into(),
+        ..Default::default()
+    }
+}
+
+fn rpc_pause_target_to_pause_target(t: rpc_mem_agent::PauseTarget) -> pause::PauseTarget {
+    match t {
+        rpc_mem_agent::PauseTarget::PAUSE_TARGET_ALL => pause::PauseTarget::All,
+        rpc_mem_agent::PauseTarget::PAUSE_TARGET_MEMCG => pause::PauseTarget::Memcg,
+        rpc_mem_agent::PauseTarget::PAUSE_TARGET_COMPACT => pause::PauseTarget::Compact,
+    }
+}
+
+pub(crate) fn pause_target_to_rpc_pause_target(
+    t: pause::PauseTarget,
+) -> rpc_mem_agent::PauseTarget {
+    match t {
+        pause::PauseTarget::All => rpc_mem_agent::PauseTarget::PAUSE_TARGET_ALL,
+        pause::PauseTarget::Memcg => rpc_mem_agent::PauseTarget::PAUSE_TARGET_MEMCG,
+        pause::PauseTarget::Compact => rpc_mem_agent::PauseTarget::PAUSE_TARGET_COMPACT,
+    }
+}
+
 fn mem_cgroups_to_rpc_memcg_status(
     mgs: HashMap<String, memcg::MemCgroup>,
+    pause: pause::PauseStatus,
 ) -> rpc_mem_agent::MemcgStatusReply {
     let mem_cgroups: Vec<rpc_mem_agent::MemCgroup> = mgs
         .iter()
@@ -78,6 +155,7 @@ fn mem_cgroups_to_rpc_memcg_status(
 
     rpc_mem_agent::MemcgStatusReply {
         mem_cgroups,
+        pause: protobuf::MessageField::some(pause_status_to_rpc_pause_status(pause)),
         ..Default::default()
     }
 }
@@ -93,6 +171,44 @@ fn rpc_memcg_single_config_to_single_option_config(
         period_psi_percent_limit: sc.period_psi_percent_limit.map(|val| val as u8),
         eviction_psi_percent_limit: sc.eviction_psi_percent_limit.map(|val| val as u8),
         eviction_run_aging_count_min: sc.eviction_run_aging_count_min,
+        eviction_backend: sc
+            .eviction_backend
+            .and_then(|b| b.enum_value().ok())
+            .map(rpc_eviction_backend_to_eviction_backend),
+        memory_high: sc.memory_high,
+        memory_high_window_secs: sc.memory_high_window_secs,
+        memory_high_headroom_percent: sc.memory_high_headroom_percent.map(|val| val as u8),
+        memory_high_psi_percent_limit: sc.memory_high_psi_percent_limit.map(|val| val as u8),
+        reclaim_target_bytes: sc.reclaim_target_bytes,
+        reclaim_target_percent: sc.reclaim_target_percent.map(|val| val as u8),
+        reclaim_rate_limit_bytes_per_sec: sc.reclaim_rate_limit_bytes_per_sec,
+        cold_age_secs: sc.cold_age_secs,
+        aging_force_scan: sc.aging_force_scan,
+        aging_idle_fresh_ignore_secs: sc.aging_idle_fresh_ignore_secs,
+        eviction_nr_gens_min: sc.eviction_nr_gens_min,
+        dry_run: sc.dry_run,
+    }
+}
+
+fn rpc_eviction_backend_to_eviction_backend(
+    b: rpc_mem_agent::EvictionBackend,
+) -> memcg::EvictionBackend {
+    match b {
+        rpc_mem_agent::EvictionBackend::EVICTION_BACKEND_LRU_GEN => memcg::EvictionBackend::LruGen,
+        rpc_mem_agent::EvictionBackend::EVICTION_BACKEND_MEMORY_RECLAIM => {
+            memcg::EvictionBackend::MemoryReclaim
+        }
+    }
+}
+
+pub(crate) fn eviction_backend_to_rpc_eviction_backend(
+    b: memcg::EvictionBackend,
+) -> rpc_mem_agent::EvictionBackend {
+    match b {
+        memcg::EvictionBackend::LruGen => rpc_mem_agent::EvictionBackend::EVICTION_BACKEND_LRU_GEN,
+        memcg::EvictionBackend::MemoryReclaim => {
+            rpc_mem_agent::EvictionBackend::EVICTION_BACKEND_MEMORY_RECLAIM
+        }
     }
 }
 
@@ -127,6 +243,8 @@ fn rpc_memcg_config_to_memcg_optionconfig(mc: &rpc_mem_agent::MemcgConfig) -> me
             .map(|item| rpc_memcg_config_item_to_cgroup_option_config(&item))
             .collect(),
         default: rpc_memcg_single_config_to_single_option_config(&mc.default),
+        eviction_workers: mc.eviction_workers.map(|v| v as usize),
+        eviction_workers_cpu_percent: mc.eviction_workers_cpu_percent,
     };
 
     moc
@@ -144,12 +262,396 @@ fn compactconfig_to_compact_optionconfig(
         compact_order: cc.compact_order.map(|val| val as u8),
         compact_threshold: cc.compact_threshold,
         compact_force_times: cc.compact_force_times,
+        dry_run: cc.dry_run,
+        per_node: cc.per_node,
+        nodes_include: cc.nodes_include.as_ref().map(|l| l.node.clone()),
+        nodes_exclude: cc.nodes_exclude.as_ref().map(|l| l.node.clone()),
+        mode: cc
+            .mode
+            .and_then(|m| m.enum_value().ok())
+            .map(rpc_compact_mode_to_compact_mode),
+        proactiveness_min: cc.proactiveness_min.map(|val| val as u8),
+        proactiveness_max: cc.proactiveness_max.map(|val| val as u8),
+        proactiveness_step: cc.proactiveness_step.map(|val| val as u8),
         ..Default::default()
     };
 
     coc
 }
 
+fn single_config_to_rpc_memcg_single_config(
+    sc: &memcg::SingleConfig,
+) -> rpc_mem_agent::MemcgSingleConfig {
+    rpc_mem_agent::MemcgSingleConfig {
+        disabled: Some(sc.disabled),
+        swap: Some(sc.swap),
+        swappiness_max: Some(sc.swappiness_max as u32),
+        period_secs: Some(sc.period_secs),
+        period_psi_percent_limit: Some(sc.period_psi_percent_limit as u32),
+        eviction_psi_percent_limit: Some(sc.eviction_psi_percent_limit as u32),
+        eviction_run_aging_count_min: Some(sc.eviction_run_aging_count_min),
+        eviction_backend: Some(
+            eviction_backend_to_rpc_eviction_backend(sc.eviction_backend).into(),
+        ),
+        memory_high: Some(sc.memory_high),
+        memory_high_window_secs: Some(sc.memory_high_window_secs),
+        memory_high_headroom_percent: Some(sc.memory_high_headroom_percent as u32),
+        memory_high_psi_percent_limit: Some(sc.memory_high_psi_percent_limit as u32),
+        reclaim_target_bytes: Some(sc.reclaim_target_bytes),
+        reclaim_target_percent: Some(sc.reclaim_target_percent as u32),
+        reclaim_rate_limit_bytes_per_sec: Some(sc.reclaim_rate_limit_bytes_per_sec),
+        cold_age_secs: Some(sc.cold_age_secs),
+        aging_force_scan: Some(sc.aging_force_scan),
+        aging_idle_fresh_ignore_secs: Some(sc.aging_idle_fresh_ignore_secs),
+        eviction_nr_gens_min: Some(sc.eviction_nr_gens_min),
+        dry_run: Some(sc.dry_run),
+        ..Default::default()
+    }
+}
+
+fn memcg_config_to_rpc_memcg_config_reply(mc: &memcg::Config) -> rpc_mem_agent::MemcgConfigReply {
+    let mut cgroups = Vec::new();
+    for (path, ccs) in &mc.cgroups {
+        for cc in ccs {
+            cgroups.push(rpc_mem_agent::MemcgConfigItem {
+                path: path.clone(),
+                numa: cc.numa_id.clone(),
+                no_subdir: Some(cc.no_subdir),
+                config: protobuf::MessageField::some(single_config_to_rpc_memcg_single_config(
+                    &cc.config,
+                )),
+                ..Default::default()
+            });
+        }
+    }
+    cgroups.sort_by(|a, b| a.path.cmp(&b.path));
+
+    rpc_mem_agent::MemcgConfigReply {
+        default: protobuf::MessageField::some(single_config_to_rpc_memcg_single_config(
+            &mc.default,
+        )),
+        cgroups,
+        eviction_workers: mc.eviction_workers as u32,
+        eviction_workers_cpu_percent: mc.eviction_workers_cpu_percent,
+        ..Default::default()
+    }
+}
+
+fn rpc_compact_mode_to_compact_mode(m: rpc_mem_agent::CompactMode) -> compact::Mode {
+    match m {
+        rpc_mem_agent::CompactMode::COMPACT_MODE_TRIGGER => compact::Mode::Trigger,
+        rpc_mem_agent::CompactMode::COMPACT_MODE_PROACTIVE => compact::Mode::Proactive,
+    }
+}
+
+pub(crate) fn compact_mode_to_rpc_compact_mode(m: compact::Mode) -> rpc_mem_agent::CompactMode {
+    match m {
+        compact::Mode::Trigger => rpc_mem_agent::CompactMode::COMPACT_MODE_TRIGGER,
+        compact::Mode::Proactive => rpc_mem_agent::CompactMode::COMPACT_MODE_PROACTIVE,
+    }
+}
+
+fn compact_config_to_rpc_compact_config(cc: &compact::Config) -> rpc_mem_agent::CompactConfig {
+    rpc_mem_agent::CompactConfig {
+        disabled: Some(cc.disabled),
+        period_secs: Some(cc.period_secs),
+        period_psi_percent_limit: Some(cc.period_psi_percent_limit as u32),
+        compact_psi_percent_limit: Some(cc.compact_psi_percent_limit as u32),
+        compact_sec_max: Some(cc.compact_sec_max),
+        compact_order: Some(cc.compact_order as u32),
+        compact_threshold: Some(cc.compact_threshold),
+        compact_force_times: Some(cc.compact_force_times),
+        dry_run: Some(cc.dry_run),
+        per_node: Some(cc.per_node),
+        nodes_include: protobuf::MessageField::some(rpc_mem_agent::CompactNodeList {
+            node: cc.nodes_include.clone(),
+            ..Default::default()
+        }),
+        nodes_exclude: protobuf::MessageField::some(rpc_mem_agent::CompactNodeList {
+            node: cc.nodes_exclude.clone(),
+            ..Default::default()
+        }),
+        mode: Some(compact_mode_to_rpc_compact_mode(cc.mode).into()),
+        proactiveness_min: Some(cc.proactiveness_min as u32),
+        proactiveness_max: Some(cc.proactiveness_max as u32),
+        proactiveness_step: Some(cc.proactiveness_step as u32),
+        ..Default::default()
+    }
+}
+
+fn compact_outcome_to_rpc_compact_outcome(
+    outcome: Option<compact::Outcome>,
+) -> rpc_mem_agent::CompactOutcome {
+    match outcome {
+        None => rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_NONE,
+        Some(compact::Outcome::PsiSkipped) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_PSI_SKIPPED
+        }
+        Some(compact::Outcome::ThresholdSkipped) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_THRESHOLD_SKIPPED
+        }
+        Some(compact::Outcome::Done) => rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_DONE,
+        Some(compact::Outcome::SecMaxKilled) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_SEC_MAX_KILLED
+        }
+        Some(compact::Outcome::PsiKilled) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_PSI_KILLED
+        }
+        Some(compact::Outcome::DryRun) => rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_DRY_RUN,
+        Some(compact::Outcome::PauseKilled) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_PAUSE_KILLED
+        }
+        Some(compact::Outcome::ProactiveRaised) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_PROACTIVE_RAISED
+        }
+        Some(compact::Outcome::ProactiveLowered) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_PROACTIVE_LOWERED
+        }
+        Some(compact::Outcome::ProactivePsiLowered) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_PROACTIVE_PSI_LOWERED
+        }
+        Some(compact::Outcome::ProactivePaused) => {
+            rpc_mem_agent::CompactOutcome::COMPACT_OUTCOME_PROACTIVE_PAUSED
+        }
+    }
+}
+
+fn compact_exec_method_to_rpc_compact_exec_method(
+    method: Option<compact::ExecMethod>,
+) -> rpc_mem_agent::CompactExecMethod {
+    match method {
+        None => rpc_mem_agent::CompactExecMethod::COMPACT_EXEC_METHOD_NONE,
+        Some(compact::ExecMethod::Process) => {
+            rpc_mem_agent::CompactExecMethod::COMPACT_EXEC_METHOD_PROCESS
+        }
+        Some(compact::ExecMethod::Thread) => {
+            rpc_mem_agent::CompactExecMethod::COMPACT_EXEC_METHOD_THREAD
+        }
+    }
+}
+
+fn compact_last_run_to_rpc_compact_last_run(r: &compact::LastRun) -> rpc_mem_agent::CompactLastRun {
+    rpc_mem_agent::CompactLastRun {
+        forced: r.forced,
+        outcome: compact_outcome_to_rpc_compact_outcome(Some(r.outcome)).into(),
+        start_time: protobuf::MessageField::some(crate::misc::datatime_to_timestamp(r.start_time)),
+        end_time: protobuf::MessageField::some(crate::misc::datatime_to_timestamp(r.end_time)),
+        duration_ms: (r.end_time - r.start_time).num_milliseconds().max(0) as u64,
+        free_movable_pages_before: r.free_movable_pages_before,
+        free_movable_pages_after: r.free_movable_pages_after,
+        exec_method: compact_exec_method_to_rpc_compact_exec_method(r.exec_method).into(),
+        uninterruptible: r.uninterruptible,
+        ..Default::default()
+    }
+}
+
+fn compact_count_to_rpc_compact_count(
+    count: &compact::OutcomeCount,
+) -> rpc_mem_agent::CompactCount {
+    rpc_mem_agent::CompactCount {
+        psi_skipped: count.psi_skipped,
+        threshold_skipped: count.threshold_skipped,
+        forced: count.forced,
+        done: count.done,
+        sec_max_killed: count.sec_max_killed,
+        psi_killed: count.psi_killed,
+        dry_run: count.dry_run,
+        pause_killed: count.pause_killed,
+        proactive_raised: count.proactive_raised,
+        proactive_lowered: count.proactive_lowered,
+        proactive_psi_lowered: count.proactive_psi_lowered,
+        proactive_paused: count.proactive_paused,
+        ..Default::default()
+    }
+}
+
+fn compact_node_status_to_rpc_compact_node_status(
+    node: u32,
+    status: &compact::NodeStatus,
+) -> rpc_mem_agent::CompactNodeStatus {
+    rpc_mem_agent::CompactNodeStatus {
+        node,
+        count: protobuf::MessageField::some(compact_count_to_rpc_compact_count(&status.count)),
+        prev_free_movable_pages_after_compact: status.prev_free_movable_pages_after_compact,
+        prev_memfree_kb: status.prev_memfree_kb,
+        last_outcome: compact_outcome_to_rpc_compact_outcome(status.last_outcome).into(),
+        last_run: status
+            .last_run
+            .as_ref()
+            .map(compact_last_run_to_rpc_compact_last_run)
+            .into(),
+        ..Default::default()
+    }
+}
+
+fn compact_status_to_rpc_compact_status(
+    status: &compact::Status,
+) -> rpc_mem_agent::CompactStatusReply {
+    rpc_mem_agent::CompactStatusReply {
+        count: protobuf::MessageField::some(compact_count_to_rpc_compact_count(&status.count)),
+        force_counter: status.force_counter,
+        prev_free_movable_pages_after_compact: status.prev_free_movable_pages_after_compact,
+        prev_memfree_kb: status.prev_memfree_kb,
+        last_outcome: compact_outcome_to_rpc_compact_outcome(status.last_outcome).into(),
+        last_run: status
+            .last_run
+            .as_ref()
+            .map(compact_last_run_to_rpc_compact_last_run)
+            .into(),
+        next_run_time: status
+            .next_run_time
+            .map(crate::misc::datatime_to_timestamp)
+            .into(),
+        pause: protobuf::MessageField::some(pause_status_to_rpc_pause_status(status.pause)),
+        nodes: status
+            .nodes
+            .iter()
+            .map(|(node, s)| compact_node_status_to_rpc_compact_node_status(*node, s))
+            .collect(),
+        proactiveness: status.proactiveness,
+        proactiveness_original: status.proactiveness_original,
+        ..Default::default()
+    }
+}
+
+fn eviction_stop_reason_to_rpc_eviction_stop_reason(
+    reason: memcg::EvictionStopReason,
+) -> rpc_mem_agent::EvictionStopReason {
+    match reason {
+        memcg::EvictionStopReason::None => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_OTHER_ERROR
+        }
+        memcg::EvictionStopReason::NoMinLru => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_NO_MIN_LRU_FILE
+        }
+        memcg::EvictionStopReason::MinLruInc => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_MIN_LRU_INC
+        }
+        memcg::EvictionStopReason::GetError => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_ERROR
+        }
+        memcg::EvictionStopReason::PsiExceedsLimit => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_PSI_EXCEEDS_LIMIT
+        }
+        memcg::EvictionStopReason::TargetReached => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_TARGET_REACHED
+        }
+        memcg::EvictionStopReason::NotCold => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_NOT_COLD
+        }
+        memcg::EvictionStopReason::Paused => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_PAUSED
+        }
+        memcg::EvictionStopReason::Cancelled => {
+            rpc_mem_agent::EvictionStopReason::EVICTION_STOP_REASON_CANCELLED
+        }
+    }
+}
+
+fn cgroup_event_to_rpc_cgroup_event(
+    path: &str,
+    id: usize,
+    ino: usize,
+) -> rpc_mem_agent::CgroupEvent {
+    rpc_mem_agent::CgroupEvent {
+        path: path.to_string(),
+        id: id as u32,
+        ino: ino as u64,
+        ..Default::default()
+    }
+}
+
+fn event_to_rpc_event(ev: &event::Event) -> rpc_mem_agent::Event {
+    let e = match &ev.kind {
+        event::EventKind::Aging { path, numa_id } => {
+            rpc_mem_agent::event::Event::Aging(rpc_mem_agent::AgingEvent {
+                path: path.clone(),
+                numa_id: *numa_id,
+                ..Default::default()
+            })
+        }
+        event::EventKind::EvictionStop {
+            path,
+            numa_id,
+            reason,
+            anon_pages,
+            file_pages,
+        } => rpc_mem_agent::event::Event::EvictionStop(rpc_mem_agent::EvictionStopEvent {
+            path: path.clone(),
+            numa_id: *numa_id,
+            reason: eviction_stop_reason_to_rpc_eviction_stop_reason(*reason).into(),
+            anon_pages: *anon_pages,
+            file_pages: *file_pages,
+            ..Default::default()
+        }),
+        event::EventKind::CompactStart { forced } => {
+            rpc_mem_agent::event::Event::CompactStart(rpc_mem_agent::CompactStartEvent {
+                forced: *forced,
+                ..Default::default()
+            })
+        }
+        event::EventKind::CompactStop { outcome, duration } => {
+            rpc_mem_agent::event::Event::CompactStop(rpc_mem_agent::CompactStopEvent {
+                outcome: compact_outcome_to_rpc_compact_outcome(Some(*outcome)).into(),
+                duration_ms: duration.as_millis() as u64,
+                ..Default::default()
+            })
+        }
+        event::EventKind::ConfigChange(target) => {
+            rpc_mem_agent::event::Event::ConfigChange(rpc_mem_agent::ConfigChangeEvent {
+                target: match target {
+                    event::ConfigTarget::Memcg => rpc_mem_agent::ConfigTarget::CONFIG_TARGET_MEMCG,
+                    event::ConfigTarget::Compact => {
+                        rpc_mem_agent::ConfigTarget::CONFIG_TARGET_COMPACT
+                    }
+                }
+                .into(),
+                ..Default::default()
+            })
+        }
+        event::EventKind::CgroupAdd { path, id, ino } => rpc_mem_agent::event::Event::CgroupAdd(
+            cgroup_event_to_rpc_cgroup_event(path, *id, *ino),
+        ),
+        event::EventKind::CgroupRemove { path, id, ino } => {
+            rpc_mem_agent::event::Event::CgroupRemove(cgroup_event_to_rpc_cgroup_event(
+                path, *id, *ino,
+            ))
+        }
+    };
+
+    rpc_mem_agent::Event {
+        time: protobuf::MessageField::some(crate::misc::datatime_to_timestamp(ev.time)),
+        event: Some(e),
+        ..Default::default()
+    }
+}
+
+fn numa_work_to_rpc_numa_work(w: &memcg::NumaWork) -> rpc_mem_agent::NumaWork {
+    rpc_mem_agent::NumaWork {
+        path: w.path.clone(),
+        numa_id: w.numa_id,
+        anon_pages: w.anon_pages,
+        file_pages: w.file_pages,
+        stop_reason: w
+            .stop_reason
+            .map(|r| eviction_stop_reason_to_rpc_eviction_stop_reason(r).into()),
+        dry_run: w.dry_run,
+        ..Default::default()
+    }
+}
+
+fn work_report_to_rpc_work_report(r: &agent::WorkReport) -> rpc_mem_agent::WorkReport {
+    rpc_mem_agent::WorkReport {
+        start_time: protobuf::MessageField::some(crate::misc::datatime_to_timestamp(r.start_time)),
+        end_time: protobuf::MessageField::some(crate::misc::datatime_to_timestamp(r.end_time)),
+        memcg_period_secs: r.memcg_period_secs.clone(),
+        memcg: r.memcg.iter().map(numa_work_to_rpc_numa_work).collect(),
+        compact_outcome: compact_outcome_to_rpc_compact_outcome(r.compact).into(),
+        error: r.error.clone().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
 #[async_trait]
 impl mem_agent_ttrpc::Control for MyControl {
     async fn memcg_status(
@@ -163,6 +665,7 @@ impl mem_agent_ttrpc::Control for MyControl {
                 error!("{}", estr);
                 Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
             })?,
+            self.agent.memcg_pause_status(),
         ))
     }
 
@@ -212,11 +715,216 @@ impl mem_agent_ttrpc::Control for MyControl {
 
         Ok(empty::Empty::new())
     }
+
+    async fn memcg_config_get(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        _: empty::Empty,
+    ) -> ::ttrpc::Result<rpc_mem_agent::MemcgConfigReply> {
+        Ok(memcg_config_to_rpc_memcg_config_reply(
+            &self.agent.memcg_get_config_async().await.map_err(|e| {
+                let estr = format!("agent.memcg_get_config_async fail: {}", e);
+                error!("{}", estr);
+                Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+            })?,
+        ))
+    }
+
+    async fn compact_config_get(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        _: empty::Empty,
+    ) -> ::ttrpc::Result<rpc_mem_agent::CompactConfig> {
+        Ok(compact_config_to_rpc_compact_config(
+            &self.agent.compact_get_config_async().await.map_err(|e| {
+                let estr = format!("agent.compact_get_config_async fail: {}", e);
+                error!("{}", estr);
+                Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+            })?,
+        ))
+    }
+
+    async fn compact_status(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        _: empty::Empty,
+    ) -> ::ttrpc::Result<rpc_mem_agent::CompactStatusReply> {
+        Ok(compact_status_to_rpc_compact_status(
+            &self.agent.compact_status_async().await.map_err(|e| {
+                let estr = format!("agent.compact_status_async fail: {}", e);
+                error!("{}", estr);
+                Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+            })?,
+        ))
+    }
+
+    async fn work_history(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        _: empty::Empty,
+    ) -> ::ttrpc::Result<rpc_mem_agent::WorkHistoryReply> {
+        let history = self.agent.work_history_async().await.map_err(|e| {
+            let estr = format!("agent.work_history_async fail: {}", e);
+            error!("{}", estr);
+            Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+        })?;
+
+        Ok(rpc_mem_agent::WorkHistoryReply {
+            reports: history.iter().map(work_report_to_rpc_work_report).collect(),
+            ..Default::default()
+        })
+    }
+
+    async fn memcg_reclaim(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        req: rpc_mem_agent::MemcgReclaimRequest,
+    ) -> ::ttrpc::Result<rpc_mem_agent::MemcgReclaimReply> {
+        if req.psi_percent_limit > 100 {
+            return Err(Error::RpcStatus(ttrpc::get_status(
+                Code::INVALID_ARGUMENT,
+                format!("psi_percent_limit {} is invalid", req.psi_percent_limit),
+            )));
+        }
+
+        let works = self
+            .agent
+            .memcg_reclaim_async(memcg::ReclaimRequest {
+                path: req.path,
+                numa_id: req.numa,
+                target_bytes: req.target_bytes,
+                swap: req.swap,
+                psi_percent_limit: req.psi_percent_limit as u8,
+            })
+            .await
+            .map_err(|e| {
+                let estr = format!("agent.memcg_reclaim_async fail: {}", e);
+                error!("{}", estr);
+                Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+            })?;
+
+        Ok(rpc_mem_agent::MemcgReclaimReply {
+            numa: works.iter().map(numa_work_to_rpc_numa_work).collect(),
+            ..Default::default()
+        })
+    }
+
+    async fn compact_now(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        req: rpc_mem_agent::CompactNowRequest,
+    ) -> ::ttrpc::Result<rpc_mem_agent::CompactLastRun> {
+        if let Some(limit) = req.compact_psi_percent_limit {
+            if limit > 100 {
+                return Err(Error::RpcStatus(ttrpc::get_status(
+                    Code::INVALID_ARGUMENT,
+                    format!("compact_psi_percent_limit {} is invalid", limit),
+                )));
+            }
+        }
+
+        let run = self
+            .agent
+            .compact_now_async(compact::CompactRequest {
+                compact_order: req.compact_order.map(|v| v as u8),
+                compact_sec_max: req.compact_sec_max,
+                compact_psi_percent_limit: req.compact_psi_percent_limit.map(|v| v as u8),
+            })
+            .await
+            .map_err(|e| {
+                let estr = format!("agent.compact_now_async fail: {}", e);
+                error!("{}", estr);
+                Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+            })?;
+
+        Ok(compact_last_run_to_rpc_compact_last_run(&run))
+    }
+
+    async fn pause(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        req: rpc_mem_agent::PauseRequest,
+    ) -> ::ttrpc::Result<empty::Empty> {
+        let target = rpc_pause_target_to_pause_target(req.target.enum_value().map_err(|v| {
+            Error::RpcStatus(ttrpc::get_status(
+                Code::INVALID_ARGUMENT,
+                format!("pause target {} is invalid", v),
+            ))
+        })?);
+        let duration = if req.duration_secs == 0 {
+            None
+        } else {
+            Some(std::time::Duration::from_secs(req.duration_secs))
+        };
+
+        self.agent
+            .pause_async(target, duration)
+            .await
+            .map_err(|e| {
+                let estr = format!("agent.pause_async fail: {}", e);
+                error!("{}", estr);
+                Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+            })?;
+
+        Ok(empty::Empty::new())
+    }
+
+    async fn resume(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        req: rpc_mem_agent::ResumeRequest,
+    ) -> ::ttrpc::Result<empty::Empty> {
+        let target = rpc_pause_target_to_pause_target(req.target.enum_value().map_err(|v| {
+            Error::RpcStatus(ttrpc::get_status(
+                Code::INVALID_ARGUMENT,
+                format!("pause target {} is invalid", v),
+            ))
+        })?);
+
+        self.agent.resume_async(target).await.map_err(|e| {
+            let estr = format!("agent.resume_async fail: {}", e);
+            error!("{}", estr);
+            Error::RpcStatus(ttrpc::get_status(Code::INTERNAL, estr))
+        })?;
+
+        Ok(empty::Empty::new())
+    }
+
+    async fn watch_events(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        _: empty::Empty,
+        sink: ::ttrpc::r#async::ServerStreamSender<rpc_mem_agent::Event>,
+    ) -> ::ttrpc::Result<()> {
+        let mut rx = self.agent.subscribe_events();
+
+        loop {
+            match rx.recv().await {
+                Ok(ev) => {
+                    if let Err(e) = sink.send(&event_to_rpc_event(&ev)).await {
+                        // The client is gone.
+                        info!("watch_events stop: {}", e);
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("watch_events lost {} events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[allow(dead_code)]
-#[tokio::main]
-pub async fn rpc_loop(agent: agent::MemAgent, addr: String) -> Result<()> {
+pub async fn rpc_loop(
+    agent: agent::MemAgent,
+    addr: String,
+    mut config_file: Option<crate::config::ConfigFile>,
+    metrics_opt: crate::option::MetricsOption,
+) -> Result<()> {
     let path = addr
         .strip_prefix("unix://")
         .ok_or(anyhow!("format of addr {} is not right", addr))?;
@@ -224,7 +932,19 @@ pub async fn rpc_loop(agent: agent::MemAgent, addr: String) -> Result<()> {
         return Err(anyhow!("addr {} is exist", addr));
     }
 
-    let control = MyControl::new(agent);
+    // Start the exporter before the server, nothing needs to be cleaned
+    // up but the agent if it fails.
+    let exporter = match crate::metrics::Exporter::start(&agent, &metrics_opt).await {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            if let Err(err) = agent.shutdown_async().await {
+                error!("agent.shutdown_async fail: {}", err);
+            }
+            return Err(anyhow!("metrics::Exporter::start fail: {}", e));
+        }
+    };
+
+    let control = MyControl::new(agent.clone());
     let service = mem_agent_ttrpc::create_control(Arc::new(control));
 
     let mut server = Server::new()
@@ -244,24 +964,46 @@ pub async fn rpc_loop(agent: agent::MemAgent, addr: String) -> Result<()> {
         .map_err(|e| anyhow!("signal(SignalKind::quit()) fail: {}", e))?;
     let mut terminate = signal(SignalKind::terminate())
         .map_err(|e| anyhow!("signal(SignalKind::terminate()) fail: {}", e))?;
+    let mut hangup = signal(SignalKind::hangup())
+        .map_err(|e| anyhow!("signal(SignalKind::hangup()) fail: {}", e))?;
     server
         .start()
         .await
         .map_err(|e| anyhow!("server.start() fail: {}", e))?;
 
-    tokio::select! {
-        _ = interrupt.recv() => {
-            info!("mem-agent: interrupt shutdown");
-        }
+    loop {
+        tokio::select! {
+            _ = interrupt.recv() => {
+                info!("mem-agent: interrupt shutdown");
+                break;
+            }
 
-        _ = quit.recv() => {
-            info!("mem-agent: quit shutdown");
-        }
+            _ = quit.recv() => {
+                info!("mem-agent: quit shutdown");
+                break;
+            }
 
-        _ = terminate.recv() => {
-            info!("mem-agent: terminate shutdown");
-        }
-    };
+            _ = terminate.recv() => {
+                info!("mem-agent: terminate shutdown");
+                break;
+            }
+
+            _ = hangup.recv() => {
+                if let Some(cf) = config_file.as_mut() {
+                    info!("mem-agent: hangup reload config file");
+                    if let Err(e) = cf.reload(&agent).await {
+                        error!("reload config file fail: {}", e);
+                    }
+                } else {
+                    info!("mem-agent: hangup ignored because no config file");
+                }
+            }
+        };
+    }
+
+    exporter
+        .stop()
+        .map_err(|e| anyhow!("exporter.stop() fail: {}", e))?;
 
     server
         .shutdown()
@@ -269,5 +1011,17 @@ pub async fn rpc_loop(agent: agent::MemAgent, addr: String) -> Result<()> {
         .map_err(|e| anyhow!("server.shutdown() fail: {}", e))?;
     fs::remove_file(&path).map_err(|e| anyhow!("fs::remove_file {} fail: {}", path, e))?;
 
+    // Stop the agent after the server to not get new requests.
+    let status = agent
+        .shutdown_async()
+        .await
+        .map_err(|e| anyhow!("agent.shutdown_async fail: {}", e))?;
+    info!(
+        "mem-agent: shutdown with {} memory cgroups, compact {:?}, {} work reports",
+        status.memcg.len(),
+        status.compact.count,
+        status.history.len()
+    );
+
     Ok(())
 }